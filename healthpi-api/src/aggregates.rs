@@ -0,0 +1,117 @@
+use actix_web::{get, web, HttpResponse, Responder, ResponseError};
+use chrono::{DateTime, FixedOffset};
+use chrono_tz::Tz;
+use healthpi_model::{
+    aggregate::{Aggregate, Bucket},
+    device::DeviceId,
+    filter::RecordFilter,
+    measurement::{SourceKind, ValueType},
+    person::PersonId,
+    units::UnitSystem,
+};
+use log::error;
+use serde::Deserialize;
+
+use crate::{
+    db::measurement::{MeasurementRepository, MeasurementRepositoryImpl},
+    error::ApiError,
+};
+
+/// Most periods a single request can be split into, e.g. about 27 years by day.
+const MAX_PERIODS: usize = 10_000;
+
+#[derive(Debug, Deserialize)]
+struct AggregatesQuery {
+    bucket: Bucket,
+    #[serde(default)]
+    #[serde(deserialize_with = "crate::comma_separated")]
+    select: Vec<ValueType>,
+    /// Earliest time of measurement, inclusive.
+    from: DateTime<FixedOffset>,
+    /// Latest time of measurement, exclusive.
+    to: DateTime<FixedOffset>,
+    /// Name of the timezone whose midnight periods start at.
+    #[serde(default = "default_timezone")]
+    tz: String,
+    #[serde(default)]
+    #[serde(deserialize_with = "crate::comma_separated")]
+    source: Vec<SourceKind>,
+    #[serde(default)]
+    #[serde(deserialize_with = "crate::comma_separated")]
+    device: Vec<DeviceId>,
+    person: Option<PersonId>,
+    #[serde(default)]
+    units: UnitSystem,
+}
+
+fn default_timezone() -> String {
+    "UTC".into()
+}
+
+impl AggregatesQuery {
+    fn filter(&self) -> RecordFilter {
+        RecordFilter {
+            select: self.select.clone(),
+            from: Some(self.from),
+            to: Some(self.to),
+            sources: self.source.clone(),
+            devices: self.device.clone(),
+            person: self.person,
+            ..Default::default()
+        }
+    }
+}
+
+/// Summarizes values of each type over days, weeks or months in given timezone.
+#[get("/aggregates")]
+async fn aggregates(
+    measurement_repository: web::Data<MeasurementRepositoryImpl>,
+    query: web::Query<AggregatesQuery>,
+) -> impl Responder {
+    let Ok(timezone) = query.tz.parse::<Tz>() else {
+        return ApiError::InvalidRequest(format!("Invalid timezone: {}", query.tz))
+            .error_response();
+    };
+    let periods = match query.bucket.periods(
+        &query.from.with_timezone(&timezone),
+        &query.to.with_timezone(&timezone),
+        MAX_PERIODS,
+    ) {
+        Ok(periods) => periods,
+        Err(e) => return ApiError::InvalidRequest(e.to_string()).error_response(),
+    };
+
+    let timestamps: Vec<_> = periods
+        .iter()
+        .map(|(start, end)| (start.timestamp(), end.timestamp()))
+        .collect();
+    match measurement_repository
+        .fetch_aggregates(&query.filter(), &timestamps)
+        .await
+    {
+        Ok(rows) => {
+            let convert = |row_type, x| query.units.amount_canonical_to(row_type, x);
+            HttpResponse::Ok().json(
+                rows.into_iter()
+                    .map(|row| Aggregate {
+                        start: periods[row.period].0.fixed_offset(),
+                        value_type: row.value_type,
+                        count: row.count,
+                        min: convert(row.value_type, row.min),
+                        max: convert(row.value_type, row.max),
+                        mean: convert(row.value_type, row.mean),
+                        median: convert(row.value_type, row.median),
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        }
+        Err(e) => {
+            error!("Failed to fetch aggregates: {e}");
+            ApiError::Internal.error_response()
+        }
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(aggregates);
+}
@@ -0,0 +1,584 @@
+use std::{collections::HashSet, error::Error, time::Duration};
+
+use actix_web::{delete, get, post, rt, web, HttpResponse, Responder, ResponseError};
+use chrono::{DateTime, Local, TimeDelta, Utc};
+use healthpi_model::{
+    alert::{Alert, AlertCondition, AlertRule, AlertRuleId, DeliveryStatus},
+    filter::{Order, RecordFilter},
+    measurement::{Record, RecordId, ValueType},
+    person::PersonId,
+};
+use itertools::Itertools;
+use log::{error, info, warn};
+use reqwest::{Client, Url};
+use serde::Serialize;
+
+use crate::{
+    db::{
+        alert::{AlertRepository, AlertRepositoryImpl, NewAlert},
+        measurement::{MeasurementRepository, MeasurementRepositoryImpl},
+        person::{PersonRepository, PersonRepositoryImpl},
+    },
+    error::ApiError,
+};
+
+/// How many times delivery of an alert is attempted before giving up.
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Delay before the first retry. It doubles with every further one.
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// How often rules on missed measurements are checked.
+const MISSED_MEASUREMENT_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+fn value_of(record: &Record, value_type: ValueType) -> Option<f64> {
+    record
+        .values
+        .iter()
+        .find(|v| v.value_type() == value_type)
+        .map(|v| v.as_f64())
+}
+
+/// Posts an alert to a webhook, retrying with increasing delays until it is
+/// accepted or attempts run out. Returns the resulting status, along with
+/// the total number of attempts made, including earlier ones.
+async fn post_alert(
+    client: &Client,
+    webhook_url: &str,
+    alert: &Alert,
+    retry_delay: Duration,
+) -> (DeliveryStatus, u32) {
+    let mut attempts = alert.attempts;
+    let mut delay = retry_delay;
+    while attempts < MAX_ATTEMPTS {
+        if attempts > alert.attempts {
+            rt::time::sleep(delay).await;
+            delay *= 2;
+        }
+        attempts += 1;
+        match client
+            .post(webhook_url)
+            .json(alert)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+        {
+            Ok(_) => return (DeliveryStatus::Delivered, attempts),
+            Err(e) => warn!(
+                "Attempt {attempts} to deliver alert {} failed: {e}",
+                alert.id
+            ),
+        }
+    }
+    (DeliveryStatus::Failed, attempts)
+}
+
+/// Evaluates alert rules against measurements, and delivers alerts they raise.
+#[derive(Clone)]
+pub struct Alerter {
+    alert_repository: AlertRepositoryImpl,
+    measurement_repository: MeasurementRepositoryImpl,
+    client: Client,
+}
+
+impl Alerter {
+    pub fn new(
+        alert_repository: AlertRepositoryImpl,
+        measurement_repository: MeasurementRepositoryImpl,
+    ) -> Self {
+        Self {
+            alert_repository,
+            measurement_repository,
+            client: Client::new(),
+        }
+    }
+
+    /// Checks records stored or changed by a request against rules of the
+    /// persons they belong to. Every write of records ends here, so that rules
+    /// see all of them. Records are kept regardless of whether alerts could be
+    /// raised, so failures are only logged.
+    pub async fn records_changed(&self, ids: &[RecordId]) {
+        if let Err(e) = self.evaluate(ids).await {
+            error!("Failed to evaluate alert rules: {e}");
+        }
+    }
+
+    /// Checks records with given identifiers against rules of the persons they
+    /// belong to. Rules depending on previous values take them from stored
+    /// records, so records must be evaluated after they are stored.
+    async fn evaluate(&self, ids: &[RecordId]) -> Result<(), Box<dyn Error>> {
+        let records = self.measurement_repository.fetch_records_by_id(ids).await?;
+        let ids: HashSet<_> = ids.iter().copied().collect();
+        for person in records.iter().flat_map(|r| r.person).unique() {
+            for (rule_id, _, rule) in self.alert_repository.fetch_rules(Some(person)).await? {
+                if let AlertCondition::MissedMeasurement { .. } = rule.condition {
+                    continue;
+                }
+                let value_type = rule.condition.value_type();
+                let changed: Vec<_> = records
+                    .iter()
+                    .filter(|r| r.person == Some(person) && value_of(r, value_type).is_some())
+                    .collect();
+                let (Some(first), Some(last)) = (
+                    changed.iter().map(|r| r.timestamp).min(),
+                    changed.iter().map(|r| r.timestamp).max(),
+                ) else {
+                    continue;
+                };
+
+                // Changed records are fetched again along with the ones
+                // preceding them with previous values.
+                let lookback = rule.condition.lookback();
+                let filter = RecordFilter::default()
+                    .select(&[value_type])
+                    .person(person)
+                    .from(first - lookback.unwrap_or_default())
+                    .to(last + TimeDelta::seconds(1))
+                    .order(Order::Ascending);
+                let stored = self
+                    .measurement_repository
+                    .fetch_records(&filter, false)
+                    .await?;
+                for (i, record) in stored.iter().enumerate() {
+                    let Some(id) = record.id.filter(|id| ids.contains(id)) else {
+                        continue;
+                    };
+                    let Some(value) = value_of(record, value_type) else {
+                        continue;
+                    };
+                    let previous = lookback.and_then(|lookback| {
+                        stored[..i]
+                            .iter()
+                            .rev()
+                            .take_while(|r| r.timestamp >= record.timestamp - lookback)
+                            .find_map(|r| value_of(r, value_type))
+                    });
+                    let Some(message) = rule.condition.check(value, previous) else {
+                        continue;
+                    };
+
+                    self.raise(
+                        NewAlert {
+                            rule: rule_id,
+                            person,
+                            trigger_key: format!("record:{id}"),
+                            triggered_at: Utc::now(),
+                            measured_at: Some(record.timestamp),
+                            record: Some(id),
+                            message,
+                        },
+                        &rule.webhook_url,
+                    )
+                    .await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Raises alerts for measurements not taken by the deadline on the day
+    /// of given time. Deadlines are in local time of the server.
+    pub async fn check_missed_measurements(
+        &self,
+        now: DateTime<Local>,
+    ) -> Result<(), Box<dyn Error>> {
+        let today = now.date_naive();
+        let Some(start_of_day) = today
+            .and_time(Default::default())
+            .and_local_timezone(Local)
+            .earliest()
+        else {
+            return Ok(());
+        };
+
+        for (rule_id, person, rule) in self.alert_repository.fetch_rules(None).await? {
+            let AlertCondition::MissedMeasurement {
+                value_type,
+                deadline,
+            } = rule.condition
+            else {
+                continue;
+            };
+            if now.time() < deadline {
+                continue;
+            }
+
+            let filter = RecordFilter::default()
+                .select(&[value_type])
+                .person(person)
+                .from(start_of_day.fixed_offset());
+            if !self
+                .measurement_repository
+                .fetch_records(&filter, false)
+                .await?
+                .is_empty()
+            {
+                continue;
+            }
+
+            self.raise(
+                NewAlert {
+                    rule: rule_id,
+                    person,
+                    trigger_key: format!("missed:{today}"),
+                    triggered_at: now.to_utc(),
+                    measured_at: None,
+                    record: None,
+                    message: format!("No {value_type:?} measured by {deadline} on {today}"),
+                },
+                &rule.webhook_url,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn raise(&self, alert: NewAlert, webhook_url: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(alert) = self.alert_repository.create_alert(alert).await? {
+            info!(
+                "Rule {} raised alert {} for person {}: {}",
+                alert.rule, alert.id, alert.person, alert.message
+            );
+            self.spawn_delivery(alert, webhook_url.to_owned());
+        }
+        Ok(())
+    }
+
+    fn spawn_delivery(&self, alert: Alert, webhook_url: String) {
+        let alerter = self.clone();
+        rt::spawn(async move {
+            let (status, attempts) =
+                post_alert(&alerter.client, &webhook_url, &alert, RETRY_DELAY).await;
+            match status {
+                DeliveryStatus::Delivered => info!("Delivered alert {}", alert.id),
+                _ => error!("Failed to deliver alert {} to {webhook_url}", alert.id),
+            }
+            if let Err(e) = alerter
+                .alert_repository
+                .update_delivery(alert.id, status, attempts)
+                .await
+            {
+                error!("Failed to update delivery of alert {}: {e}", alert.id);
+            }
+        });
+    }
+
+    /// Resumes delivery of alerts left pending, e.g. by a restart.
+    pub async fn resume_deliveries(&self) -> Result<usize, Box<dyn Error>> {
+        let pending = self.alert_repository.fetch_pending_alerts().await?;
+        let count = pending.len();
+        for (alert, webhook_url) in pending {
+            self.spawn_delivery(alert, webhook_url);
+        }
+        Ok(count)
+    }
+
+    /// Periodically checks rules on missed measurements in the background.
+    pub fn spawn_missed_measurement_checks(&self) {
+        let alerter = self.clone();
+        rt::spawn(async move {
+            let mut interval = rt::time::interval(MISSED_MEASUREMENT_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = alerter.check_missed_measurements(Local::now()).await {
+                    error!("Failed to check missed measurements: {e}");
+                }
+            }
+        });
+    }
+}
+
+fn check_rule(rule: &AlertRule) -> Result<(), String> {
+    match Url::parse(&rule.webhook_url) {
+        Ok(url) if ["http", "https"].contains(&url.scheme()) => {}
+        _ => return Err("Webhook URL must be an HTTP(S) URL".into()),
+    }
+    match rule.condition {
+        AlertCondition::Below { threshold, .. } | AlertCondition::Above { threshold, .. }
+            if !threshold.is_finite() =>
+        {
+            Err("Threshold must be a finite number".into())
+        }
+        AlertCondition::RateOfChange { max_change, .. }
+            if !(max_change.is_finite() && max_change > 0.0) =>
+        {
+            Err("Maximum change must be positive".into())
+        }
+        AlertCondition::RateOfChange {
+            within_hours: 0, ..
+        } => Err("Number of hours must be positive".into()),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct AlertRuleResponse {
+    id: AlertRuleId,
+    #[serde(flatten)]
+    rule: AlertRule,
+}
+
+async fn person_exists(
+    person_repository: &PersonRepositoryImpl,
+    id: PersonId,
+) -> Result<(), HttpResponse> {
+    match person_repository.fetch_person(id).await {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err(ApiError::NotFound(format!("Person {id} does not exist")).error_response()),
+        Err(e) => {
+            error!("Failed to fetch person {id}: {e}");
+            Err(ApiError::Internal.error_response())
+        }
+    }
+}
+
+#[get("/persons/{id}/alert-rules")]
+async fn list_rules(
+    person_repository: web::Data<PersonRepositoryImpl>,
+    alert_repository: web::Data<AlertRepositoryImpl>,
+    id: web::Path<i64>,
+) -> impl Responder {
+    let id = PersonId::new(*id);
+    if let Err(response) = person_exists(&person_repository, id).await {
+        return response;
+    }
+    match alert_repository.fetch_rules(Some(id)).await {
+        Ok(rules) => HttpResponse::Ok().json(
+            rules
+                .into_iter()
+                .map(|(id, _, rule)| AlertRuleResponse { id, rule })
+                .collect::<Vec<_>>(),
+        ),
+        Err(e) => {
+            error!("Failed to fetch alert rules of person {id}: {e}");
+            ApiError::Internal.error_response()
+        }
+    }
+}
+
+/// Adds an alert rule for a person. Values in conditions are in canonical units.
+#[post("/persons/{id}/alert-rules")]
+async fn create_rule(
+    person_repository: web::Data<PersonRepositoryImpl>,
+    alert_repository: web::Data<AlertRepositoryImpl>,
+    id: web::Path<i64>,
+    rule: web::Json<AlertRule>,
+) -> impl Responder {
+    let id = PersonId::new(*id);
+    if let Err(e) = check_rule(&rule) {
+        return ApiError::InvalidRequest(e).error_response();
+    }
+    if let Err(response) = person_exists(&person_repository, id).await {
+        return response;
+    }
+    match alert_repository.create_rule(id, rule.0.clone()).await {
+        Ok(rule_id) => {
+            info!("Created alert rule {rule_id} for person {id}");
+            HttpResponse::Created().json(AlertRuleResponse {
+                id: rule_id,
+                rule: rule.0,
+            })
+        }
+        Err(e) => {
+            error!("Failed to create alert rule for person {id}: {e}");
+            ApiError::Internal.error_response()
+        }
+    }
+}
+
+#[delete("/persons/{id}/alert-rules/{rule_id}")]
+async fn delete_rule(
+    alert_repository: web::Data<AlertRepositoryImpl>,
+    path: web::Path<(i64, i64)>,
+) -> impl Responder {
+    let (id, rule_id) = (PersonId::new(path.0), AlertRuleId::new(path.1));
+    match alert_repository.delete_rule(id, rule_id).await {
+        Ok(true) => {
+            info!("Deleted alert rule {rule_id} of person {id}");
+            HttpResponse::NoContent().finish()
+        }
+        Ok(false) => ApiError::NotFound(format!(
+            "Alert rule {rule_id} of person {id} does not exist"
+        ))
+        .error_response(),
+        Err(e) => {
+            error!("Failed to delete alert rule {rule_id} of person {id}: {e}");
+            ApiError::Internal.error_response()
+        }
+    }
+}
+
+/// Lists alerts raised for a person, most recent first, along with status
+/// of their delivery.
+#[get("/persons/{id}/alerts")]
+async fn list_alerts(
+    person_repository: web::Data<PersonRepositoryImpl>,
+    alert_repository: web::Data<AlertRepositoryImpl>,
+    id: web::Path<i64>,
+) -> impl Responder {
+    let id = PersonId::new(*id);
+    if let Err(response) = person_exists(&person_repository, id).await {
+        return response;
+    }
+    match alert_repository.fetch_alerts(id).await {
+        Ok(alerts) => HttpResponse::Ok().json(alerts),
+        Err(e) => {
+            error!("Failed to fetch alerts of person {id}: {e}");
+            ApiError::Internal.error_response()
+        }
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_rules)
+        .service(create_rule)
+        .service(delete_rule)
+        .service(list_alerts);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    };
+
+    use actix_web::{App, HttpServer};
+    use chrono::NaiveDate;
+    use healthpi_model::{
+        alert::AlertId,
+        body_composition::FormulaSet,
+        device::DeviceId,
+        measurement::{Source, Value},
+        person::{Person, Sex},
+    };
+
+    use super::*;
+    use crate::db::{connection::Connection, migration};
+
+    #[derive(Default)]
+    struct Webhook {
+        calls: AtomicU32,
+        failures: u32,
+        received: Mutex<Vec<Alert>>,
+    }
+
+    async fn receive(webhook: web::Data<Webhook>, alert: web::Json<Alert>) -> HttpResponse {
+        if webhook.calls.fetch_add(1, Ordering::SeqCst) < webhook.failures {
+            return HttpResponse::ServiceUnavailable().finish();
+        }
+        webhook.received.lock().unwrap().push(alert.0);
+        HttpResponse::Ok().finish()
+    }
+
+    /// Starts a local server accepting alerts after failing given number of times.
+    fn serve(failures: u32) -> (String, Arc<Webhook>) {
+        let webhook = web::Data::new(Webhook {
+            failures,
+            ..Default::default()
+        });
+        let data = webhook.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(data.clone())
+                .route("/hook", web::post().to(receive))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let url = format!("http://{}/hook", server.addrs()[0]);
+        rt::spawn(server.run());
+        (url, webhook.into_inner())
+    }
+
+    fn alert() -> Alert {
+        Alert {
+            id: AlertId::new(1),
+            rule: AlertRuleId::new(2),
+            person: PersonId::new(3),
+            triggered_at: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            measured_at: None,
+            record: None,
+            message: "Glucose of 62 is below 70".into(),
+            status: DeliveryStatus::Pending,
+            attempts: 0,
+        }
+    }
+
+    #[actix_web::test]
+    async fn delivers_alert_to_webhook_after_retries() {
+        let (url, webhook) = serve(2);
+
+        let result = post_alert(&Client::new(), &url, &alert(), Duration::from_millis(1)).await;
+
+        assert_eq!(result, (DeliveryStatus::Delivered, 3));
+        assert_eq!(*webhook.received.lock().unwrap(), vec![alert()]);
+    }
+
+    #[actix_web::test]
+    async fn gives_up_after_max_attempts() {
+        let (url, webhook) = serve(u32::MAX);
+
+        let result = post_alert(&Client::new(), &url, &alert(), Duration::from_millis(1)).await;
+
+        assert_eq!(result, (DeliveryStatus::Failed, MAX_ATTEMPTS));
+        assert_eq!(webhook.calls.load(Ordering::SeqCst), MAX_ATTEMPTS);
+        assert!(webhook.received.lock().unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn raises_alerts_for_records_measured_at_fractions_of_seconds() {
+        let connection = Connection::establish("sqlite::memory:", true, 1)
+            .await
+            .unwrap();
+        migration::run(&connection).await.unwrap();
+        let alert_repository = AlertRepositoryImpl::new(connection.clone());
+        let measurement_repository = MeasurementRepositoryImpl::new(connection.clone());
+        let person = PersonRepositoryImpl::new(connection)
+            .create_person(Person {
+                name: "Alice".into(),
+                birth_date: NaiveDate::from_ymd_opt(1980, 5, 17).unwrap(),
+                sex: Sex::Female,
+                height_cm: 168,
+                activity_level: 2,
+                formula: FormulaSet::default(),
+                goal_weight_kg: None,
+            })
+            .await
+            .unwrap();
+        let (url, _) = serve(0);
+        alert_repository
+            .create_rule(
+                person,
+                AlertRule {
+                    condition: AlertCondition::Below {
+                        value_type: ValueType::Glucose,
+                        threshold: 70.0,
+                    },
+                    webhook_url: url,
+                },
+            )
+            .await
+            .unwrap();
+        let mut record = Record::new(
+            "2024-03-20T07:00:00.250+01:00".parse().unwrap(),
+            vec![Value::Glucose(62.0)],
+            Vec::new(),
+            Source::Device(DeviceId::new("contour".into())),
+        );
+        record.person = Some(person);
+        let ids = measurement_repository
+            .store_records(vec![record])
+            .await
+            .unwrap();
+
+        Alerter::new(alert_repository.clone(), measurement_repository)
+            .evaluate(&ids)
+            .await
+            .unwrap();
+
+        let alerts = alert_repository.fetch_alerts(person).await.unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].record, Some(ids[0]));
+    }
+}
@@ -0,0 +1,114 @@
+use actix_web::{get, web, HttpResponse, Responder, ResponseError};
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use healthpi_model::{
+    blood_pressure::{self, Average, BloodPressureReading},
+    device::DeviceId,
+    filter::{Order, RecordFilter},
+    measurement::{SourceKind, ValueType},
+    person::PersonId,
+    units::{Unit, UnitSystem},
+};
+use log::error;
+use serde::Deserialize;
+
+use crate::{
+    db::measurement::{MeasurementRepository, MeasurementRepositoryImpl},
+    error::ApiError,
+};
+
+#[derive(Debug, Deserialize)]
+struct BloodPressureQuery {
+    /// Earliest time of measurement, inclusive.
+    from: Option<DateTime<FixedOffset>>,
+    /// Latest time of measurement, exclusive.
+    to: Option<DateTime<FixedOffset>>,
+    #[serde(default)]
+    #[serde(deserialize_with = "crate::comma_separated")]
+    source: Vec<SourceKind>,
+    #[serde(default)]
+    #[serde(deserialize_with = "crate::comma_separated")]
+    device: Vec<DeviceId>,
+    person: Option<PersonId>,
+    #[serde(default)]
+    units: UnitSystem,
+    /// Minutes from the first reading of a session within which following
+    /// readings belong to it.
+    #[serde(default = "default_window")]
+    window: u32,
+}
+
+fn default_window() -> u32 {
+    10
+}
+
+impl BloodPressureQuery {
+    fn filter(&self) -> RecordFilter {
+        RecordFilter {
+            select: vec![
+                ValueType::BloodPressureSystolic,
+                ValueType::BloodPressureDiastolic,
+                ValueType::HeartRate,
+            ],
+            from: self.from,
+            to: self.to,
+            sources: self.source.clone(),
+            devices: self.device.clone(),
+            person: self.person,
+            order: Order::Ascending,
+            ..Default::default()
+        }
+    }
+}
+
+fn convert_average(average: &mut Average, units: UnitSystem) {
+    average.systolic = units.pressure.canonical_to(average.systolic);
+    average.diastolic = units.pressure.canonical_to(average.diastolic);
+}
+
+/// Groups blood pressure readings into sessions, and averages them overall,
+/// in the morning and in the evening. Categories are always determined from
+/// pressures in mmHg.
+#[get("/blood-pressure")]
+async fn blood_pressure_summary(
+    measurement_repository: web::Data<MeasurementRepositoryImpl>,
+    query: web::Query<BloodPressureQuery>,
+) -> impl Responder {
+    if query.window == 0 {
+        return ApiError::InvalidRequest("Window must be positive".into()).error_response();
+    }
+
+    let records = match measurement_repository
+        .fetch_records(&query.filter(), false)
+        .await
+    {
+        Ok(records) => records,
+        Err(e) => {
+            error!("Failed to fetch blood pressure readings: {e}");
+            return ApiError::Internal.error_response();
+        }
+    };
+    let readings: Vec<_> = records
+        .iter()
+        .filter_map(BloodPressureReading::from_record)
+        .collect();
+
+    let mut summary = blood_pressure::summarize(&readings, TimeDelta::minutes(query.window.into()));
+    for session in &mut summary.sessions {
+        convert_average(&mut session.average, query.units);
+    }
+    for average in [
+        &mut summary.overall,
+        &mut summary.morning,
+        &mut summary.evening,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        convert_average(average, query.units);
+    }
+    HttpResponse::Ok().json(summary)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(blood_pressure_summary);
+}
@@ -0,0 +1,353 @@
+//! Configuration of the API server. Settings are read from a config file in
+//! RON notation, then overridden by environment variables, and finally by
+//! command line flags.
+
+use std::{fmt, fs, io, path::PathBuf};
+
+use serde::Deserialize;
+
+/// Config file read if no other is given and it exists.
+const DEFAULT_CONFIG_FILE: &str = "healthpi-api.ron";
+
+pub const USAGE: &str = "Usage: healthpi-api [migrate] [OPTIONS]
+
+Applies pending database migrations and starts the server.
+
+Commands:
+    migrate                Apply pending database migrations and exit
+
+Options:
+    --check-migrations     Report pending database migrations and exit, failing
+                           if there are any
+    --config <FILE>        Config file [env: HEALTHPI_CONFIG] [default: healthpi-api.ron]
+    --address <ADDRESS>    Address to listen on [env: HEALTHPI_ADDRESS] [default: 127.0.0.1]
+    --port <PORT>          Port to listen on [env: HEALTHPI_PORT] [default: 8080]
+    --database <DATABASE>  SQLite database file or URL [env: HEALTHPI_DATABASE, DATABASE_URL]
+                           [default: sqlite:healthpi.db]
+    --read-connections <N> Number of database connections serving reads at once
+                           [env: HEALTHPI_READ_CONNECTIONS] [default: 8]
+    --cors-origin <ORIGIN> Origin allowed to make cross-origin requests, can be repeated.
+                           All origins are allowed if none is given
+                           [env: HEALTHPI_CORS_ORIGINS, comma separated]
+    --log-config <FILE>    log4rs config file [env: HEALTHPI_LOG_CONFIG] [default: log4rs.yml]
+    --help                 Print this message";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Serve,
+    Migrate,
+    CheckMigrations,
+    Help,
+}
+
+impl Command {
+    /// Splits command line arguments, excluding the name of the program,
+    /// into the command and the remaining ones configuring it.
+    pub fn from_args(args: &[String]) -> (Self, Vec<String>) {
+        let mut args = args.to_vec();
+        let mut command = Command::Serve;
+        if args.first().is_some_and(|arg| arg == "migrate") {
+            args.remove(0);
+            command = Command::Migrate;
+        }
+        if let Some(i) = args.iter().position(|arg| arg == "--check-migrations") {
+            args.remove(i);
+            command = Command::CheckMigrations;
+        }
+        if args.iter().any(|arg| arg == "--help") {
+            command = Command::Help;
+        }
+        (command, args)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub address: String,
+    pub port: u16,
+    /// Path to an SQLite database file, or an `sqlite:` URL.
+    pub database: String,
+    /// Size of the pool of connections serving reads. Writes always go
+    /// through a single connection.
+    pub read_connections: u32,
+    /// Origins allowed to make cross-origin requests. All are allowed if empty.
+    pub cors_origins: Vec<String>,
+    pub log_config: PathBuf,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            address: "127.0.0.1".into(),
+            port: 8080,
+            database: "sqlite:healthpi.db".into(),
+            read_connections: 8,
+            cors_origins: Vec::new(),
+            log_config: "log4rs.yml".into(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    UnknownArgument(String),
+    MissingValue(String),
+    InvalidValue { setting: String, value: String },
+    ReadFile(PathBuf, io::Error),
+    ParseFile(PathBuf, ron::de::SpannedError),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::UnknownArgument(arg) => write!(f, "Unknown argument: {arg}"),
+            ConfigError::MissingValue(flag) => write!(f, "Missing value of {flag}"),
+            ConfigError::InvalidValue { setting, value } => {
+                write!(f, "Invalid value of {setting}: {value}")
+            }
+            ConfigError::ReadFile(path, e) => {
+                write!(f, "Failed to read config file {}: {e}", path.display())
+            }
+            ConfigError::ParseFile(path, e) => {
+                write!(f, "Invalid config file {}: {e}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Settings given by environment variables or command line flags, overriding
+/// those from the config file.
+#[derive(Debug, Default)]
+struct Overrides {
+    config_file: Option<PathBuf>,
+    address: Option<String>,
+    port: Option<String>,
+    database: Option<String>,
+    read_connections: Option<String>,
+    cors_origins: Option<Vec<String>>,
+    log_config: Option<PathBuf>,
+}
+
+impl Overrides {
+    fn from_env(env: impl Fn(&str) -> Option<String>) -> Self {
+        Self {
+            config_file: env("HEALTHPI_CONFIG").map(PathBuf::from),
+            address: env("HEALTHPI_ADDRESS"),
+            port: env("HEALTHPI_PORT"),
+            // Also used by sqlx-cli, so that both work on the same database.
+            database: env("HEALTHPI_DATABASE").or_else(|| env("DATABASE_URL")),
+            read_connections: env("HEALTHPI_READ_CONNECTIONS"),
+            cors_origins: env("HEALTHPI_CORS_ORIGINS").map(|origins| {
+                origins
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|origin| !origin.is_empty())
+                    .map(String::from)
+                    .collect()
+            }),
+            log_config: env("HEALTHPI_LOG_CONFIG").map(PathBuf::from),
+        }
+    }
+
+    fn from_args(args: &[String]) -> Result<Self, ConfigError> {
+        let mut overrides = Self::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .cloned()
+                    .ok_or_else(|| ConfigError::MissingValue(arg.clone()))
+            };
+            match arg.as_str() {
+                "--config" => overrides.config_file = Some(value()?.into()),
+                "--address" => overrides.address = Some(value()?),
+                "--port" => overrides.port = Some(value()?),
+                "--database" => overrides.database = Some(value()?),
+                "--read-connections" => overrides.read_connections = Some(value()?),
+                "--cors-origin" => {
+                    let origin = value()?;
+                    overrides
+                        .cors_origins
+                        .get_or_insert_with(Vec::new)
+                        .push(origin)
+                }
+                "--log-config" => overrides.log_config = Some(value()?.into()),
+                _ => return Err(ConfigError::UnknownArgument(arg.clone())),
+            }
+        }
+        Ok(overrides)
+    }
+
+    fn apply(self, config: &mut Config) -> Result<(), ConfigError> {
+        if let Some(address) = self.address {
+            config.address = address;
+        }
+        if let Some(port) = self.port {
+            config.port = port.parse().map_err(|_| ConfigError::InvalidValue {
+                setting: "port".into(),
+                value: port,
+            })?;
+        }
+        if let Some(database) = self.database {
+            config.database = database;
+        }
+        if let Some(read_connections) = self.read_connections {
+            config.read_connections =
+                read_connections
+                    .parse()
+                    .map_err(|_| ConfigError::InvalidValue {
+                        setting: "read connections".into(),
+                        value: read_connections,
+                    })?;
+        }
+        if let Some(cors_origins) = self.cors_origins {
+            config.cors_origins = cors_origins;
+        }
+        if let Some(log_config) = self.log_config {
+            config.log_config = log_config;
+        }
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Loads configuration given command line arguments, excluding the name
+    /// of the program, and a source of environment variables.
+    pub fn load(
+        args: &[String],
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, ConfigError> {
+        let from_env = Overrides::from_env(env);
+        let from_args = Overrides::from_args(args)?;
+
+        let mut config = match from_args
+            .config_file
+            .as_ref()
+            .or(from_env.config_file.as_ref())
+        {
+            Some(path) => Self::from_file(path)?,
+            None if fs::metadata(DEFAULT_CONFIG_FILE).is_ok() => {
+                Self::from_file(&PathBuf::from(DEFAULT_CONFIG_FILE))?
+            }
+            None => Self::default(),
+        };
+        from_env.apply(&mut config)?;
+        from_args.apply(&mut config)?;
+        if config.read_connections == 0 {
+            return Err(ConfigError::InvalidValue {
+                setting: "read connections".into(),
+                value: "0".into(),
+            });
+        }
+        Ok(config)
+    }
+
+    fn from_file(path: &PathBuf) -> Result<Self, ConfigError> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| ConfigError::ReadFile(path.clone(), e))?;
+        ron::from_str(&contents).map_err(|e| ConfigError::ParseFile(path.clone(), e))
+    }
+
+    /// URL of the database, given either a path or a URL.
+    pub fn database_url(&self) -> String {
+        if self.database.starts_with("sqlite:") {
+            self.database.clone()
+        } else {
+            format!("sqlite:{}", self.database)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn flags_override_environment() {
+        let env = HashMap::from([
+            ("HEALTHPI_ADDRESS", "0.0.0.0"),
+            ("HEALTHPI_PORT", "9000"),
+            ("DATABASE_URL", "sqlite:old.db"),
+            ("HEALTHPI_CORS_ORIGINS", "http://a.local, http://b.local"),
+        ]);
+
+        let config = Config::load(
+            &args(&["--port", "9090", "--database", "/var/lib/healthpi.db"]),
+            |var| env.get(var).map(|value| value.to_string()),
+        )
+        .unwrap();
+
+        assert_eq!(
+            config,
+            Config {
+                address: "0.0.0.0".into(),
+                port: 9090,
+                database: "/var/lib/healthpi.db".into(),
+                read_connections: 8,
+                cors_origins: vec!["http://a.local".into(), "http://b.local".into()],
+                log_config: "log4rs.yml".into(),
+            }
+        );
+        assert_eq!(config.database_url(), "sqlite:/var/lib/healthpi.db");
+    }
+
+    #[test]
+    fn separates_command_from_flags() {
+        assert_eq!(
+            Command::from_args(&args(&["migrate", "--database", "pi.db"])),
+            (Command::Migrate, args(&["--database", "pi.db"]))
+        );
+        assert_eq!(
+            Command::from_args(&args(&["--port", "80", "--check-migrations"])),
+            (Command::CheckMigrations, args(&["--port", "80"]))
+        );
+        assert_eq!(
+            Command::from_args(&args(&["--port", "80"])),
+            (Command::Serve, args(&["--port", "80"]))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        assert!(matches!(
+            Config::load(&args(&["--port"]), |_| None),
+            Err(ConfigError::MissingValue(_))
+        ));
+        assert!(matches!(
+            Config::load(&args(&["--port", "http"]), |_| None),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            Config::load(&args(&["--read-connections", "0"]), |_| None),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            Config::load(&args(&["--verbose"]), |_| None),
+            Err(ConfigError::UnknownArgument(_))
+        ));
+    }
+
+    #[test]
+    fn parses_partial_config_file() {
+        let config: Config =
+            ron::from_str(r#"(address: "0.0.0.0", cors_origins: ["http://pi.local"])"#).unwrap();
+
+        assert_eq!(
+            config,
+            Config {
+                address: "0.0.0.0".into(),
+                cors_origins: vec!["http://pi.local".into()],
+                ..Default::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,284 @@
+use std::error::Error;
+
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset, Utc};
+use healthpi_model::{
+    alert::{Alert, AlertId, AlertRule, AlertRuleId, DeliveryStatus},
+    measurement::RecordId,
+    person::PersonId,
+};
+use sqlx::{sqlite::SqliteRow, FromRow, Row};
+
+use super::{connection::Connection, measurement::DbError};
+
+fn status_to_str(status: DeliveryStatus) -> &'static str {
+    match status {
+        DeliveryStatus::Pending => "pending",
+        DeliveryStatus::Delivered => "delivered",
+        DeliveryStatus::Failed => "failed",
+    }
+}
+
+fn status_from_str(s: &str) -> Option<DeliveryStatus> {
+    match s {
+        "pending" => Some(DeliveryStatus::Pending),
+        "delivered" => Some(DeliveryStatus::Delivered),
+        "failed" => Some(DeliveryStatus::Failed),
+        _ => None,
+    }
+}
+
+pub struct AlertRuleRow {
+    id: AlertRuleId,
+    person: PersonId,
+    rule: AlertRule,
+}
+
+impl<'r> FromRow<'r, SqliteRow> for AlertRuleRow {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            id: AlertRuleId::new(row.try_get("id")?),
+            person: PersonId::new(row.try_get("person_id")?),
+            rule: AlertRule {
+                condition: ron::from_str(row.try_get("condition")?).map_err(|e| {
+                    sqlx::Error::ColumnDecode {
+                        index: "condition".into(),
+                        source: Box::new(e),
+                    }
+                })?,
+                webhook_url: row.try_get("webhook_url")?,
+            },
+        })
+    }
+}
+
+pub struct AlertRow {
+    alert: Alert,
+}
+
+impl<'r> FromRow<'r, SqliteRow> for AlertRow {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        let measured_at = row
+            .try_get::<Option<i64>, _>("measured_at")?
+            .zip(row.try_get::<Option<i32>, _>("utc_offset")?)
+            .map(|(timestamp, utc_offset)| {
+                DateTime::from_timestamp(timestamp, 0)
+                    .zip(FixedOffset::east_opt(utc_offset))
+                    .map(|(timestamp, offset)| timestamp.with_timezone(&offset))
+                    .ok_or_else(|| sqlx::Error::ColumnDecode {
+                        index: "measured_at".into(),
+                        source: Box::new(DbError::InvalidTimestamp),
+                    })
+            })
+            .transpose()?;
+        Ok(Self {
+            alert: Alert {
+                id: AlertId::new(row.try_get("id")?),
+                rule: AlertRuleId::new(row.try_get("rule_id")?),
+                person: PersonId::new(row.try_get("person_id")?),
+                triggered_at: DateTime::from_timestamp(row.try_get("triggered_at")?, 0)
+                    .ok_or_else(|| sqlx::Error::ColumnDecode {
+                        index: "triggered_at".into(),
+                        source: Box::new(DbError::InvalidTimestamp),
+                    })?,
+                measured_at,
+                record: row
+                    .try_get::<Option<i64>, _>("record_id")?
+                    .map(RecordId::new),
+                message: row.try_get("message")?,
+                status: status_from_str(row.try_get("status")?).ok_or_else(|| {
+                    sqlx::Error::ColumnDecode {
+                        index: "status".into(),
+                        source: Box::new(DbError::InvalidValue),
+                    }
+                })?,
+                attempts: row.try_get("attempts")?,
+            },
+        })
+    }
+}
+
+/// An alert about to be raised.
+pub struct NewAlert {
+    pub rule: AlertRuleId,
+    pub person: PersonId,
+    /// Identifies what triggered the alert. An alert is only raised once
+    /// per rule and key.
+    pub trigger_key: String,
+    pub triggered_at: DateTime<Utc>,
+    pub measured_at: Option<DateTime<FixedOffset>>,
+    pub record: Option<RecordId>,
+    pub message: String,
+}
+
+#[async_trait]
+pub trait AlertRepository: Send + Sync {
+    async fn create_rule(
+        &self,
+        person: PersonId,
+        rule: AlertRule,
+    ) -> Result<AlertRuleId, Box<dyn Error>>;
+    /// Fetches rules of a person, or of everyone if no person is given.
+    async fn fetch_rules(
+        &self,
+        person: Option<PersonId>,
+    ) -> Result<Vec<(AlertRuleId, PersonId, AlertRule)>, Box<dyn Error>>;
+    async fn delete_rule(&self, person: PersonId, id: AlertRuleId) -> Result<bool, Box<dyn Error>>;
+    /// Raises an alert, as pending delivery. Returns None if the rule has
+    /// already raised an alert with the same trigger key.
+    async fn create_alert(&self, alert: NewAlert) -> Result<Option<Alert>, Box<dyn Error>>;
+    /// Fetches alerts raised for a person, most recent first.
+    async fn fetch_alerts(&self, person: PersonId) -> Result<Vec<Alert>, Box<dyn Error>>;
+    /// Fetches alerts pending delivery, along with webhook URLs of their rules.
+    async fn fetch_pending_alerts(&self) -> Result<Vec<(Alert, String)>, Box<dyn Error>>;
+    async fn update_delivery(
+        &self,
+        id: AlertId,
+        status: DeliveryStatus,
+        attempts: u32,
+    ) -> Result<(), Box<dyn Error>>;
+}
+
+#[derive(Clone)]
+pub struct AlertRepositoryImpl {
+    connection: Connection,
+}
+
+impl AlertRepositoryImpl {
+    pub fn new(connection: Connection) -> Self {
+        Self { connection }
+    }
+}
+
+#[async_trait]
+impl AlertRepository for AlertRepositoryImpl {
+    async fn create_rule(
+        &self,
+        person: PersonId,
+        rule: AlertRule,
+    ) -> Result<AlertRuleId, Box<dyn Error>> {
+        let mut conn = self.connection.write().await?;
+        let id = sqlx::query(
+            "INSERT INTO alert_rules(person_id, condition, webhook_url) VALUES (?, ?, ?)",
+        )
+        .bind(person.value())
+        .bind(ron::to_string(&rule.condition)?)
+        .bind(rule.webhook_url)
+        .execute(&mut *conn)
+        .await?
+        .last_insert_rowid();
+
+        Ok(AlertRuleId::new(id))
+    }
+
+    async fn fetch_rules(
+        &self,
+        person: Option<PersonId>,
+    ) -> Result<Vec<(AlertRuleId, PersonId, AlertRule)>, Box<dyn Error>> {
+        let mut conn = self.connection.read().await?;
+        Ok(sqlx::query_as::<_, AlertRuleRow>(
+            "SELECT * FROM alert_rules WHERE ? IS NULL OR person_id = ? ORDER BY id",
+        )
+        .bind(person.map(|p| p.value()))
+        .bind(person.map(|p| p.value()))
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|row| (row.id, row.person, row.rule))
+        .collect())
+    }
+
+    async fn delete_rule(&self, person: PersonId, id: AlertRuleId) -> Result<bool, Box<dyn Error>> {
+        let mut conn = self.connection.write().await?;
+        let result = sqlx::query("DELETE FROM alert_rules WHERE id = ? AND person_id = ?")
+            .bind(id.value())
+            .bind(person.value())
+            .execute(&mut *conn)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn create_alert(&self, alert: NewAlert) -> Result<Option<Alert>, Box<dyn Error>> {
+        let mut conn = self.connection.write().await?;
+        let result = sqlx::query(
+            "INSERT INTO alerts(rule_id, person_id, trigger_key, triggered_at, measured_at,
+                utc_offset, record_id, message, status)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT DO NOTHING",
+        )
+        .bind(alert.rule.value())
+        .bind(alert.person.value())
+        .bind(alert.trigger_key)
+        .bind(alert.triggered_at.timestamp())
+        .bind(alert.measured_at.map(|t| t.timestamp()))
+        .bind(alert.measured_at.map(|t| t.offset().local_minus_utc()))
+        .bind(alert.record.map(|r| r.value()))
+        .bind(&alert.message)
+        .bind(status_to_str(DeliveryStatus::Pending))
+        .execute(&mut *conn)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(Alert {
+            id: AlertId::new(result.last_insert_rowid()),
+            rule: alert.rule,
+            person: alert.person,
+            triggered_at: alert.triggered_at,
+            measured_at: alert.measured_at,
+            record: alert.record,
+            message: alert.message,
+            status: DeliveryStatus::Pending,
+            attempts: 0,
+        }))
+    }
+
+    async fn fetch_alerts(&self, person: PersonId) -> Result<Vec<Alert>, Box<dyn Error>> {
+        let mut conn = self.connection.read().await?;
+        Ok(sqlx::query_as::<_, AlertRow>(
+            "SELECT * FROM alerts WHERE person_id = ? ORDER BY triggered_at DESC, id DESC",
+        )
+        .bind(person.value())
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|row| row.alert)
+        .collect())
+    }
+
+    async fn fetch_pending_alerts(&self) -> Result<Vec<(Alert, String)>, Box<dyn Error>> {
+        let mut conn = self.connection.read().await?;
+        let rows = sqlx::query(
+            "SELECT alerts.*, alert_rules.webhook_url
+            FROM alerts, alert_rules
+            WHERE alerts.rule_id = alert_rules.id AND status = ?
+            ORDER BY alerts.id",
+        )
+        .bind(status_to_str(DeliveryStatus::Pending))
+        .fetch_all(&mut *conn)
+        .await?;
+
+        rows.iter()
+            .map(|row| Ok((AlertRow::from_row(row)?.alert, row.try_get("webhook_url")?)))
+            .collect()
+    }
+
+    async fn update_delivery(
+        &self,
+        id: AlertId,
+        status: DeliveryStatus,
+        attempts: u32,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut conn = self.connection.write().await?;
+        sqlx::query("UPDATE alerts SET status = ?, attempts = ? WHERE id = ?")
+            .bind(status_to_str(status))
+            .bind(attempts)
+            .bind(id.value())
+            .execute(&mut *conn)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -1,34 +1,254 @@
-use std::{env, error::Error, sync::Arc};
+use std::{error::Error, str::FromStr};
 
-use dotenv::dotenv;
-use sqlx::{Connection as SqlxConnection, Executor, SqliteConnection};
-use tokio::sync::{Mutex, MutexGuard};
-
-const SETUP_QUERY: &str = "PRAGMA mmap_size = 30000000000;
-PRAGMA cache_size = -1000;
-PRAGMA page_size = 4096;
-PRAGMA journal_mode = WAL;
-PRAGMA synchronous = NORMAL;";
+use sqlx::{
+    pool::PoolConnection,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous},
+    Sqlite, SqlitePool,
+};
 
+/// Connections to the database. Reads go through a pool of read-only
+/// connections, so that they can run concurrently with each other and with
+/// a write, which the WAL journal allows. Writes go through a single
+/// connection, as SQLite only allows one writer at a time anyway.
 #[derive(Clone)]
 pub struct Connection {
-    inner: Arc<Mutex<SqliteConnection>>,
+    readers: SqlitePool,
+    writer: SqlitePool,
 }
 
 impl Connection {
-    pub async fn establish() -> Result<Self, Box<dyn Error>> {
-        dotenv().ok();
-        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-        let mut connection = SqliteConnection::connect(&database_url).await?;
+    /// Connects to a database, creating it first if it does not exist
+    /// and it is allowed to. At most `read_connections` reads run at once.
+    pub async fn establish(
+        database_url: &str,
+        create_if_missing: bool,
+        read_connections: u32,
+    ) -> Result<Self, Box<dyn Error>> {
+        let options = SqliteConnectOptions::from_str(database_url)?
+            .pragma("mmap_size", "30000000000")
+            .pragma("cache_size", "-1000")
+            .page_size(4096);
+
+        // Connections are kept open for good, as otherwise an in-memory
+        // database would be dropped along with the last of them.
+        let writer = SqlitePoolOptions::new()
+            .max_connections(1)
+            .min_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_with(
+                options
+                    .clone()
+                    .create_if_missing(create_if_missing)
+                    .journal_mode(SqliteJournalMode::Wal)
+                    .synchronous(SqliteSynchronous::Normal),
+            )
+            .await?;
+
+        // Every connection to an in-memory database opens a database of its
+        // own, so the writer has to serve reads as well.
+        let in_memory = database_url.contains(":memory:") || database_url.contains("mode=memory");
+        let readers = if in_memory {
+            writer.clone()
+        } else {
+            SqlitePoolOptions::new()
+                .max_connections(read_connections)
+                .connect_with(options.read_only(true))
+                .await?
+        };
+
+        Ok(Self { readers, writer })
+    }
+
+    /// Acquires a connection for reading. It sees the database as of the
+    /// last write committed before each of its queries.
+    pub async fn read(&self) -> Result<PoolConnection<Sqlite>, sqlx::Error> {
+        self.readers.acquire().await
+    }
+
+    /// Acquires the connection for writing, waiting for other writes
+    /// to finish.
+    pub async fn write(&self) -> Result<PoolConnection<Sqlite>, sqlx::Error> {
+        self.writer.acquire().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs,
+        path::PathBuf,
+        time::{Duration, Instant},
+    };
+
+    use chrono::{DateTime, FixedOffset};
+    use futures_util::future::join_all;
+    use healthpi_model::{
+        device::DeviceId,
+        filter::RecordFilter,
+        measurement::{Record, Source, Value},
+    };
+
+    use super::*;
+    use crate::db::{
+        measurement::{MeasurementRepository, MeasurementRepositoryImpl},
+        migration,
+    };
+
+    /// Database file removed once the test is done with it. Concurrent
+    /// reads need a file, as in-memory databases are read by the writer.
+    struct TempDatabase(PathBuf);
 
-        connection.execute(SETUP_QUERY).await?;
+    impl TempDatabase {
+        fn new(name: &str) -> Self {
+            Self(env::temp_dir().join(format!("healthpi-{name}-{}.db", std::process::id())))
+        }
 
-        Ok(Self {
-            inner: Arc::new(Mutex::new(connection)),
-        })
+        fn url(&self) -> String {
+            format!("sqlite:{}", self.0.display())
+        }
     }
 
-    pub async fn lock(&self) -> MutexGuard<'_, SqliteConnection> {
-        self.inner.lock().await
+    impl Drop for TempDatabase {
+        fn drop(&mut self) {
+            for suffix in ["", "-wal", "-shm"] {
+                let _ = fs::remove_file(format!("{}{suffix}", self.0.display()));
+            }
+        }
+    }
+
+    fn records(count: usize, offset: usize) -> Vec<Record> {
+        let start: DateTime<FixedOffset> = "2024-01-01T00:00:00+01:00".parse().unwrap();
+        (offset..offset + count)
+            .map(|i| {
+                Record::new(
+                    start + chrono::Duration::minutes(i as i64),
+                    vec![Value::Glucose(90.0 + (i % 50) as f64)],
+                    Vec::new(),
+                    Source::Device(DeviceId::new("contour".into())),
+                )
+            })
+            .collect()
+    }
+
+    /// Repository reading through given number of connections, or through
+    /// the writer if none is given, as all queries did before reads had
+    /// a pool of their own.
+    async fn repository(
+        database: &TempDatabase,
+        read_connections: Option<u32>,
+    ) -> MeasurementRepositoryImpl {
+        let mut connection =
+            Connection::establish(&database.url(), true, read_connections.unwrap_or(1))
+                .await
+                .unwrap();
+        if read_connections.is_none() {
+            connection.readers = connection.writer.clone();
+        }
+        migration::run(&connection).await.unwrap();
+        MeasurementRepositoryImpl::new(connection)
+    }
+
+    #[actix_web::test]
+    async fn reads_do_not_wait_for_writes() {
+        let database = TempDatabase::new("reads-do-not-wait");
+        let connection = Connection::establish(&database.url(), true, 2)
+            .await
+            .unwrap();
+        migration::run(&connection).await.unwrap();
+        let repository = MeasurementRepositoryImpl::new(connection.clone());
+        repository.store_records(records(10, 0)).await.unwrap();
+
+        let mut writer = connection.write().await.unwrap();
+        sqlx::query("BEGIN IMMEDIATE")
+            .execute(&mut *writer)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE records SET deleted_at = 0")
+            .execute(&mut *writer)
+            .await
+            .unwrap();
+
+        let read = tokio::time::timeout(
+            Duration::from_secs(1),
+            repository.fetch_records(&RecordFilter::default(), false),
+        )
+        .await
+        .expect("read waited for the write")
+        .unwrap();
+        assert_eq!(read.len(), 10);
+
+        sqlx::query("ROLLBACK").execute(&mut *writer).await.unwrap();
+    }
+
+    /// Measures throughput of reads of records, as served by `GET /`, while
+    /// records are continuously stored, as by `POST /`. Run with:
+    ///
+    /// cargo test --release -p healthpi-api concurrent_reads -- --ignored --nocapture
+    ///
+    /// Averages of two runs on a single core, where reads sharing the writer
+    /// outpace a small pool, while uploads wait behind every queued read.
+    /// A pool as large as the number of concurrent readers is ahead on both;
+    /// doubling it past that gains little for twice the open connections.
+    ///
+    /// | read connections | reads/s | write batches/s |
+    /// |------------------|---------|-----------------|
+    /// | shared (before)  | 8.1     | 1.2             |
+    /// | 1                | 3.0     | 5.7             |
+    /// | 2                | 4.7     | 4.5             |
+    /// | 4                | 7.3     | 3.5             |
+    /// | 8 (default)      | 10.3    | 2.9             |
+    /// | 16               | 11.3    | 2.9             |
+    #[actix_web::test]
+    #[ignore]
+    async fn benchmark_concurrent_reads_during_writes() {
+        const READERS: usize = 8;
+        const DURATION: Duration = Duration::from_secs(3);
+        const WRITE_BATCH: usize = 1000;
+
+        for read_connections in [None, Some(1), Some(2), Some(4), Some(8), Some(16)] {
+            let label = read_connections.map_or("shared".into(), |n| n.to_string());
+            let database = TempDatabase::new(&format!("benchmark-{label}"));
+            let repository = repository(&database, read_connections).await;
+            for batch in 0..10 {
+                repository
+                    .store_records(records(WRITE_BATCH, batch * WRITE_BATCH))
+                    .await
+                    .unwrap();
+            }
+
+            let start = Instant::now();
+            let writes = async {
+                let mut batches = 0;
+                while start.elapsed() < DURATION {
+                    let offset = (10 + batches) * WRITE_BATCH;
+                    repository
+                        .store_records(records(WRITE_BATCH, offset))
+                        .await
+                        .unwrap();
+                    batches += 1;
+                }
+                batches
+            };
+            let reads = join_all((0..READERS).map(|_| async {
+                let mut count = 0;
+                while start.elapsed() < DURATION {
+                    repository
+                        .fetch_records(&RecordFilter::default(), false)
+                        .await
+                        .unwrap();
+                    count += 1;
+                }
+                count
+            }));
+            let (batches, reads) = futures_util::join!(writes, reads);
+
+            let elapsed = start.elapsed().as_secs_f64();
+            println!(
+                "{label} read connections: {:.1} reads/s, {:.1} writes/s",
+                reads.iter().sum::<usize>() as f64 / elapsed,
+                batches as f64 / elapsed,
+            );
+        }
     }
 }
@@ -1,18 +1,22 @@
 use core::fmt;
-use std::{
-    error::Error,
-    hash::{Hash, Hasher},
-};
+use std::error::Error;
 
 use async_trait::async_trait;
-use chrono::{DateTime, NaiveDateTime};
-use healthpi_model::measurement::{Record, Source, Value, ValueType};
+use chrono::{DateTime, FixedOffset, NaiveTime, Utc};
+use healthpi_model::{
+    changes::ChangeCursor,
+    correction::RecordPatch,
+    filter::{Order, RecordFilter},
+    measurement::{Record, RecordId, Source, SourceKind, Value, ValueType},
+    person::PersonId,
+};
 use itertools::Itertools;
 use log::{debug, error};
-use rustc_hash::FxHasher;
-use sqlx::{sqlite::SqliteRow, FromRow, QueryBuilder, Row};
+use sqlx::{
+    sqlite::SqliteRow, Connection as _, FromRow, QueryBuilder, Row, Sqlite, SqliteConnection,
+};
 
-use super::connection::Connection;
+use super::{connection::Connection, record_ref};
 
 #[derive(Debug)]
 pub enum DbError {
@@ -28,17 +32,30 @@ impl fmt::Display for DbError {
 
 impl std::error::Error for DbError {}
 
+/// Sources are stored in RON notation, which starts with the name of the variant.
+fn source_kind_pattern(kind: SourceKind) -> &'static str {
+    match kind {
+        SourceKind::Device => "Device(%",
+        SourceKind::Manual => "Manual(%",
+        SourceKind::Import => "Import(%",
+        SourceKind::Unknown => "Unknown(%",
+    }
+}
+
 pub struct NewRecord {
     record_ref: Vec<u8>,
     timestamp: i64,
+    utc_offset: i32,
     source: String,
+    person_id: Option<i64>,
+    raw_data: Option<Vec<u8>>,
 }
 
 fn record_to_new_value(val: Record) -> (NewRecord, Vec<NewValue>) {
-    let mut hasher = FxHasher::default();
-    val.timestamp.hash(&mut hasher);
-    val.source.hash(&mut hasher);
-    let record_ref = hasher.finish().to_le_bytes().to_vec();
+    // Records are identified by the local time of the device, rather than the
+    // absolute time. The offset is derived from configuration and may change,
+    // e.g. when it was not known before, but the reading stays the same.
+    let record_ref = record_ref::record_ref(val.timestamp.naive_local(), &val.source);
 
     let new_values = val
         .values
@@ -47,10 +64,11 @@ fn record_to_new_value(val: Record) -> (NewRecord, Vec<NewValue>) {
         .collect();
     let new_record = NewRecord {
         record_ref,
-        // Note: the timestamp is not actually in UTC or any other determinable timezone,
-        // but chrono deprecated timestamps for `NaiveDateTime`s.
-        timestamp: val.timestamp.and_utc().timestamp(),
+        timestamp: val.timestamp.timestamp(),
+        utc_offset: val.timestamp.offset().local_minus_utc(),
         source: ron::to_string(&val.source).unwrap(),
+        person_id: val.person.map(|p| p.value()),
+        raw_data: Some(val.raw_data).filter(|raw_data| !raw_data.is_empty()),
     };
 
     (new_record, new_values)
@@ -58,50 +76,65 @@ fn record_to_new_value(val: Record) -> (NewRecord, Vec<NewValue>) {
 
 pub struct NewValue {
     record_ref: Vec<u8>,
-    value_type: i32,
+    value_type: u32,
     value: f64,
 }
 
 impl NewValue {
     pub fn from_value(dto: Value, record_ref: Vec<u8>) -> Self {
-        let (value_type, value): (usize, f64) = dto.into();
+        let (value_type, value): (u32, f64) = dto.into();
         Self {
             record_ref,
-            value_type: value_type as i32,
+            value_type,
             value,
         }
     }
 }
 
 pub struct RecordRow {
-    timestamp: NaiveDateTime,
+    id: RecordId,
+    change_seq: i64,
+    deleted: bool,
+    timestamp: DateTime<FixedOffset>,
     source: Source,
-    value: Value,
+    person: Option<PersonId>,
+    raw_data: Vec<u8>,
+    /// Missing for records without values, as they are joined with none.
+    value: Option<Value>,
 }
 
 impl<'r> FromRow<'r, SqliteRow> for RecordRow {
     fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
         let timestamp = row.try_get("timestamp")?;
+        let utc_offset = row.try_get("utc_offset")?;
         Ok(Self {
+            id: RecordId::new(row.try_get("id")?),
+            change_seq: row.try_get("change_seq")?,
+            deleted: row.try_get::<Option<i64>, _>("deleted_at")?.is_some(),
             timestamp: DateTime::from_timestamp(timestamp, 0)
+                .zip(FixedOffset::east_opt(utc_offset))
+                .map(|(timestamp, offset)| timestamp.with_timezone(&offset))
                 .ok_or_else(|| sqlx::Error::ColumnDecode {
                     index: "timestamp".into(),
                     source: Box::new(DbError::InvalidTimestamp),
-                })?
-                // Note: the timestamp is not actually in UTC or any other determinable timezone,
-                // but chrono deprecated timestamps for `NaiveDateTime`s.
-                .naive_utc(),
+                })?,
             source: ron::from_str(row.try_get("source")?).map_err(|e| {
                 sqlx::Error::ColumnDecode {
                     index: "source".into(),
                     source: Box::new(e),
                 }
             })?,
-            value: (
-                row.try_get::<u32, _>("value_type")? as usize,
-                row.try_get::<f64, _>("value")?,
-            )
-                .try_into()
+            person: row
+                .try_get::<Option<i64>, _>("person_id")?
+                .map(PersonId::new),
+            raw_data: row
+                .try_get::<Option<Vec<u8>>, _>("raw_data")?
+                .unwrap_or_default(),
+            value: row
+                .try_get::<Option<u32>, _>("value_type")?
+                .zip(row.try_get::<Option<f64>, _>("value")?)
+                .map(Value::try_from)
+                .transpose()
                 .map_err(|_| sqlx::Error::ColumnDecode {
                     index: "value".into(),
                     source: Box::new(DbError::InvalidValue),
@@ -110,10 +143,241 @@ impl<'r> FromRow<'r, SqliteRow> for RecordRow {
     }
 }
 
+/// A record as stored, along with its position in the sequence of changes.
+pub struct StoredRecord {
+    pub cursor: ChangeCursor,
+    pub record: Record,
+    pub deleted: bool,
+}
+
 #[async_trait]
 pub trait MeasurementRepository: Send + Sync {
-    async fn store_records(&self, records: Vec<Record>) -> Result<(), Box<dyn Error>>;
-    async fn fetch_records(&self, select: &[ValueType]) -> Result<Vec<Record>, Box<dyn Error>>;
+    /// Stores records, updating ones stored before. Returns identifiers of
+    /// the stored records, in no particular order.
+    async fn store_records(&self, records: Vec<Record>) -> Result<Vec<RecordId>, Box<dyn Error>>;
+    /// Fetches records matching the filter. Raw data of records is only fetched
+    /// if requested, and left empty otherwise.
+    async fn fetch_records(
+        &self,
+        filter: &RecordFilter,
+        raw_data: bool,
+    ) -> Result<Vec<Record>, Box<dyn Error>>;
+    /// Fetches up to `limit` records matching the filter, following given time
+    /// of measurement and record in order of time and then identifier, so that
+    /// many records can be gone through a chunk at a time. Starts with the
+    /// first record if none is given.
+    async fn fetch_records_after(
+        &self,
+        filter: &RecordFilter,
+        after: Option<(DateTime<FixedOffset>, RecordId)>,
+        limit: u32,
+    ) -> Result<Vec<Record>, Box<dyn Error>>;
+    /// Fetches types of values present in records matching the filter.
+    async fn fetch_value_types(
+        &self,
+        filter: &RecordFilter,
+    ) -> Result<Vec<ValueType>, Box<dyn Error>>;
+    /// Summarizes values of records matching the filter within each of given
+    /// periods, as pairs of timestamps of their start, inclusive, and end,
+    /// exclusive. Periods without any values are left out.
+    async fn fetch_aggregates(
+        &self,
+        filter: &RecordFilter,
+        periods: &[(i64, i64)],
+    ) -> Result<Vec<AggregateRow>, Box<dyn Error>>;
+    /// Fetches a record by its identifier, unless it has been deleted. Records
+    /// left without values, e.g. by a patch, are fetched too.
+    async fn fetch_record(&self, id: RecordId) -> Result<Option<Record>, Box<dyn Error>>;
+    /// Fetches records by their identifiers, in order of time of measurement.
+    /// Records that have been deleted are left out.
+    async fn fetch_records_by_id(&self, ids: &[RecordId]) -> Result<Vec<Record>, Box<dyn Error>>;
+    /// Fetches up to `limit` records changed after given point in the sequence
+    /// of changes, in order of their changes. Includes deleted records.
+    async fn fetch_changes(
+        &self,
+        since: ChangeCursor,
+        limit: u32,
+    ) -> Result<Vec<StoredRecord>, Box<dyn Error>>;
+    /// Applies changes to a record. Returns false if there is no such record,
+    /// or it has been deleted.
+    async fn update_record(
+        &self,
+        id: RecordId,
+        patch: &RecordPatch,
+    ) -> Result<bool, Box<dyn Error>>;
+    /// Applies changes to several records at once, so that either all or none
+    /// of them are applied. Returns the number of records changed, leaving out
+    /// ones that do not exist or have been deleted.
+    async fn update_records(
+        &self,
+        patches: &[(RecordId, RecordPatch)],
+    ) -> Result<usize, Box<dyn Error>>;
+    /// Marks a record as deleted at given time. Returns false if there is
+    /// no such record, or it has already been deleted.
+    async fn delete_record(&self, id: RecordId, at: DateTime<Utc>) -> Result<bool, Box<dyn Error>>;
+    /// Restores a record, provided it was deleted no earlier than given time.
+    async fn restore_record(
+        &self,
+        id: RecordId,
+        deleted_since: DateTime<Utc>,
+    ) -> Result<bool, Box<dyn Error>>;
+}
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// How many identifiers or refs of records are bound in a single query.
+const MAX_BOUND_IDS: usize = 500;
+
+const SELECT_RECORDS: &str =
+    "SELECT id, change_seq, deleted_at, timestamp, utc_offset, source, person_id, ";
+
+/// Appends conditions selecting values of records matching the filter,
+/// other than their order, to a query joining records with their values.
+fn push_filter(query: &mut QueryBuilder<'_, Sqlite>, filter: &RecordFilter) {
+    if let Some(from) = filter.from {
+        query.push(" AND timestamp >= ").push_bind(from.timestamp());
+    }
+    if let Some(to) = filter.to {
+        query.push(" AND timestamp < ").push_bind(to.timestamp());
+    }
+    // Local time of a record is its timestamp shifted by its own offset,
+    // compared here as if it was in UTC.
+    if let Some(from_date) = filter.from_date {
+        query
+            .push(" AND timestamp + utc_offset >= ")
+            .push_bind(from_date.and_time(NaiveTime::MIN).and_utc().timestamp());
+    }
+    if let Some(to_date) = filter.to_date {
+        query
+            .push(" AND timestamp + utc_offset < ")
+            .push_bind(to_date.and_time(NaiveTime::MIN).and_utc().timestamp() + SECONDS_PER_DAY);
+    }
+    if !filter.devices.is_empty() {
+        query
+            .push(" AND source IN ")
+            .push_tuples(&filter.devices, |mut b, device| {
+                b.push_bind(ron::to_string(&Source::Device(device.clone())).unwrap());
+            });
+    }
+    if let Some(person) = filter.person {
+        query.push(" AND person_id = ").push_bind(person.value());
+    }
+    if !filter.sources.is_empty() {
+        query.push(" AND (");
+        let mut separated = query.separated(" OR ");
+        for kind in &filter.sources {
+            separated
+                .push("source LIKE ")
+                .push_bind_unseparated(source_kind_pattern(*kind));
+        }
+        query.push(")");
+    }
+    if !filter.select.is_empty() {
+        query
+            .push(" AND value_type IN ")
+            .push_tuples(&filter.select, |mut b, value| {
+                b.push_bind(value.code());
+            });
+    }
+}
+
+/// Summary of values of a single type within one of the requested periods.
+pub struct AggregateRow {
+    /// Index of the period.
+    pub period: usize,
+    pub value_type: ValueType,
+    pub count: u32,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub median: f64,
+}
+
+/// Groups rows of consecutive values into records.
+fn group_rows(rows: Vec<SqliteRow>) -> Vec<StoredRecord> {
+    rows.iter()
+        .flat_map(|row| RecordRow::from_row(row).map_err(|e| error!("{}", e)).ok())
+        .group_by(|s| {
+            (
+                s.id,
+                s.change_seq,
+                s.deleted,
+                s.timestamp,
+                s.source.clone(),
+                s.person,
+                s.raw_data.clone(),
+            )
+        })
+        .into_iter()
+        .map(
+            |((id, change_seq, deleted, timestamp, source, person, raw_data), values)| {
+                let mut record = Record::new(
+                    timestamp,
+                    values.into_iter().flat_map(|r| r.value).collect(),
+                    raw_data,
+                    source,
+                );
+                record.id = Some(id);
+                record.person = person;
+                StoredRecord {
+                    cursor: ChangeCursor::new(change_seq),
+                    record,
+                    deleted,
+                }
+            },
+        )
+        .collect()
+}
+
+/// Applies changes to a record within an ongoing transaction. Returns false
+/// if there is no such record, or it has been deleted.
+async fn apply_patch(
+    conn: &mut SqliteConnection,
+    id: RecordId,
+    patch: &RecordPatch,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE records SET person_id = COALESCE(?, person_id)
+        WHERE id = ? AND deleted_at IS NULL",
+    )
+    .bind(patch.person.map(|p| p.value()))
+    .bind(id.value())
+    .execute(&mut *conn)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    for value in &patch.values {
+        let (value_type, value): (u32, f64) = value.clone().into();
+        sqlx::query(
+            "INSERT INTO record_values(record_ref, value, value_type)
+            SELECT record_ref, ?, ? FROM records WHERE id = ?
+            ON CONFLICT DO UPDATE SET value=excluded.value",
+        )
+        .bind(value)
+        .bind(value_type)
+        .bind(id.value())
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    if !patch.remove.is_empty() {
+        QueryBuilder::new(
+            "DELETE FROM record_values
+            WHERE record_ref = (SELECT record_ref FROM records WHERE id = ",
+        )
+        .push_bind(id.value())
+        .push(") AND value_type IN ")
+        .push_tuples(&patch.remove, |mut b, value_type| {
+            b.push_bind(value_type.code());
+        })
+        .build()
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(true)
 }
 
 #[derive(Clone)]
@@ -127,77 +391,649 @@ impl MeasurementRepositoryImpl {
     }
 }
 
+impl MeasurementRepositoryImpl {
+    /// Rewrites refs of records derived with previous versions of the scheme,
+    /// so that they match records uploaded again. Returns the number of
+    /// rewritten records.
+    pub async fn upgrade_record_refs(&self) -> Result<usize, Box<dyn Error>> {
+        let mut conn = self.connection.write().await?;
+        let rows = sqlx::query(
+            "SELECT record_ref, timestamp, utc_offset, source FROM records WHERE ref_version < ?",
+        )
+        .bind(record_ref::CURRENT_VERSION)
+        .fetch_all(&mut *conn)
+        .await?;
+        if rows.is_empty() {
+            return Ok(0);
+        }
+
+        let mut tx = conn.begin().await?;
+        // Values are moved to the new ref after the record, so the constraint
+        // only holds at the end of the transaction.
+        sqlx::query("PRAGMA defer_foreign_keys = ON")
+            .execute(&mut *tx)
+            .await?;
+        for row in &rows {
+            let old_ref: Vec<u8> = row.try_get("record_ref")?;
+            let timestamp = DateTime::from_timestamp(row.try_get("timestamp")?, 0)
+                .zip(FixedOffset::east_opt(row.try_get("utc_offset")?))
+                .map(|(timestamp, offset)| timestamp.with_timezone(&offset))
+                .ok_or(DbError::InvalidTimestamp)?;
+            let source: Source = ron::from_str(row.try_get("source")?)?;
+            let new_ref = record_ref::record_ref(timestamp.naive_local(), &source);
+
+            sqlx::query("UPDATE records SET record_ref = ?, ref_version = ? WHERE record_ref = ?")
+                .bind(&new_ref)
+                .bind(record_ref::CURRENT_VERSION)
+                .bind(&old_ref)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("UPDATE record_values SET record_ref = ? WHERE record_ref = ?")
+                .bind(&new_ref)
+                .bind(&old_ref)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(rows.len())
+    }
+}
+
 #[async_trait]
 impl MeasurementRepository for MeasurementRepositoryImpl {
-    async fn store_records(&self, records: Vec<Record>) -> Result<(), Box<dyn Error>> {
+    async fn store_records(&self, records: Vec<Record>) -> Result<Vec<RecordId>, Box<dyn Error>> {
+        if records.is_empty() {
+            return Ok(Vec::new());
+        }
+
         debug!("Converting records");
         let (new_records, new_values_vecs): (Vec<NewRecord>, Vec<Vec<NewValue>>) =
             records.into_iter().map(record_to_new_value).unzip();
         let new_values: Vec<NewValue> = new_values_vecs.into_iter().flatten().collect();
 
-        let mut conn = self.connection.lock().await;
+        let record_refs: Vec<_> = new_records.iter().map(|r| r.record_ref.clone()).collect();
+
+        let mut conn = self.connection.write().await?;
+        let mut tx = conn.begin().await?;
 
         debug!("Storing records");
-        QueryBuilder::new("INSERT INTO records(timestamp, source, record_ref) ")
-            .push_values(new_records, |mut b, record| {
-                b.push_bind(record.timestamp)
-                    .push_bind(record.source)
-                    .push_bind(record.record_ref);
+        QueryBuilder::new(
+            "INSERT INTO records(timestamp, utc_offset, source, record_ref, ref_version, person_id, raw_data) ",
+        )
+        .push_values(new_records, |mut b, record| {
+            b.push_bind(record.timestamp)
+                .push_bind(record.utc_offset)
+                .push_bind(record.source)
+                .push_bind(record.record_ref)
+                .push_bind(record_ref::CURRENT_VERSION)
+                .push_bind(record.person_id)
+                .push_bind(record.raw_data);
+        })
+        // Records stored before offsets were known get them filled in
+        // when they are uploaded again. Uploads that do not know who the
+        // record belongs to do not override a previous assignment, and the same
+        // goes for raw data. Deleted records stay deleted, so that readings
+        // removed by hand do not come back when the device is synced again.
+        .push(
+            " ON CONFLICT(record_ref) DO UPDATE
+            SET timestamp=excluded.timestamp, utc_offset=excluded.utc_offset,
+                person_id=COALESCE(excluded.person_id, person_id),
+                raw_data=COALESCE(excluded.raw_data, raw_data) ",
+        )
+        .build()
+        .execute(&mut *tx)
+        .await?;
+
+        // Identifiers are given out by a trigger, so they are only known
+        // once records are inserted.
+        let mut ids = Vec::new();
+        for chunk in record_refs.chunks(MAX_BOUND_IDS) {
+            let rows = QueryBuilder::new("SELECT id FROM records WHERE record_ref IN ")
+                .push_tuples(chunk, |mut b, record_ref| {
+                    b.push_bind(record_ref.clone());
+                })
+                .build()
+                .fetch_all(&mut *tx)
+                .await?;
+            for row in rows {
+                ids.push(RecordId::new(row.try_get("id")?));
+            }
+        }
+
+        if !new_values.is_empty() {
+            debug!("Storing values");
+            QueryBuilder::new("INSERT INTO record_values(record_ref, value, value_type) ")
+                .push_values(new_values, |mut b, value| {
+                    b.push_bind(value.record_ref)
+                        .push_bind(value.value)
+                        .push_bind(value.value_type);
+                })
+                .push(" ON CONFLICT DO UPDATE SET value=excluded.value ")
+                .build()
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(ids)
+    }
+
+    async fn fetch_records(
+        &self,
+        filter: &RecordFilter,
+        raw_data: bool,
+    ) -> Result<Vec<Record>, Box<dyn Error>> {
+        let mut conn = self.connection.read().await?;
+        let mut query = QueryBuilder::new(SELECT_RECORDS);
+        query
+            .push(if raw_data {
+                "raw_data"
+            } else {
+                "NULL AS raw_data"
+            })
+            .push(
+                r#", value, value_type
+            FROM records, record_values 
+            WHERE records.record_ref = record_values.record_ref
+                AND deleted_at IS NULL "#,
+            );
+        push_filter(&mut query, filter);
+        let rows = query
+            .push(match filter.order {
+                Order::Ascending => " ORDER BY timestamp ASC, source ",
+                Order::Descending => " ORDER BY timestamp DESC, source ",
+            })
+            .build()
+            .fetch_all(&mut *conn)
+            .await?;
+
+        Ok(group_rows(rows)
+            .into_iter()
+            .map(|stored| stored.record)
+            .collect())
+    }
+
+    async fn fetch_records_after(
+        &self,
+        filter: &RecordFilter,
+        after: Option<(DateTime<FixedOffset>, RecordId)>,
+        limit: u32,
+    ) -> Result<Vec<Record>, Box<dyn Error>> {
+        let (comparison, order) = match filter.order {
+            Order::Ascending => (" > ", " ORDER BY timestamp ASC, id ASC "),
+            Order::Descending => (" < ", " ORDER BY timestamp DESC, id DESC "),
+        };
+        let mut conn = self.connection.read().await?;
+        // Records are limited before they are joined with their values,
+        // so that the limit applies to records rather than values.
+        let mut query = QueryBuilder::new(SELECT_RECORDS);
+        query.push(
+            r#"NULL AS raw_data, value, value_type
+            FROM (
+                SELECT DISTINCT id, change_seq, deleted_at, timestamp, utc_offset, source,
+                    person_id, records.record_ref
+                FROM records, record_values
+                WHERE records.record_ref = record_values.record_ref
+                    AND deleted_at IS NULL "#,
+        );
+        push_filter(&mut query, filter);
+        if let Some((timestamp, id)) = after {
+            query
+                .push(" AND (timestamp, id)")
+                .push(comparison)
+                .push("(")
+                .push_bind(timestamp.timestamp())
+                .push(", ")
+                .push_bind(id.value())
+                .push(")");
+        }
+        query.push(order).push(" LIMIT ").push_bind(limit).push(
+            r#") AS records, record_values
+            WHERE records.record_ref = record_values.record_ref "#,
+        );
+        push_filter(&mut query, filter);
+        let rows = query.push(order).build().fetch_all(&mut *conn).await?;
+
+        Ok(group_rows(rows)
+            .into_iter()
+            .map(|stored| stored.record)
+            .collect())
+    }
+
+    async fn fetch_value_types(
+        &self,
+        filter: &RecordFilter,
+    ) -> Result<Vec<ValueType>, Box<dyn Error>> {
+        let mut conn = self.connection.read().await?;
+        let mut query = QueryBuilder::new(
+            r#"SELECT DISTINCT value_type
+            FROM records, record_values
+            WHERE records.record_ref = record_values.record_ref
+                AND deleted_at IS NULL "#,
+        );
+        push_filter(&mut query, filter);
+        let rows = query.build().fetch_all(&mut *conn).await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(
+                    ValueType::from_code(row.try_get("value_type")?)
+                        .ok_or(DbError::InvalidValue)?,
+                )
             })
-            .push(" ON CONFLICT DO NOTHING ")
+            .collect()
+    }
+
+    async fn fetch_aggregates(
+        &self,
+        filter: &RecordFilter,
+        periods: &[(i64, i64)],
+    ) -> Result<Vec<AggregateRow>, Box<dyn Error>> {
+        if periods.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut conn = self.connection.read().await?;
+        // Periods are inlined rather than bound, as there can be more of them
+        // than SQLite allows parameters in a single query.
+        let mut query = QueryBuilder::new("WITH periods(period, start, end) AS (VALUES ");
+        for (i, (start, end)) in periods.iter().enumerate() {
+            if i > 0 {
+                query.push(", ");
+            }
+            query.push(format!("({i}, {start}, {end})"));
+        }
+        query.push(
+            r#"),
+            period_values AS (
+                SELECT period, value_type, value
+                FROM records, record_values, periods
+                WHERE records.record_ref = record_values.record_ref
+                    AND deleted_at IS NULL
+                    AND timestamp >= periods.start AND timestamp < periods.end "#,
+        );
+        push_filter(&mut query, filter);
+        let rows = query
+            .push(
+                r#"),
+            ranked_values AS (
+                SELECT period, value_type, value,
+                    ROW_NUMBER() OVER (PARTITION BY period, value_type ORDER BY value) AS rank,
+                    COUNT(*) OVER (PARTITION BY period, value_type) AS total
+                FROM period_values
+            )
+            SELECT period, value_type, COUNT(*) AS count,
+                MIN(value) AS min, MAX(value) AS max, AVG(value) AS mean,
+                AVG(CASE WHEN rank IN ((total + 1) / 2, (total + 2) / 2) THEN value END) AS median
+            FROM ranked_values
+            GROUP BY period, value_type
+            ORDER BY period, value_type"#,
+            )
             .build()
-            .execute(&mut *conn)
+            .fetch_all(&mut *conn)
             .await?;
 
-        debug!("Storing values");
-        QueryBuilder::new("INSERT INTO record_values(record_ref, value, value_type) ")
-            .push_values(new_values, |mut b, value| {
-                b.push_bind(value.record_ref)
-                    .push_bind(value.value)
-                    .push_bind(value.value_type);
+        rows.iter()
+            .map(|row| {
+                Ok(AggregateRow {
+                    period: row.try_get::<i64, _>("period")? as usize,
+                    value_type: ValueType::from_code(row.try_get("value_type")?)
+                        .ok_or(DbError::InvalidValue)?,
+                    count: row.try_get("count")?,
+                    min: row.try_get("min")?,
+                    max: row.try_get("max")?,
+                    mean: row.try_get("mean")?,
+                    median: row.try_get("median")?,
+                })
             })
-            .push(" ON CONFLICT DO UPDATE SET value=excluded.value ")
+            .collect()
+    }
+
+    async fn fetch_record(&self, id: RecordId) -> Result<Option<Record>, Box<dyn Error>> {
+        let mut conn = self.connection.read().await?;
+        let rows = QueryBuilder::new(SELECT_RECORDS)
+            .push(
+                r#"NULL AS raw_data, value, value_type
+            FROM records LEFT JOIN record_values
+                ON records.record_ref = record_values.record_ref
+            WHERE deleted_at IS NULL AND id = "#,
+            )
+            .push_bind(id.value())
             .build()
-            .execute(&mut *conn)
+            .fetch_all(&mut *conn)
             .await?;
 
-        Ok(())
+        Ok(group_rows(rows)
+            .into_iter()
+            .next()
+            .map(|stored| stored.record))
     }
 
-    async fn fetch_records(&self, select: &[ValueType]) -> Result<Vec<Record>, Box<dyn Error>> {
-        let mut conn = self.connection.lock().await;
-        let mut query = QueryBuilder::new(
-            r#"SELECT timestamp, source, value, value_type
-            FROM records, record_values 
-            WHERE records.record_ref = record_values.record_ref "#,
-        );
-        if !select.is_empty() {
-            query
-                .push(" AND value_type IN ")
-                .push_tuples(select, |mut b, value| {
-                    b.push_bind(*value as u16);
+    async fn fetch_records_by_id(&self, ids: &[RecordId]) -> Result<Vec<Record>, Box<dyn Error>> {
+        let mut conn = self.connection.read().await?;
+        let mut records = Vec::new();
+        // Identifiers are bound in chunks, as there can be more of them than
+        // SQLite allows parameters in a single query.
+        for chunk in ids.chunks(MAX_BOUND_IDS) {
+            let rows = QueryBuilder::new(SELECT_RECORDS)
+                .push(
+                    r#"NULL AS raw_data, value, value_type
+                FROM records LEFT JOIN record_values
+                    ON records.record_ref = record_values.record_ref
+                WHERE deleted_at IS NULL AND id IN "#,
+                )
+                .push_tuples(chunk, |mut b, id| {
+                    b.push_bind(id.value());
                 })
-        } else {
-            &mut query
+                .push(" ORDER BY id")
+                .build()
+                .fetch_all(&mut *conn)
+                .await?;
+            records.extend(group_rows(rows).into_iter().map(|stored| stored.record));
         }
-        .push(" ORDER BY timestamp DESC, source ")
-        .build()
-        .fetch_all(&mut *conn)
-        .await?
-        .iter()
-        .flat_map(|row| RecordRow::from_row(row).map_err(|e| error!("{}", e)).ok())
-        .group_by(|s| (s.timestamp, s.source.clone()))
-        .into_iter()
-        .map(
-            |((timestamp, source), values)| -> Result<_, Box<dyn Error>> {
-                Ok(Record::new(
-                    timestamp,
-                    values.into_iter().map(|r| r.value).collect(),
-                    Vec::new(),
-                    source.clone(),
-                ))
-            },
+        records.sort_by_key(|record| record.timestamp);
+
+        Ok(records)
+    }
+
+    async fn fetch_changes(
+        &self,
+        since: ChangeCursor,
+        limit: u32,
+    ) -> Result<Vec<StoredRecord>, Box<dyn Error>> {
+        let mut conn = self.connection.read().await?;
+        let rows = QueryBuilder::new(SELECT_RECORDS)
+            .push(
+                r#"NULL AS raw_data, value, value_type
+            FROM (
+                SELECT * FROM records
+                WHERE change_seq > "#,
+            )
+            .push_bind(since.value())
+            .push(" ORDER BY change_seq LIMIT ")
+            .push_bind(limit)
+            // Records without values are joined with none, rather than left
+            // out, so that pages are as long as requested.
+            .push(
+                r#") AS records LEFT JOIN record_values
+                ON records.record_ref = record_values.record_ref
+            ORDER BY change_seq"#,
+            )
+            .build()
+            .fetch_all(&mut *conn)
+            .await?;
+
+        Ok(group_rows(rows))
+    }
+
+    async fn update_record(
+        &self,
+        id: RecordId,
+        patch: &RecordPatch,
+    ) -> Result<bool, Box<dyn Error>> {
+        let mut conn = self.connection.write().await?;
+        let mut tx = conn.begin().await?;
+        let updated = apply_patch(&mut tx, id, patch).await?;
+        tx.commit().await?;
+
+        Ok(updated)
+    }
+
+    async fn update_records(
+        &self,
+        patches: &[(RecordId, RecordPatch)],
+    ) -> Result<usize, Box<dyn Error>> {
+        let mut conn = self.connection.write().await?;
+        let mut tx = conn.begin().await?;
+        let mut updated = 0;
+        for (id, patch) in patches {
+            if apply_patch(&mut tx, *id, patch).await? {
+                updated += 1;
+            }
+        }
+        tx.commit().await?;
+
+        Ok(updated)
+    }
+
+    async fn delete_record(&self, id: RecordId, at: DateTime<Utc>) -> Result<bool, Box<dyn Error>> {
+        let mut conn = self.connection.write().await?;
+        let result =
+            sqlx::query("UPDATE records SET deleted_at = ? WHERE id = ? AND deleted_at IS NULL")
+                .bind(at.timestamp())
+                .bind(id.value())
+                .execute(&mut *conn)
+                .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn restore_record(
+        &self,
+        id: RecordId,
+        deleted_since: DateTime<Utc>,
+    ) -> Result<bool, Box<dyn Error>> {
+        let mut conn = self.connection.write().await?;
+        let result =
+            sqlx::query("UPDATE records SET deleted_at = NULL WHERE id = ? AND deleted_at >= ?")
+                .bind(id.value())
+                .bind(deleted_since.timestamp())
+                .execute(&mut *conn)
+                .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use healthpi_model::{device::DeviceId, measurement::MealIndicator};
+
+    use super::*;
+    use crate::db::migration;
+
+    async fn repository() -> MeasurementRepositoryImpl {
+        let connection = Connection::establish("sqlite::memory:", true, 1)
+            .await
+            .unwrap();
+        migration::run(&connection).await.unwrap();
+        MeasurementRepositoryImpl::new(connection)
+    }
+
+    fn record(timestamp: &str, values: Vec<Value>) -> Record {
+        Record::new(
+            timestamp.parse().unwrap(),
+            values,
+            Vec::new(),
+            Source::Device(DeviceId::new("contour".into())),
         )
-        .collect()
+    }
+
+    #[actix_web::test]
+    async fn changes_include_records_without_values() {
+        let repository = repository().await;
+        repository
+            .store_records(vec![
+                record("2024-03-20T07:00:00+01:00", vec![Value::Glucose(90.0)]),
+                record("2024-03-20T08:00:00+01:00", Vec::new()),
+                record("2024-03-20T09:00:00+01:00", vec![Value::Glucose(110.0)]),
+            ])
+            .await
+            .unwrap();
+
+        let page = repository
+            .fetch_changes(ChangeCursor::START, 2)
+            .await
+            .unwrap();
+        // Values are stored after records, so the one without them changed first.
+        assert_eq!(page.len(), 2);
+        assert!(page[0].record.values.is_empty());
+
+        let rest = repository.fetch_changes(page[1].cursor, 2).await.unwrap();
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].record.values, vec![Value::Glucose(110.0)]);
+    }
+
+    #[actix_web::test]
+    async fn filters_by_local_dates() {
+        let repository = repository().await;
+        repository
+            .store_records(vec![
+                record("2024-03-20T23:30:00-05:00", vec![Value::Glucose(90.0)]),
+                record("2024-03-21T00:30:00+09:00", vec![Value::Glucose(100.0)]),
+                record("2024-03-22T00:00:00+01:00", vec![Value::Glucose(110.0)]),
+            ])
+            .await
+            .unwrap();
+        let date = "2024-03-21".parse().unwrap();
+
+        let records = repository
+            .fetch_records(
+                &RecordFilter::default().from_date(date).to_date(date),
+                false,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].values, vec![Value::Glucose(100.0)]);
+    }
+
+    #[actix_web::test]
+    async fn updates_records_together() {
+        let repository = repository().await;
+        repository
+            .store_records(vec![
+                record("2024-03-20T07:00:00+01:00", vec![Value::Weight(80.0)]),
+                record(
+                    "2024-03-21T07:00:00+01:00",
+                    vec![Value::Weight(81.0), Value::FatPercent(20.0)],
+                ),
+            ])
+            .await
+            .unwrap();
+        let ids: Vec<_> = repository
+            .fetch_records(&RecordFilter::default().order(Order::Ascending), false)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|record| record.id.unwrap())
+            .collect();
+
+        let updated = repository
+            .update_records(&[
+                (
+                    ids[0],
+                    RecordPatch::default().values(&[Value::BodyMassIndex(24.7)]),
+                ),
+                (
+                    ids[1],
+                    RecordPatch::default().remove(&[ValueType::FatPercent]),
+                ),
+                (
+                    RecordId::new(1000),
+                    RecordPatch::default().remove(&[ValueType::Weight]),
+                ),
+            ])
+            .await
+            .unwrap();
+
+        assert_eq!(updated, 2);
+        let records = repository
+            .fetch_records(&RecordFilter::default().order(Order::Ascending), false)
+            .await
+            .unwrap();
+        assert_eq!(
+            records[0].values,
+            vec![Value::Weight(80.0), Value::BodyMassIndex(24.7)]
+        );
+        assert_eq!(records[1].values, vec![Value::Weight(81.0)]);
+    }
+
+    #[actix_web::test]
+    async fn fetches_records_in_chunks() {
+        let repository = repository().await;
+        let mut manual = record("2024-03-20T07:00:00+01:00", vec![Value::Glucose(95.0)]);
+        manual.source = Source::Unknown("meter".into());
+        repository
+            .store_records(vec![
+                record(
+                    "2024-03-20T07:00:00+01:00",
+                    vec![Value::Glucose(90.0), Value::Meal(MealIndicator::BeforeMeal)],
+                ),
+                manual,
+                record("2024-03-20T08:00:00+01:00", vec![Value::Glucose(110.0)]),
+            ])
+            .await
+            .unwrap();
+        let filter = RecordFilter::default().order(Order::Ascending);
+
+        let mut values = Vec::new();
+        let mut after = None;
+        loop {
+            let chunk = repository
+                .fetch_records_after(&filter, after, 2)
+                .await
+                .unwrap();
+            values.extend(chunk.iter().map(|record| record.values.len()));
+            match chunk.last() {
+                Some(last) if chunk.len() == 2 => after = Some((last.timestamp, last.id.unwrap())),
+                _ => break,
+            }
+        }
+
+        assert_eq!(values, vec![2, 1, 1]);
+    }
+
+    #[actix_web::test]
+    async fn fetches_records_left_without_values() {
+        let repository = repository().await;
+        let ids = repository
+            .store_records(vec![record(
+                "2024-03-20T07:00:00+01:00",
+                vec![Value::Glucose(90.0), Value::Meal(MealIndicator::BeforeMeal)],
+            )])
+            .await
+            .unwrap();
+
+        let patch = RecordPatch::default().remove(&[ValueType::Glucose, ValueType::Meal]);
+        assert!(repository.update_record(ids[0], &patch).await.unwrap());
+
+        let record = repository.fetch_record(ids[0]).await.unwrap().unwrap();
+        assert!(record.values.is_empty());
+        assert_eq!(repository.fetch_records_by_id(&ids).await.unwrap().len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn corrects_offsets_of_records_an_offset_apart() {
+        let repository = repository().await;
+        // Stored before the offset was known, as if local time was UTC.
+        repository
+            .store_records(vec![
+                record("2024-03-20T07:00:00+00:00", vec![Value::Glucose(90.0)]),
+                record("2024-03-20T08:00:00+00:00", vec![Value::Glucose(110.0)]),
+            ])
+            .await
+            .unwrap();
+
+        // The later one moves to where the earlier one still is.
+        repository
+            .store_records(vec![
+                record("2024-03-20T08:00:00+01:00", vec![Value::Glucose(110.0)]),
+                record("2024-03-20T07:00:00+01:00", vec![Value::Glucose(90.0)]),
+            ])
+            .await
+            .unwrap();
+
+        let records = repository
+            .fetch_records(&RecordFilter::default().order(Order::Ascending), false)
+            .await
+            .unwrap();
+        let timestamps: Vec<_> = records.iter().map(|r| r.timestamp.to_rfc3339()).collect();
+        assert_eq!(
+            timestamps,
+            vec!["2024-03-20T07:00:00+01:00", "2024-03-20T08:00:00+01:00"]
+        );
     }
 }
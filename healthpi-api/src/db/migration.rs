@@ -0,0 +1,122 @@
+//! Migrations of the database schema, embedded in the binary.
+
+use std::error::Error;
+
+use sqlx::{
+    migrate::{Migrate, Migrator},
+    SqliteConnection,
+};
+
+use super::connection::Connection;
+
+static MIGRATOR: Migrator = sqlx::migrate!("../migrations");
+
+/// State of the database schema compared to migrations known to this binary.
+#[derive(Debug, PartialEq, Eq)]
+pub enum MigrationStatus {
+    UpToDate,
+    /// Migrations yet to be applied, as versions along with descriptions.
+    Pending(Vec<(i64, String)>),
+    /// Versions of applied migrations this binary does not know of, i.e. the
+    /// database has been migrated by a newer version of the API.
+    Newer(Vec<i64>),
+}
+
+async fn applied_versions(conn: &mut SqliteConnection) -> Result<Vec<i64>, Box<dyn Error>> {
+    // Checking the status should not create the table of migrations.
+    let migrated: bool = sqlx::query_scalar(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations'",
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    if !migrated {
+        return Ok(Vec::new());
+    }
+
+    Ok(conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|migration| migration.version)
+        .collect())
+}
+
+pub async fn status(connection: &Connection) -> Result<MigrationStatus, Box<dyn Error>> {
+    let mut conn = connection.read().await?;
+    let applied = applied_versions(&mut conn).await?;
+
+    let known: Vec<_> = MIGRATOR
+        .iter()
+        .filter(|migration| migration.migration_type.is_up_migration())
+        .collect();
+    let unknown: Vec<_> = applied
+        .iter()
+        .copied()
+        .filter(|version| !known.iter().any(|m| m.version == *version))
+        .collect();
+    if !unknown.is_empty() {
+        return Ok(MigrationStatus::Newer(unknown));
+    }
+
+    let pending: Vec<_> = known
+        .into_iter()
+        .filter(|migration| !applied.contains(&migration.version))
+        .map(|migration| (migration.version, migration.description.to_string()))
+        .collect();
+    if pending.is_empty() {
+        Ok(MigrationStatus::UpToDate)
+    } else {
+        Ok(MigrationStatus::Pending(pending))
+    }
+}
+
+/// Applies pending migrations. Fails if an applied migration differs from
+/// the one embedded in the binary.
+pub async fn run(connection: &Connection) -> Result<(), Box<dyn Error>> {
+    let mut conn = connection.write().await?;
+    MIGRATOR.run(&mut *conn).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn migrates_fresh_database() {
+        let connection = Connection::establish("sqlite::memory:", true, 1)
+            .await
+            .unwrap();
+        assert!(matches!(
+            status(&connection).await.unwrap(),
+            MigrationStatus::Pending(_)
+        ));
+
+        run(&connection).await.unwrap();
+
+        assert_eq!(
+            status(&connection).await.unwrap(),
+            MigrationStatus::UpToDate
+        );
+    }
+
+    #[actix_web::test]
+    async fn detects_database_migrated_by_newer_version() {
+        let connection = Connection::establish("sqlite::memory:", true, 1)
+            .await
+            .unwrap();
+        run(&connection).await.unwrap();
+        sqlx::query(
+            "INSERT INTO _sqlx_migrations(version, description, success, checksum, execution_time)
+            VALUES (99990101000000, 'future', TRUE, x'00', 0)",
+        )
+        .execute(&mut *connection.write().await.unwrap())
+        .await
+        .unwrap();
+
+        assert_eq!(
+            status(&connection).await.unwrap(),
+            MigrationStatus::Newer(vec![99990101000000])
+        );
+    }
+}
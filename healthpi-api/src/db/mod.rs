@@ -1,2 +1,6 @@
+pub(crate) mod alert;
 pub(crate) mod connection;
 pub(crate) mod measurement;
+pub(crate) mod migration;
+pub(crate) mod person;
+pub(crate) mod record_ref;
@@ -0,0 +1,190 @@
+use std::error::Error;
+
+use async_trait::async_trait;
+use healthpi_model::{
+    body_composition::FormulaSet,
+    person::{Person, PersonId, Sex},
+};
+use sqlx::{sqlite::SqliteRow, Connection as _, FromRow, Row};
+
+use super::{connection::Connection, measurement::DbError};
+
+fn sex_to_str(sex: Sex) -> &'static str {
+    match sex {
+        Sex::Female => "female",
+        Sex::Male => "male",
+    }
+}
+
+fn sex_from_str(s: &str) -> Option<Sex> {
+    match s {
+        "female" => Some(Sex::Female),
+        "male" => Some(Sex::Male),
+        _ => None,
+    }
+}
+
+fn formula_to_str(formula: FormulaSet) -> &'static str {
+    match formula {
+        FormulaSet::Soehnle => "soehnle",
+        FormulaSet::Anthropometric => "anthropometric",
+    }
+}
+
+fn formula_from_str(s: &str) -> Option<FormulaSet> {
+    match s {
+        "soehnle" => Some(FormulaSet::Soehnle),
+        "anthropometric" => Some(FormulaSet::Anthropometric),
+        _ => None,
+    }
+}
+
+pub struct PersonRow {
+    id: PersonId,
+    person: Person,
+}
+
+impl<'r> FromRow<'r, SqliteRow> for PersonRow {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            id: PersonId::new(row.try_get("id")?),
+            person: Person {
+                name: row.try_get("name")?,
+                birth_date: row.try_get::<&str, _>("birth_date")?.parse().map_err(|e| {
+                    sqlx::Error::ColumnDecode {
+                        index: "birth_date".into(),
+                        source: Box::new(e),
+                    }
+                })?,
+                sex: sex_from_str(row.try_get("sex")?).ok_or_else(|| {
+                    sqlx::Error::ColumnDecode {
+                        index: "sex".into(),
+                        source: Box::new(DbError::InvalidValue),
+                    }
+                })?,
+                height_cm: row.try_get("height_cm")?,
+                activity_level: row.try_get("activity_level")?,
+                formula: formula_from_str(row.try_get("formula")?).ok_or_else(|| {
+                    sqlx::Error::ColumnDecode {
+                        index: "formula".into(),
+                        source: Box::new(DbError::InvalidValue),
+                    }
+                })?,
+                goal_weight_kg: row.try_get("goal_weight_kg")?,
+            },
+        })
+    }
+}
+
+#[async_trait]
+pub trait PersonRepository: Send + Sync {
+    async fn create_person(&self, person: Person) -> Result<PersonId, Box<dyn Error>>;
+    async fn fetch_persons(&self) -> Result<Vec<(PersonId, Person)>, Box<dyn Error>>;
+    async fn fetch_person(&self, id: PersonId) -> Result<Option<Person>, Box<dyn Error>>;
+    async fn update_person(&self, id: PersonId, person: Person) -> Result<bool, Box<dyn Error>>;
+    async fn delete_person(&self, id: PersonId) -> Result<bool, Box<dyn Error>>;
+}
+
+#[derive(Clone)]
+pub struct PersonRepositoryImpl {
+    connection: Connection,
+}
+
+impl PersonRepositoryImpl {
+    pub fn new(connection: Connection) -> Self {
+        Self { connection }
+    }
+}
+
+#[async_trait]
+impl PersonRepository for PersonRepositoryImpl {
+    async fn create_person(&self, person: Person) -> Result<PersonId, Box<dyn Error>> {
+        let mut conn = self.connection.write().await?;
+        let id = sqlx::query(
+            "INSERT INTO persons(name, birth_date, sex, height_cm, activity_level, formula,
+                goal_weight_kg)
+            VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(person.name)
+        .bind(person.birth_date.to_string())
+        .bind(sex_to_str(person.sex))
+        .bind(person.height_cm)
+        .bind(person.activity_level)
+        .bind(formula_to_str(person.formula))
+        .bind(person.goal_weight_kg)
+        .execute(&mut *conn)
+        .await?
+        .last_insert_rowid();
+
+        Ok(PersonId::new(id))
+    }
+
+    async fn fetch_persons(&self) -> Result<Vec<(PersonId, Person)>, Box<dyn Error>> {
+        let mut conn = self.connection.read().await?;
+        Ok(
+            sqlx::query_as::<_, PersonRow>("SELECT * FROM persons ORDER BY id")
+                .fetch_all(&mut *conn)
+                .await?
+                .into_iter()
+                .map(|row| (row.id, row.person))
+                .collect(),
+        )
+    }
+
+    async fn fetch_person(&self, id: PersonId) -> Result<Option<Person>, Box<dyn Error>> {
+        let mut conn = self.connection.read().await?;
+        Ok(
+            sqlx::query_as::<_, PersonRow>("SELECT * FROM persons WHERE id = ?")
+                .bind(id.value())
+                .fetch_optional(&mut *conn)
+                .await?
+                .map(|row| row.person),
+        )
+    }
+
+    async fn update_person(&self, id: PersonId, person: Person) -> Result<bool, Box<dyn Error>> {
+        let mut conn = self.connection.write().await?;
+        let result = sqlx::query(
+            "UPDATE persons
+            SET name = ?, birth_date = ?, sex = ?, height_cm = ?, activity_level = ?,
+                formula = ?, goal_weight_kg = ?
+            WHERE id = ?",
+        )
+        .bind(person.name)
+        .bind(person.birth_date.to_string())
+        .bind(sex_to_str(person.sex))
+        .bind(person.height_cm)
+        .bind(person.activity_level)
+        .bind(formula_to_str(person.formula))
+        .bind(person.goal_weight_kg)
+        .bind(id.value())
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_person(&self, id: PersonId) -> Result<bool, Box<dyn Error>> {
+        let mut conn = self.connection.write().await?;
+        let mut tx = conn.begin().await?;
+        sqlx::query("UPDATE records SET person_id = NULL WHERE person_id = ?")
+            .bind(id.value())
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM alerts WHERE person_id = ?")
+            .bind(id.value())
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM alert_rules WHERE person_id = ?")
+            .bind(id.value())
+            .execute(&mut *tx)
+            .await?;
+        let result = sqlx::query("DELETE FROM persons WHERE id = ?")
+            .bind(id.value())
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
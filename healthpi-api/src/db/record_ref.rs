@@ -0,0 +1,105 @@
+//! Identification of records by their content.
+//!
+//! Records are deduplicated by `record_ref`, derived from the local time of the
+//! measurement and its source, so that the same reading uploaded twice ends up
+//! in a single record. Refs are persisted, so the way they are derived must not
+//! change. If it ever needs to, add a new version and keep the previous ones,
+//! so that stored refs can be rewritten on startup.
+//!
+//! Version 1 is the SHA-256 digest of the following fields, each encoded as
+//! a netstring (`<length in bytes>:<bytes>,`):
+//!
+//! 1. local time of the measurement, formatted as `%Y-%m-%dT%H:%M:%S`,
+//! 2. kind of the source: `device`, `manual`, `import` or `unknown`,
+//! 3. fields of the source in order of declaration: the device ID, the name of
+//!    the person who entered the record, the importer and file name, or the
+//!    description of an unknown source.
+//!
+//! Version 0 was an FxHash of the same data, which depended on implementation
+//! details of the standard library and chrono, and is no longer computed.
+
+use chrono::NaiveDateTime;
+use healthpi_model::measurement::Source;
+use sha2::{Digest, Sha256};
+
+pub const CURRENT_VERSION: i64 = 1;
+
+fn push_field(encoded: &mut Vec<u8>, field: &str) {
+    encoded.extend_from_slice(format!("{}:{},", field.len(), field).as_bytes());
+}
+
+fn canonical_encoding(local_time: NaiveDateTime, source: &Source) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    push_field(
+        &mut encoded,
+        &local_time.format("%Y-%m-%dT%H:%M:%S").to_string(),
+    );
+    match source {
+        Source::Device(device_id) => {
+            push_field(&mut encoded, "device");
+            push_field(&mut encoded, &device_id.to_string());
+        }
+        Source::Manual { entered_by } => {
+            push_field(&mut encoded, "manual");
+            push_field(&mut encoded, entered_by);
+        }
+        Source::Import { importer, file } => {
+            push_field(&mut encoded, "import");
+            push_field(&mut encoded, importer);
+            push_field(&mut encoded, file);
+        }
+        Source::Unknown(description) => {
+            push_field(&mut encoded, "unknown");
+            push_field(&mut encoded, description);
+        }
+    }
+    encoded
+}
+
+/// Derives the ref of a record with the current version of the scheme.
+pub fn record_ref(local_time: NaiveDateTime, source: &Source) -> Vec<u8> {
+    Sha256::digest(canonical_encoding(local_time, source)).to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use healthpi_model::device::DeviceId;
+
+    use super::*;
+
+    fn local_time() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2022, 8, 4)
+            .unwrap()
+            .and_hms_opt(13, 49, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn encodes_fields_as_netstrings() {
+        let source = Source::Import {
+            importer: "csv".into(),
+            file: "weight, 2022.csv".into(),
+        };
+
+        assert_eq!(
+            canonical_encoding(local_time(), &source),
+            b"19:2022-08-04T13:49:00,6:import,3:csv,16:weight, 2022.csv,".to_vec()
+        );
+    }
+
+    #[test]
+    fn refs_are_stable() {
+        // Changing this value means stored records will no longer be matched.
+        let source = Source::Device(DeviceId::new("12:34:56:78:9A:BC".into()));
+
+        assert_eq!(
+            hex(&record_ref(local_time(), &source)),
+            "64b552e08759e3e2b04b1e96003e0ed60fc1b7af251f850d63c0851d2c8b7b63"
+        );
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{b:02x}")).collect()
+    }
+}
@@ -0,0 +1,176 @@
+use std::fmt;
+
+use actix_web::{http::StatusCode, web, HttpResponse, ResponseError};
+use healthpi_model::{
+    problem::{Problem, ProblemCode, RecordProblem},
+    validation::Issue,
+};
+
+const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/// Errors reported to clients as problem responses. Failures on the side of
+/// the server are logged where they happen, rather than exposed.
+#[derive(Debug)]
+pub enum ApiError {
+    InvalidRequest(String),
+    NotFound(String),
+    /// Records refer to persons that do not exist.
+    UnknownPerson(Vec<RecordProblem>),
+    /// Values of a record are impossible.
+    InvalidValues(Vec<Issue>),
+    /// None of the submitted records were stored, as all of them have
+    /// impossible values.
+    RejectedRecords(Vec<RecordProblem>),
+    Internal,
+}
+
+impl ApiError {
+    pub fn problem(&self) -> Problem {
+        match self {
+            ApiError::InvalidRequest(message) => {
+                Problem::new(ProblemCode::InvalidRequest, message.clone())
+            }
+            ApiError::NotFound(message) => Problem::new(ProblemCode::NotFound, message.clone()),
+            ApiError::UnknownPerson(records) => Problem {
+                records: records.clone(),
+                ..Problem::new(
+                    ProblemCode::UnknownPerson,
+                    "Records refer to persons that do not exist",
+                )
+            },
+            ApiError::InvalidValues(issues) => Problem {
+                records: vec![RecordProblem {
+                    index: None,
+                    message: "Some values are impossible".into(),
+                    issues: issues.clone(),
+                }],
+                ..Problem::new(ProblemCode::InvalidValues, "Record has impossible values")
+            },
+            ApiError::RejectedRecords(records) => Problem {
+                records: records.clone(),
+                ..Problem::new(ProblemCode::InvalidValues, "No records could be stored")
+            },
+            ApiError::Internal => Problem::new(ProblemCode::Internal, "Internal server error"),
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.problem())
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::InvalidRequest(_)
+            | ApiError::UnknownPerson(_)
+            | ApiError::InvalidValues(_) => StatusCode::BAD_REQUEST,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::RejectedRecords(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code())
+            .content_type(PROBLEM_CONTENT_TYPE)
+            .json(self.problem())
+    }
+}
+
+async fn unknown_endpoint() -> HttpResponse {
+    ApiError::NotFound("No such endpoint".into()).error_response()
+}
+
+/// Reports requests that cannot be parsed, and ones to unknown endpoints,
+/// as problems too.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.app_data(web::JsonConfig::default().error_handler(|e, _| {
+        ApiError::InvalidRequest(format!("Invalid request body: {e}")).into()
+    }))
+    .app_data(
+        web::QueryConfig::default()
+            .error_handler(|e, _| ApiError::InvalidRequest(format!("Invalid query: {e}")).into()),
+    )
+    .app_data(
+        web::PathConfig::default()
+            .error_handler(|e, _| ApiError::InvalidRequest(format!("Invalid path: {e}")).into()),
+    )
+    .default_service(web::to(unknown_endpoint));
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        get,
+        test::{call_service, init_service, read_body_json, TestRequest},
+        App,
+    };
+    use healthpi_model::{measurement::ValueType, validation::Plausibility};
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct LimitQuery {
+        #[allow(dead_code)]
+        limit: u32,
+    }
+
+    #[get("/limited")]
+    async fn limited(_query: web::Query<LimitQuery>) -> HttpResponse {
+        HttpResponse::Ok().finish()
+    }
+
+    #[actix_web::test]
+    async fn reports_malformed_requests_as_problems() {
+        let app = init_service(App::new().configure(configure).service(limited)).await;
+
+        let response = call_service(
+            &app,
+            TestRequest::get().uri("/limited?limit=-1").to_request(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            PROBLEM_CONTENT_TYPE
+        );
+        let problem: Problem = read_body_json(response).await;
+        assert_eq!(problem.code, ProblemCode::InvalidRequest);
+
+        let response = call_service(&app, TestRequest::get().uri("/unknown").to_request()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let problem: Problem = read_body_json(response).await;
+        assert_eq!(problem.code, ProblemCode::NotFound);
+    }
+
+    #[test]
+    fn keeps_issues_of_invalid_values() {
+        let issues = vec![Issue {
+            value_type: ValueType::Glucose,
+            value: -1.0,
+            plausibility: Plausibility::Impossible,
+        }];
+
+        let problem = ApiError::InvalidValues(issues.clone()).problem();
+
+        assert_eq!(problem.code, ProblemCode::InvalidValues);
+        assert_eq!(problem.records[0].issues, issues);
+    }
+
+    #[test]
+    fn reports_rejected_records_as_unprocessable() {
+        let error = ApiError::RejectedRecords(vec![RecordProblem {
+            index: Some(1),
+            message: "Some values are impossible".into(),
+            issues: Vec::new(),
+        }]);
+
+        assert_eq!(error.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(error.problem().code, ProblemCode::InvalidValues);
+        assert_eq!(error.problem().records[0].index, Some(1));
+    }
+}
@@ -0,0 +1,288 @@
+use std::error::Error;
+
+use actix_web::{get, http::header, web, HttpResponse, Responder, ResponseError};
+use chrono::{DateTime, FixedOffset};
+use chrono_tz::Tz;
+use futures_util::{future, stream, StreamExt};
+use healthpi_model::{
+    device::DeviceId,
+    filter::{Order, RecordFilter},
+    measurement::{Record, Source, SourceKind, Value, ValueType},
+    person::PersonId,
+    units::UnitSystem,
+};
+use log::error;
+use serde::Deserialize;
+
+use crate::{
+    db::measurement::{MeasurementRepository, MeasurementRepositoryImpl},
+    error::ApiError,
+};
+
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+const OFFSET_FORMAT: &str = "UTC%:z";
+
+/// How many records are fetched at a time while the export is written out.
+const CHUNK_SIZE: u32 = 1000;
+
+#[derive(Debug, Deserialize)]
+struct ExportQuery {
+    /// Types of values to export, in order of their columns. Types present
+    /// in the exported records are exported if empty.
+    #[serde(default)]
+    #[serde(deserialize_with = "crate::comma_separated")]
+    select: Vec<ValueType>,
+    /// Earliest time of measurement, inclusive.
+    from: Option<DateTime<FixedOffset>>,
+    /// Latest time of measurement, exclusive.
+    to: Option<DateTime<FixedOffset>>,
+    #[serde(default)]
+    #[serde(deserialize_with = "crate::comma_separated")]
+    source: Vec<SourceKind>,
+    #[serde(default)]
+    #[serde(deserialize_with = "crate::comma_separated")]
+    device: Vec<DeviceId>,
+    #[serde(default)]
+    order: Order,
+    #[serde(default)]
+    units: UnitSystem,
+    person: Option<PersonId>,
+    /// Name of the timezone to express times of measurement in. They are
+    /// left in local time of the device that took them if not given.
+    tz: Option<String>,
+}
+
+impl ExportQuery {
+    fn filter(&self) -> RecordFilter {
+        RecordFilter {
+            select: self.select.clone(),
+            from: self.from,
+            to: self.to,
+            sources: self.source.clone(),
+            devices: self.device.clone(),
+            person: self.person,
+            order: self.order,
+            ..Default::default()
+        }
+    }
+}
+
+/// Quotes a field if it contains characters with special meaning in CSV.
+/// Text that spreadsheets would take for a formula is prefixed with an
+/// apostrophe, so that it is shown as it is rather than evaluated.
+fn csv_field(field: &str) -> String {
+    let field = if field.starts_with(['=', '+', '-', '@']) && field.parse::<f64>().is_err() {
+        format!("'{field}")
+    } else {
+        field.to_owned()
+    };
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+fn csv_line(fields: impl IntoIterator<Item = String>) -> String {
+    let mut line = fields
+        .into_iter()
+        .map(|field| csv_field(&field))
+        .collect::<Vec<_>>()
+        .join(",");
+    line.push_str("\r\n");
+    line
+}
+
+fn source_label(source: &Source) -> String {
+    match source {
+        Source::Device(device) => device.to_string(),
+        Source::Manual { entered_by } => format!("manual ({entered_by})"),
+        Source::Import { importer, .. } => format!("import ({importer})"),
+        Source::Unknown(source) => source.clone(),
+    }
+}
+
+fn value_label(value: &Value) -> String {
+    match value {
+        Value::Meal(meal) => format!("{meal:?}"),
+        value => value.as_f64().to_string(),
+    }
+}
+
+fn header(columns: &[ValueType], units: UnitSystem) -> String {
+    csv_line(
+        ["id", "timestamp", "offset", "source", "person"]
+            .into_iter()
+            .map(String::from)
+            .chain(
+                columns
+                    .iter()
+                    .map(|value_type| match units.unit_symbol(*value_type) {
+                        Some(unit) => format!("{value_type:?} ({unit})"),
+                        None => format!("{value_type:?}"),
+                    }),
+            ),
+    )
+}
+
+fn row(record: &Record, columns: &[ValueType], units: UnitSystem, timezone: Option<Tz>) -> String {
+    let timestamp = match timezone {
+        Some(timezone) => record.timestamp.with_timezone(&timezone).fixed_offset(),
+        None => record.timestamp,
+    };
+    csv_line(
+        [
+            record.id.map(|id| id.to_string()).unwrap_or_default(),
+            timestamp.format(TIMESTAMP_FORMAT).to_string(),
+            timestamp.format(OFFSET_FORMAT).to_string(),
+            source_label(&record.source),
+            record.person.map(|p| p.to_string()).unwrap_or_default(),
+        ]
+        .into_iter()
+        .chain(columns.iter().map(|value_type| {
+            record
+                .values
+                .iter()
+                .find(|v| v.value_type() == *value_type)
+                .map(|v| value_label(&units.canonical_to(v.clone())))
+                .unwrap_or_default()
+        })),
+    )
+}
+
+/// Exports records as CSV, with one row per record and one column per type
+/// of value. Accepts the same filters as `GET /`.
+#[get("/export.csv")]
+async fn export_csv(
+    measurement_repository: web::Data<MeasurementRepositoryImpl>,
+    query: web::Query<ExportQuery>,
+) -> impl Responder {
+    let timezone = match &query.tz {
+        Some(tz) => match tz.parse::<Tz>() {
+            Ok(timezone) => Some(timezone),
+            Err(_) => {
+                return ApiError::InvalidRequest(format!("Invalid timezone: {tz}")).error_response()
+            }
+        },
+        None => None,
+    };
+    let filter = query.filter();
+    let columns = if query.select.is_empty() {
+        match measurement_repository.fetch_value_types(&filter).await {
+            Ok(present) => ValueType::ALL
+                .iter()
+                .copied()
+                .filter(|value_type| present.contains(value_type))
+                .collect(),
+            Err(e) => {
+                error!("Failed to fetch types of values: {e}");
+                return ApiError::Internal.error_response();
+            }
+        }
+    } else {
+        query.select.clone()
+    };
+    let units = query.units;
+
+    // Records are fetched a chunk at a time, as the response is written out,
+    // each chunk following the last record of the previous one.
+    let header = header(&columns, units);
+    let repository = measurement_repository.get_ref().clone();
+    let rows = stream::try_unfold(Some(None), move |after| {
+        let (repository, filter, columns) = (repository.clone(), filter.clone(), columns.clone());
+        async move {
+            let Some(after) = after else {
+                return Ok(None);
+            };
+            let records = repository
+                .fetch_records_after(&filter, after, CHUNK_SIZE)
+                .await
+                .inspect_err(|e| error!("Failed to fetch records: {e}"))?;
+            let next = match records.last() {
+                Some(Record {
+                    timestamp,
+                    id: Some(id),
+                    ..
+                }) if records.len() == CHUNK_SIZE as usize => Some(Some((*timestamp, *id))),
+                _ => None,
+            };
+            let rows: String = records
+                .iter()
+                .map(|record| row(record, &columns, units, timezone))
+                .collect();
+            Ok(Some((web::Bytes::from(rows), next)))
+        }
+    });
+    HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"healthpi.csv\"",
+        ))
+        .streaming(
+            stream::once(future::ok::<_, Box<dyn Error>>(web::Bytes::from(header))).chain(rows),
+        )
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(export_csv);
+}
+
+#[cfg(test)]
+mod tests {
+    use healthpi_model::{
+        measurement::{MealIndicator, RecordId},
+        units::GlucoseUnit,
+    };
+
+    use super::*;
+
+    #[test]
+    fn keeps_formulas_from_being_evaluated() {
+        assert_eq!(
+            csv_line(["=1+1".into(), "@SUM(A1)".into(), "-2.5".into()]),
+            "'=1+1,'@SUM(A1),-2.5\r\n"
+        );
+    }
+
+    #[test]
+    fn quotes_fields_with_special_characters() {
+        assert_eq!(
+            csv_line(["plain".into(), "a,b".into(), "say \"hi\"".into()]),
+            "plain,\"a,b\",\"say \"\"hi\"\"\"\r\n"
+        );
+    }
+
+    #[test]
+    fn writes_one_column_per_value_type() {
+        let mut record = Record::new(
+            "2024-03-20T07:30:00+01:00".parse().unwrap(),
+            vec![
+                Value::Meal(MealIndicator::BeforeMeal),
+                Value::Glucose(18.0156),
+            ],
+            Vec::new(),
+            Source::Device(DeviceId::new("contour".into())),
+        );
+        record.id = Some(RecordId::new(7));
+        let columns = [ValueType::Glucose, ValueType::Meal, ValueType::Weight];
+        let units = UnitSystem {
+            glucose: GlucoseUnit::MillimolesPerLitre,
+            ..UnitSystem::canonical()
+        };
+
+        assert_eq!(
+            header(&columns, units),
+            "id,timestamp,offset,source,person,Glucose (mmol/L),Meal,Weight (kg)\r\n"
+        );
+        assert_eq!(
+            row(&record, &columns, units, None),
+            "7,2024-03-20 07:30:00,UTC+01:00,contour,,1,BeforeMeal,\r\n"
+        );
+        assert_eq!(
+            row(&record, &columns, units, Some(Tz::UTC)),
+            "7,2024-03-20 06:30:00,UTC+00:00,contour,,1,BeforeMeal,\r\n"
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use actix_web::{get, web, HttpResponse, Responder, ResponseError};
+use chrono::{DateTime, FixedOffset};
+use healthpi_model::{
+    device::DeviceId,
+    filter::RecordFilter,
+    glucose::{self, GlucoseReading, GlucoseSummary, GlucoseThresholds},
+    measurement::{SourceKind, ValueType},
+    person::PersonId,
+    units::{Unit, UnitSystem},
+};
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db::measurement::{MeasurementRepository, MeasurementRepositoryImpl},
+    error::ApiError,
+};
+
+#[derive(Debug, Deserialize)]
+struct GlucoseQuery {
+    /// Earliest time of measurement, inclusive.
+    from: Option<DateTime<FixedOffset>>,
+    /// Latest time of measurement, exclusive.
+    to: Option<DateTime<FixedOffset>>,
+    #[serde(default)]
+    #[serde(deserialize_with = "crate::comma_separated")]
+    source: Vec<SourceKind>,
+    #[serde(default)]
+    #[serde(deserialize_with = "crate::comma_separated")]
+    device: Vec<DeviceId>,
+    person: Option<PersonId>,
+    #[serde(default)]
+    units: UnitSystem,
+    /// Thresholds in the requested units, overriding the default ones.
+    very_low: Option<f64>,
+    low: Option<f64>,
+    high: Option<f64>,
+    very_high: Option<f64>,
+}
+
+impl GlucoseQuery {
+    fn filter(&self) -> RecordFilter {
+        RecordFilter {
+            select: vec![ValueType::Glucose, ValueType::Meal],
+            from: self.from,
+            to: self.to,
+            sources: self.source.clone(),
+            devices: self.device.clone(),
+            person: self.person,
+            ..Default::default()
+        }
+    }
+
+    /// Thresholds in canonical units.
+    fn thresholds(&self) -> GlucoseThresholds {
+        let defaults = GlucoseThresholds::default();
+        let threshold =
+            |x: Option<f64>, default| x.map_or(default, |x| self.units.glucose.to_canonical(x));
+        GlucoseThresholds {
+            very_low: threshold(self.very_low, defaults.very_low),
+            low: threshold(self.low, defaults.low),
+            high: threshold(self.high, defaults.high),
+            very_high: threshold(self.very_high, defaults.very_high),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct GlucoseResponse {
+    thresholds: GlucoseThresholds,
+    /// Null if there are no readings in the range.
+    summary: Option<GlucoseSummary>,
+}
+
+impl GlucoseResponse {
+    fn canonical_to(self, units: UnitSystem) -> Self {
+        let convert = |x| units.glucose.canonical_to(x);
+        Self {
+            thresholds: GlucoseThresholds {
+                very_low: convert(self.thresholds.very_low),
+                low: convert(self.thresholds.low),
+                high: convert(self.thresholds.high),
+                very_high: convert(self.thresholds.very_high),
+            },
+            summary: self.summary.map(|mut summary| {
+                summary.mean = convert(summary.mean);
+                summary.standard_deviation = convert(summary.standard_deviation);
+                for meal in &mut summary.by_meal {
+                    meal.mean = convert(meal.mean);
+                }
+                summary
+            }),
+        }
+    }
+}
+
+/// Summarizes glucose readings: time in range, estimated HbA1c, variability,
+/// and readings before and after meals.
+#[get("/glucose")]
+async fn glucose_summary(
+    measurement_repository: web::Data<MeasurementRepositoryImpl>,
+    query: web::Query<GlucoseQuery>,
+) -> impl Responder {
+    let thresholds = query.thresholds();
+    if !(thresholds.very_low <= thresholds.low
+        && thresholds.low <= thresholds.high
+        && thresholds.high <= thresholds.very_high)
+    {
+        return ApiError::InvalidRequest("Thresholds must be in ascending order".into())
+            .error_response();
+    }
+
+    let records = match measurement_repository
+        .fetch_records(&query.filter(), false)
+        .await
+    {
+        Ok(records) => records,
+        Err(e) => {
+            error!("Failed to fetch glucose readings: {e}");
+            return ApiError::Internal.error_response();
+        }
+    };
+    let readings: Vec<_> = records
+        .iter()
+        .filter_map(GlucoseReading::from_record)
+        .collect();
+
+    HttpResponse::Ok().json(
+        GlucoseResponse {
+            summary: glucose::summarize(&readings, &thresholds),
+            thresholds,
+        }
+        .canonical_to(query.units),
+    )
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(glucose_summary);
+}
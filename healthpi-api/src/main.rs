@@ -1,33 +1,166 @@
+mod aggregates;
+mod alerts;
+mod blood_pressure;
+mod config;
 mod db;
+mod error;
+mod export;
+mod glucose;
+mod persons;
+mod records;
+mod report;
+mod weight_trend;
 
-use std::str::FromStr;
+use std::{env, error::Error, fmt, process::ExitCode, str::FromStr};
 
 use actix_cors::Cors;
-use actix_web::{get, post, web, App, HttpResponse, HttpServer, Responder};
-use healthpi_model::measurement::{Record, ValueType};
-use log::{error, info};
-use serde::{de, Deserialize};
-
-use crate::db::{
-    connection::Connection,
-    measurement::{MeasurementRepository, MeasurementRepositoryImpl},
+use actix_web::{
+    get, http::Uri, post, web, App, HttpResponse, HttpServer, Responder, ResponseError,
+};
+use chrono::{DateTime, FixedOffset, Utc};
+use dotenv::dotenv;
+use healthpi_model::{
+    changes::ChangeCursor,
+    device::DeviceId,
+    filter::{Order, RecordFilter},
+    measurement::{Record, RecordId, SourceKind, Value, ValueType},
+    person::PersonId,
+    problem::RecordProblem,
+    units::UnitSystem,
+    validation::{self, RecordReport, RecordStatus},
+};
+use itertools::Itertools;
+use log::{error, info, warn};
+use serde::{de, Deserialize, Serialize};
+
+use crate::{
+    alerts::Alerter,
+    config::{Command, Config, USAGE},
+    db::{
+        alert::AlertRepositoryImpl,
+        connection::Connection,
+        measurement::{MeasurementRepository, MeasurementRepositoryImpl},
+        migration::{self, MigrationStatus},
+        person::{PersonRepository, PersonRepositoryImpl},
+    },
+    error::ApiError,
 };
 
-fn comma_separated_value_types<'de, D>(deserializer: D) -> Result<Vec<ValueType>, D::Error>
+fn comma_separated<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
 where
     D: de::Deserializer<'de>,
+    T: FromStr,
+    T::Err: fmt::Display,
 {
-    let s: &str = Deserialize::deserialize(deserializer)?;
+    let s: String = Deserialize::deserialize(deserializer)?;
     s.split(',')
-        .map(|s| ValueType::from_str(s).map_err(|e| de::Error::custom(e.to_string())))
+        .map(|s| T::from_str(s).map_err(|e| de::Error::custom(e.to_string())))
         .collect()
 }
 
 #[derive(Debug, Deserialize)]
 struct Query {
     #[serde(default)]
-    #[serde(deserialize_with = "comma_separated_value_types")]
+    #[serde(deserialize_with = "comma_separated")]
     select: Vec<ValueType>,
+    /// Earliest time of measurement, inclusive.
+    from: Option<DateTime<FixedOffset>>,
+    /// Latest time of measurement, exclusive.
+    to: Option<DateTime<FixedOffset>>,
+    /// Kinds of sources to include records from. All are included if empty.
+    #[serde(default)]
+    #[serde(deserialize_with = "comma_separated")]
+    source: Vec<SourceKind>,
+    /// Devices to include records from. All are included if empty.
+    #[serde(default)]
+    #[serde(deserialize_with = "comma_separated")]
+    device: Vec<DeviceId>,
+    #[serde(default)]
+    order: Order,
+    /// Whether to include raw data of records, as received from devices.
+    #[serde(default)]
+    raw: bool,
+    #[serde(default)]
+    units: UnitSystem,
+    person: Option<PersonId>,
+}
+
+impl Query {
+    fn filter(&self) -> RecordFilter {
+        RecordFilter {
+            select: self.select.clone(),
+            from: self.from,
+            to: self.to,
+            sources: self.source.clone(),
+            devices: self.device.clone(),
+            person: self.person,
+            order: self.order,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PostQuery {
+    #[serde(default)]
+    units: UnitSystem,
+    /// Person to attribute submitted records to, unless they specify one themselves.
+    person: Option<PersonId>,
+}
+
+/// A record as returned by the API. Its timestamp is in device local time,
+/// so it is accompanied by the same instant in UTC for convenience.
+#[derive(Debug, Serialize)]
+struct RecordResponse {
+    #[serde(flatten)]
+    record: Record,
+    timestamp_utc: DateTime<Utc>,
+}
+
+impl From<Record> for RecordResponse {
+    fn from(record: Record) -> Self {
+        Self {
+            timestamp_utc: record.timestamp.to_utc(),
+            record,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ChangesQuery {
+    #[serde(default)]
+    since: ChangeCursor,
+    /// Most records to return, up to `MAX_CHANGES_LIMIT`.
+    #[serde(default = "default_changes_limit")]
+    limit: u32,
+    #[serde(default)]
+    units: UnitSystem,
+}
+
+fn default_changes_limit() -> u32 {
+    500
+}
+
+/// Most records a single page of changes can hold.
+const MAX_CHANGES_LIMIT: u32 = 1000;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ChangesResponse {
+    records: Vec<RecordResponse>,
+    deleted: Vec<RecordId>,
+    next_cursor: ChangeCursor,
+    has_more: bool,
+}
+
+fn convert_records(records: Vec<Record>, convert: impl Fn(Value) -> Value) -> Vec<Record> {
+    records
+        .into_iter()
+        .map(|mut record| {
+            record.values = record.values.into_iter().map(&convert).collect();
+            record
+        })
+        .collect()
 }
 
 #[get("/")]
@@ -35,48 +168,333 @@ async fn index(
     measurement_repository: web::Data<MeasurementRepositoryImpl>,
     query: web::Query<Query>,
 ) -> impl Responder {
-    web::Json(
-        measurement_repository
-            .fetch_records(&query.select)
-            .await
-            .unwrap(),
+    let records = match measurement_repository
+        .fetch_records(&query.filter(), query.raw)
+        .await
+    {
+        Ok(records) => records,
+        Err(e) => {
+            error!("Failed to fetch records: {e}");
+            return ApiError::Internal.error_response();
+        }
+    };
+    HttpResponse::Ok().json(
+        convert_records(records, |value| query.units.canonical_to(value))
+            .into_iter()
+            .map(RecordResponse::from)
+            .collect::<Vec<_>>(),
     )
 }
 
+/// Lists records changed since given cursor, in order of their changes,
+/// and identifiers of records deleted since then.
+#[get("/changes")]
+async fn changes(
+    measurement_repository: web::Data<MeasurementRepositoryImpl>,
+    query: web::Query<ChangesQuery>,
+) -> impl Responder {
+    if query.limit == 0 || query.limit > MAX_CHANGES_LIMIT {
+        return ApiError::InvalidRequest(format!(
+            "Limit must be between 1 and {MAX_CHANGES_LIMIT}"
+        ))
+        .error_response();
+    }
+    // One more record than requested tells whether there are more to fetch.
+    let mut changes = match measurement_repository
+        .fetch_changes(query.since, query.limit + 1)
+        .await
+    {
+        Ok(changes) => changes,
+        Err(e) => {
+            error!("Failed to fetch changes: {e}");
+            return ApiError::Internal.error_response();
+        }
+    };
+    let has_more = changes.len() > query.limit as usize;
+    changes.truncate(query.limit as usize);
+
+    let next_cursor = changes.last().map_or(query.since, |stored| stored.cursor);
+    let (deleted, records): (Vec<_>, Vec<_>) =
+        changes.into_iter().partition(|stored| stored.deleted);
+    HttpResponse::Ok().json(ChangesResponse {
+        records: convert_records(
+            records.into_iter().map(|stored| stored.record).collect(),
+            |value| query.units.canonical_to(value),
+        )
+        .into_iter()
+        .map(RecordResponse::from)
+        .collect(),
+        deleted: deleted
+            .into_iter()
+            .flat_map(|stored| stored.record.id)
+            .collect(),
+        next_cursor,
+        has_more,
+    })
+}
+
+/// Stores records, and reports on each of them. Records with impossible values
+/// are left out, and if that leaves none, the whole request is rejected.
 #[post("/")]
 async fn post_measurements(
     measurement_repository: web::Data<MeasurementRepositoryImpl>,
+    person_repository: web::Data<PersonRepositoryImpl>,
+    alerter: web::Data<Alerter>,
+    query: web::Query<PostQuery>,
     measurements: web::Json<Vec<Record>>,
 ) -> impl Responder {
-    match measurement_repository.store_records(measurements.0).await {
-        Ok(_) => {
+    let mut records = convert_records(measurements.0, |value| query.units.to_canonical(value));
+    for record in records.iter_mut().filter(|r| r.person.is_none()) {
+        record.person = query.person;
+    }
+    let mut unknown_persons = Vec::new();
+    for person in records.iter().flat_map(|r| r.person).unique() {
+        match person_repository.fetch_person(person).await {
+            Ok(Some(_)) => {}
+            Ok(None) => unknown_persons.push(person),
+            Err(e) => {
+                error!("Failed to fetch person {person}: {e}");
+                return ApiError::Internal.error_response();
+            }
+        }
+    }
+    if !unknown_persons.is_empty() {
+        return ApiError::UnknownPerson(
+            records
+                .iter()
+                .enumerate()
+                .filter_map(|(i, record)| {
+                    let person = record.person.filter(|p| unknown_persons.contains(p))?;
+                    Some(RecordProblem {
+                        index: Some(i),
+                        message: format!("Person {person} does not exist"),
+                        issues: Vec::new(),
+                    })
+                })
+                .collect(),
+        )
+        .error_response();
+    }
+
+    let (reports, records): (Vec<_>, Vec<_>) = records
+        .into_iter()
+        .enumerate()
+        .map(|(i, record)| {
+            (
+                RecordReport::new(i, validation::check_record(&record)),
+                record,
+            )
+        })
+        .unzip();
+    if !reports.is_empty() && reports.iter().all(RecordReport::is_rejected) {
+        return ApiError::RejectedRecords(
+            reports
+                .into_iter()
+                .map(|report| RecordProblem {
+                    index: Some(report.index),
+                    message: "Some values are impossible".into(),
+                    issues: report.issues,
+                })
+                .collect(),
+        )
+        .error_response();
+    }
+    let records: Vec<_> = records
+        .into_iter()
+        .zip(&reports)
+        .filter(|(_, report)| !report.is_rejected())
+        .map(|(record, _)| record)
+        .collect();
+    for report in reports.iter().filter(|r| r.status != RecordStatus::Stored) {
+        warn!(
+            "Record #{} {:?}: {:?}",
+            report.index, report.status, report.issues
+        );
+    }
+
+    match measurement_repository.store_records(records).await {
+        Ok(ids) => {
             info!("Successfully stored records");
-            HttpResponse::Created().json(())
+            alerter.records_changed(&ids).await;
+            HttpResponse::Created().json(reports)
         }
         Err(e) => {
             error!("Failed to store records: {e}");
-            HttpResponse::InternalServerError().json(())
+            ApiError::Internal.error_response()
         }
     }
 }
 
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    log4rs::init_file("log4rs.yml", Default::default()).unwrap();
+fn cors(origins: &[String]) -> Cors {
+    if origins.is_empty() || origins.iter().any(|origin| origin == "*") {
+        return Cors::permissive();
+    }
+    origins
+        .iter()
+        .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+        .allow_any_method()
+        .allow_any_header()
+}
+
+async fn connect(config: &Config, create_if_missing: bool) -> Result<Connection, Box<dyn Error>> {
+    info!("Connecting to database {}", config.database);
+    Connection::establish(
+        &config.database_url(),
+        create_if_missing,
+        config.read_connections,
+    )
+    .await
+    .map_err(|e| format!("Failed to connect to database {}: {e}", config.database).into())
+}
+
+fn newer_schema_error(versions: &[i64]) -> Box<dyn Error> {
+    format!(
+        "Database has been migrated by a newer version of healthpi-api, \
+        with migrations {}. Upgrade healthpi-api to use it.",
+        versions.iter().join(", ")
+    )
+    .into()
+}
+
+/// Applies pending migrations, unless the database has been migrated
+/// by a newer version.
+async fn migrate(conn: &Connection) -> Result<(), Box<dyn Error>> {
+    match migration::status(conn).await? {
+        MigrationStatus::UpToDate => info!("Database schema is up to date"),
+        MigrationStatus::Pending(pending) => {
+            for (version, description) in &pending {
+                info!("Applying migration {version} {description}");
+            }
+            migration::run(conn)
+                .await
+                .map_err(|e| format!("Failed to migrate database: {e}"))?;
+            info!("Applied {} migrations", pending.len());
+        }
+        MigrationStatus::Newer(versions) => return Err(newer_schema_error(&versions)),
+    }
+    Ok(())
+}
+
+/// Reports migrations pending, failing if there are any.
+async fn check_migrations(conn: &Connection) -> Result<(), Box<dyn Error>> {
+    match migration::status(conn).await? {
+        MigrationStatus::UpToDate => {
+            info!("Database schema is up to date");
+            Ok(())
+        }
+        MigrationStatus::Pending(pending) => {
+            for (version, description) in &pending {
+                warn!("Migration {version} {description} is pending");
+            }
+            Err(format!("{} migrations are pending", pending.len()).into())
+        }
+        MigrationStatus::Newer(versions) => Err(newer_schema_error(&versions)),
+    }
+}
+
+async fn serve(config: Config) -> Result<(), Box<dyn Error>> {
+    for origin in config.cors_origins.iter().filter(|origin| *origin != "*") {
+        if origin.parse::<Uri>().is_err() {
+            return Err(format!("Invalid CORS origin: {origin}").into());
+        }
+    }
 
-    info!("Connecting to database");
-    let conn = Connection::establish().await.unwrap();
+    let conn = connect(&config, true).await?;
+    migrate(&conn).await?;
     let measurement_repository = MeasurementRepositoryImpl::new(conn.clone());
+    let person_repository = PersonRepositoryImpl::new(conn.clone());
+    let alert_repository = AlertRepositoryImpl::new(conn.clone());
+    match measurement_repository.upgrade_record_refs().await {
+        Ok(0) => {}
+        Ok(count) => info!("Rewrote refs of {count} records"),
+        Err(e) => return Err(format!("Failed to rewrite record refs: {e}").into()),
+    }
+
+    let alerter = Alerter::new(alert_repository.clone(), measurement_repository.clone());
+    match alerter.resume_deliveries().await {
+        Ok(0) => {}
+        Ok(count) => info!("Resumed delivery of {count} alerts"),
+        Err(e) => error!("Failed to resume delivery of alerts: {e}"),
+    }
+    alerter.spawn_missed_measurement_checks();
 
-    HttpServer::new(move || {
-        let cors = Cors::permissive();
+    let cors_origins = config.cors_origins.clone();
+    let server = HttpServer::new(move || {
         App::new()
-            .wrap(cors)
+            .wrap(cors(&cors_origins))
             .app_data(web::Data::new(measurement_repository.clone()))
+            .app_data(web::Data::new(person_repository.clone()))
+            .app_data(web::Data::new(alert_repository.clone()))
+            .app_data(web::Data::new(alerter.clone()))
+            .configure(error::configure)
             .service(index)
+            .service(changes)
             .service(post_measurements)
+            .configure(aggregates::configure)
+            .configure(alerts::configure)
+            .configure(blood_pressure::configure)
+            .configure(export::configure)
+            .configure(glucose::configure)
+            .configure(persons::configure)
+            .configure(records::configure)
+            .configure(report::configure)
+            .configure(weight_trend::configure)
     })
-    .bind(("127.0.0.1", 8080))?
-    .run()
-    .await
+    .bind((config.address.as_str(), config.port))
+    .map_err(|e| {
+        format!(
+            "Failed to listen on {}:{}: {e}",
+            config.address, config.port
+        )
+    })?;
+
+    info!("Listening on {}:{}", config.address, config.port);
+    server.run().await?;
+    Ok(())
+}
+
+#[actix_web::main]
+async fn main() -> ExitCode {
+    let args: Vec<_> = env::args().skip(1).collect();
+    let (command, args) = Command::from_args(&args);
+    if command == Command::Help {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+
+    dotenv().ok();
+    let config = match Config::load(&args, |var| env::var(var).ok()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Err(e) = log4rs::init_file(&config.log_config, Default::default()) {
+        eprintln!(
+            "Failed to configure logging from {}: {e}",
+            config.log_config.display()
+        );
+        return ExitCode::FAILURE;
+    }
+
+    let result = match command {
+        Command::Serve => serve(config).await,
+        Command::Migrate => match connect(&config, true).await {
+            Ok(conn) => migrate(&conn).await,
+            Err(e) => Err(e),
+        },
+        Command::CheckMigrations => match connect(&config, false).await {
+            Ok(conn) => check_migrations(&conn).await,
+            Err(e) => Err(e),
+        },
+        Command::Help => Ok(()),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            error!("{e}");
+            ExitCode::FAILURE
+        }
+    }
 }
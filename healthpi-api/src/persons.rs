@@ -0,0 +1,318 @@
+use std::error::Error;
+
+use actix_web::{delete, get, post, put, web, HttpResponse, Responder, ResponseError};
+use chrono::NaiveDate;
+use healthpi_model::{
+    body_composition::{self, Impedance},
+    correction::RecordPatch,
+    filter::RecordFilter,
+    measurement::{Value, ValueType},
+    person::{Person, PersonId},
+    validation::{self, Plausibility},
+};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    alerts::Alerter,
+    db::{
+        measurement::{MeasurementRepository, MeasurementRepositoryImpl},
+        person::{PersonRepository, PersonRepositoryImpl},
+    },
+    error::ApiError,
+};
+
+#[derive(Debug, Serialize)]
+struct PersonResponse {
+    id: PersonId,
+    #[serde(flatten)]
+    person: Person,
+}
+
+fn check_person(person: &Person) -> Result<(), String> {
+    if person.name.trim().is_empty() {
+        Err("Name must not be empty".into())
+    } else if person.height_cm == 0 {
+        Err("Height must be positive".into())
+    } else if !(1..=5).contains(&person.activity_level) {
+        Err("Activity level must be between 1 and 5".into())
+    } else if person.goal_weight_kg.is_some_and(|goal| goal <= 0.0) {
+        Err("Goal weight must be positive".into())
+    } else {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RecomputeQuery {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RecomputeResponse {
+    recomputed_records: usize,
+    /// Number of derived values left out, as they came out impossible,
+    /// e.g. for a person whose height was entered wrong.
+    skipped_values: usize,
+}
+
+/// Recomputes values derived from weight and impedance of a person's records
+/// measured between given dates, inclusive, using the person's formula set.
+/// Derived values the formula set no longer produces, or that come out
+/// impossible, are removed. Records that only have weight, e.g. entered
+/// manually, are left as they are.
+async fn recompute_body_composition(
+    measurement_repository: &MeasurementRepositoryImpl,
+    alerter: &Alerter,
+    id: PersonId,
+    person: &Person,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> Result<RecomputeResponse, Box<dyn Error>> {
+    let derived = [
+        ValueType::BodyMassIndex,
+        ValueType::BasalMetabolicRate,
+        ValueType::FatPercent,
+        ValueType::WaterPercent,
+        ValueType::MusclePercent,
+        ValueType::LeanBodyMass,
+        ValueType::FatFreeMassIndex,
+    ];
+    let filter = RecordFilter {
+        select: [
+            &derived[..],
+            &[
+                ValueType::Weight,
+                ValueType::Impedance5kHz,
+                ValueType::Impedance50kHz,
+            ],
+        ]
+        .concat(),
+        from_date: from,
+        to_date: to,
+        person: Some(id),
+        ..Default::default()
+    };
+    let mut skipped_values = 0;
+    let patches: Vec<_> = measurement_repository
+        .fetch_records(&filter, false)
+        .await?
+        .into_iter()
+        .filter_map(|record| {
+            let weight = record.values.iter().find_map(|v| match v {
+                Value::Weight(x) => Some(*x),
+                _ => None,
+            })?;
+            let impedance = Impedance::from_values(&record.values);
+            if impedance.is_none()
+                && !record
+                    .values
+                    .iter()
+                    .any(|v| derived.contains(&v.value_type()))
+            {
+                return None;
+            }
+            let age = person.age_at(record.timestamp.date_naive());
+            let (values, impossible): (Vec<_>, Vec<_>) =
+                body_composition::derive_values(person, age, weight, impedance)
+                    .into_iter()
+                    .partition(|v| validation::check_value(v) != Plausibility::Impossible);
+            skipped_values += impossible.len();
+            let remove: Vec<_> = derived
+                .iter()
+                .filter(|value_type| !values.iter().any(|v| v.value_type() == **value_type))
+                .copied()
+                .collect();
+            Some((
+                record.id?,
+                RecordPatch::default().values(&values).remove(&remove),
+            ))
+        })
+        .collect();
+
+    let recomputed_records = measurement_repository.update_records(&patches).await?;
+    let ids: Vec<_> = patches.iter().map(|(id, _)| *id).collect();
+    alerter.records_changed(&ids).await;
+    if skipped_values > 0 {
+        warn!("Skipped {skipped_values} impossible values recomputed for person {id}");
+    }
+    Ok(RecomputeResponse {
+        recomputed_records,
+        skipped_values,
+    })
+}
+
+#[get("/persons")]
+async fn list_persons(person_repository: web::Data<PersonRepositoryImpl>) -> impl Responder {
+    match person_repository.fetch_persons().await {
+        Ok(persons) => HttpResponse::Ok().json(
+            persons
+                .into_iter()
+                .map(|(id, person)| PersonResponse { id, person })
+                .collect::<Vec<_>>(),
+        ),
+        Err(e) => {
+            error!("Failed to fetch persons: {e}");
+            ApiError::Internal.error_response()
+        }
+    }
+}
+
+#[post("/persons")]
+async fn create_person(
+    person_repository: web::Data<PersonRepositoryImpl>,
+    person: web::Json<Person>,
+) -> impl Responder {
+    if let Err(e) = check_person(&person) {
+        return ApiError::InvalidRequest(e).error_response();
+    }
+    match person_repository.create_person(person.0.clone()).await {
+        Ok(id) => {
+            info!("Created person {id}");
+            HttpResponse::Created().json(PersonResponse {
+                id,
+                person: person.0,
+            })
+        }
+        Err(e) => {
+            error!("Failed to create person: {e}");
+            ApiError::Internal.error_response()
+        }
+    }
+}
+
+#[get("/persons/{id}")]
+async fn get_person(
+    person_repository: web::Data<PersonRepositoryImpl>,
+    id: web::Path<i64>,
+) -> impl Responder {
+    let id = PersonId::new(*id);
+    match person_repository.fetch_person(id).await {
+        Ok(Some(person)) => HttpResponse::Ok().json(PersonResponse { id, person }),
+        Ok(None) => ApiError::NotFound(format!("Person {id} does not exist")).error_response(),
+        Err(e) => {
+            error!("Failed to fetch person {id}: {e}");
+            ApiError::Internal.error_response()
+        }
+    }
+}
+
+/// Updates a person. Values derived from their measurements are recomputed
+/// for the whole history.
+#[put("/persons/{id}")]
+async fn update_person(
+    person_repository: web::Data<PersonRepositoryImpl>,
+    measurement_repository: web::Data<MeasurementRepositoryImpl>,
+    alerter: web::Data<Alerter>,
+    id: web::Path<i64>,
+    person: web::Json<Person>,
+) -> impl Responder {
+    let id = PersonId::new(*id);
+    if let Err(e) = check_person(&person) {
+        return ApiError::InvalidRequest(e).error_response();
+    }
+    match person_repository.update_person(id, person.0.clone()).await {
+        Ok(true) => info!("Updated person {id}"),
+        Ok(false) => {
+            return ApiError::NotFound(format!("Person {id} does not exist")).error_response()
+        }
+        Err(e) => {
+            error!("Failed to update person {id}: {e}");
+            return ApiError::Internal.error_response();
+        }
+    }
+
+    match recompute_body_composition(&measurement_repository, &alerter, id, &person, None, None)
+        .await
+    {
+        Ok(response) => {
+            info!(
+                "Recomputed {} records of person {id}",
+                response.recomputed_records
+            );
+            HttpResponse::Ok().json(PersonResponse {
+                id,
+                person: person.0,
+            })
+        }
+        Err(e) => {
+            error!("Failed to recompute records of person {id}: {e}");
+            ApiError::Internal.error_response()
+        }
+    }
+}
+
+#[post("/persons/{id}/recompute")]
+async fn recompute(
+    person_repository: web::Data<PersonRepositoryImpl>,
+    measurement_repository: web::Data<MeasurementRepositoryImpl>,
+    alerter: web::Data<Alerter>,
+    id: web::Path<i64>,
+    query: web::Query<RecomputeQuery>,
+) -> impl Responder {
+    let id = PersonId::new(*id);
+    let person = match person_repository.fetch_person(id).await {
+        Ok(Some(person)) => person,
+        Ok(None) => {
+            return ApiError::NotFound(format!("Person {id} does not exist")).error_response()
+        }
+        Err(e) => {
+            error!("Failed to fetch person {id}: {e}");
+            return ApiError::Internal.error_response();
+        }
+    };
+
+    match recompute_body_composition(
+        &measurement_repository,
+        &alerter,
+        id,
+        &person,
+        query.from,
+        query.to,
+    )
+    .await
+    {
+        Ok(response) => {
+            info!(
+                "Recomputed {} records of person {id}",
+                response.recomputed_records
+            );
+            HttpResponse::Ok().json(response)
+        }
+        Err(e) => {
+            error!("Failed to recompute records of person {id}: {e}");
+            ApiError::Internal.error_response()
+        }
+    }
+}
+
+/// Deletes a person. Their records are kept, but no longer attributed to anyone.
+#[delete("/persons/{id}")]
+async fn delete_person(
+    person_repository: web::Data<PersonRepositoryImpl>,
+    id: web::Path<i64>,
+) -> impl Responder {
+    let id = PersonId::new(*id);
+    match person_repository.delete_person(id).await {
+        Ok(true) => {
+            info!("Deleted person {id}");
+            HttpResponse::NoContent().finish()
+        }
+        Ok(false) => ApiError::NotFound(format!("Person {id} does not exist")).error_response(),
+        Err(e) => {
+            error!("Failed to delete person {id}: {e}");
+            ApiError::Internal.error_response()
+        }
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_persons)
+        .service(create_person)
+        .service(get_person)
+        .service(update_person)
+        .service(delete_person)
+        .service(recompute);
+}
@@ -0,0 +1,185 @@
+use actix_web::{delete, get, patch, post, web, HttpResponse, Responder, ResponseError};
+use chrono::{Duration, Utc};
+use healthpi_model::{
+    correction::{Deletion, RecordPatch},
+    measurement::RecordId,
+    problem::RecordProblem,
+    units::UnitSystem,
+    validation::{self, Plausibility},
+};
+use log::{error, info, warn};
+use serde::Deserialize;
+
+use crate::{
+    alerts::Alerter,
+    convert_records,
+    db::{
+        measurement::{MeasurementRepository, MeasurementRepositoryImpl},
+        person::{PersonRepository, PersonRepositoryImpl},
+    },
+    error::ApiError,
+    RecordResponse,
+};
+
+/// How long deleted records can be restored for.
+const UNDO_WINDOW_HOURS: i64 = 24;
+
+#[derive(Debug, Deserialize)]
+struct UnitsQuery {
+    #[serde(default)]
+    units: UnitSystem,
+}
+
+async fn record_response(
+    measurement_repository: &MeasurementRepositoryImpl,
+    id: RecordId,
+    units: UnitSystem,
+) -> HttpResponse {
+    match measurement_repository.fetch_record(id).await {
+        Ok(Some(record)) => HttpResponse::Ok().json(RecordResponse::from(
+            convert_records(vec![record], |value| units.canonical_to(value))
+                .pop()
+                .unwrap(),
+        )),
+        Ok(None) => ApiError::NotFound(format!("Record {id} does not exist")).error_response(),
+        Err(e) => {
+            error!("Failed to fetch record {id}: {e}");
+            ApiError::Internal.error_response()
+        }
+    }
+}
+
+#[get("/records/{id}")]
+async fn get_record(
+    measurement_repository: web::Data<MeasurementRepositoryImpl>,
+    id: web::Path<i64>,
+    query: web::Query<UnitsQuery>,
+) -> impl Responder {
+    record_response(&measurement_repository, RecordId::new(*id), query.units).await
+}
+
+/// Adds, replaces or removes values of a record, or attributes it to a person.
+/// Values that are not physically possible are rejected along with the whole
+/// patch, and reported back.
+#[patch("/records/{id}")]
+async fn update_record(
+    measurement_repository: web::Data<MeasurementRepositoryImpl>,
+    person_repository: web::Data<PersonRepositoryImpl>,
+    alerter: web::Data<Alerter>,
+    id: web::Path<i64>,
+    query: web::Query<UnitsQuery>,
+    patch: web::Json<RecordPatch>,
+) -> impl Responder {
+    let id = RecordId::new(*id);
+    let mut patch = patch.0;
+    patch.values = patch
+        .values
+        .into_iter()
+        .map(|value| query.units.to_canonical(value))
+        .collect();
+
+    let issues = validation::check_values(&patch.values);
+    if issues
+        .iter()
+        .any(|issue| issue.plausibility == Plausibility::Impossible)
+    {
+        return ApiError::InvalidValues(issues).error_response();
+    }
+    if !issues.is_empty() {
+        warn!("Record {id} patched with suspicious values: {issues:?}");
+    }
+
+    if let Some(person) = patch.person {
+        match person_repository.fetch_person(person).await {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                return ApiError::UnknownPerson(vec![RecordProblem {
+                    index: None,
+                    message: format!("Person {person} does not exist"),
+                    issues: Vec::new(),
+                }])
+                .error_response()
+            }
+            Err(e) => {
+                error!("Failed to fetch person {person}: {e}");
+                return ApiError::Internal.error_response();
+            }
+        }
+    }
+
+    match measurement_repository.update_record(id, &patch).await {
+        Ok(true) => info!("Updated record {id}"),
+        Ok(false) => {
+            return ApiError::NotFound(format!("Record {id} does not exist")).error_response()
+        }
+        Err(e) => {
+            error!("Failed to update record {id}: {e}");
+            return ApiError::Internal.error_response();
+        }
+    }
+    alerter.records_changed(&[id]).await;
+
+    record_response(&measurement_repository, id, query.units).await
+}
+
+/// Deletes a record. It can be restored within the undo window, after which
+/// the deletion is final.
+#[delete("/records/{id}")]
+async fn delete_record(
+    measurement_repository: web::Data<MeasurementRepositoryImpl>,
+    id: web::Path<i64>,
+) -> impl Responder {
+    let id = RecordId::new(*id);
+    let now = Utc::now();
+    match measurement_repository.delete_record(id, now).await {
+        Ok(true) => {
+            info!("Deleted record {id}");
+            HttpResponse::Ok().json(Deletion {
+                restorable_until: now + Duration::hours(UNDO_WINDOW_HOURS),
+            })
+        }
+        Ok(false) => ApiError::NotFound(format!("Record {id} does not exist")).error_response(),
+        Err(e) => {
+            error!("Failed to delete record {id}: {e}");
+            ApiError::Internal.error_response()
+        }
+    }
+}
+
+/// Restores a record deleted within the undo window.
+#[post("/records/{id}/restore")]
+async fn restore_record(
+    measurement_repository: web::Data<MeasurementRepositoryImpl>,
+    alerter: web::Data<Alerter>,
+    id: web::Path<i64>,
+    query: web::Query<UnitsQuery>,
+) -> impl Responder {
+    let id = RecordId::new(*id);
+    let deleted_since = Utc::now() - Duration::hours(UNDO_WINDOW_HOURS);
+    match measurement_repository
+        .restore_record(id, deleted_since)
+        .await
+    {
+        Ok(true) => info!("Restored record {id}"),
+        Ok(false) => {
+            return ApiError::NotFound(format!(
+                "Record {id} was not deleted within the undo window"
+            ))
+            .error_response()
+        }
+        Err(e) => {
+            error!("Failed to restore record {id}: {e}");
+            return ApiError::Internal.error_response();
+        }
+    }
+    alerter.records_changed(&[id]).await;
+
+    record_response(&measurement_repository, id, query.units).await
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_record)
+        .service(update_record)
+        .service(delete_record)
+        .service(restore_record);
+}
@@ -0,0 +1,564 @@
+use std::fmt::{self, Write};
+
+use actix_web::{get, web, HttpResponse, Responder, ResponseError};
+use chrono::{DateTime, Days, NaiveDate, TimeDelta, Utc};
+use chrono_tz::Tz;
+use healthpi_model::{
+    aggregate::local_midnight,
+    blood_pressure::{self, Average, BloodPressureReading, BloodPressureSummary, Category},
+    filter::{Order, RecordFilter},
+    glucose::{self, GlucoseReading, GlucoseSummary, GlucoseThresholds, TimeInRange},
+    measurement::{MealIndicator, ValueType},
+    person::{Person, PersonId},
+    units::UnitSystem,
+    weight_trend::{self, WeightTrend, DEFAULT_SMOOTHING},
+};
+use itertools::Itertools;
+use log::error;
+use serde::Deserialize;
+
+use crate::{
+    db::{
+        measurement::{MeasurementRepository, MeasurementRepositoryImpl},
+        person::{PersonRepository, PersonRepositoryImpl},
+    },
+    error::ApiError,
+};
+
+/// Longest range a single report can span.
+const MAX_DAYS: u64 = 366;
+
+/// Minutes from the first reading of a blood pressure session within which
+/// following readings belong to it.
+const SESSION_WINDOW_MINUTES: i64 = 10;
+
+const STYLE: &str = r#"
+body { font-family: sans-serif; font-size: 11pt; margin: 2em; color: #000; }
+h1 { font-size: 18pt; margin-bottom: 0; }
+h2 { font-size: 14pt; border-bottom: 1px solid #000; margin-top: 1.5em; page-break-after: avoid; }
+h3 { font-size: 11pt; margin: 1em 0 0.3em; page-break-after: avoid; }
+p.meta { margin-top: 0.3em; color: #444; }
+table { border-collapse: collapse; margin-bottom: 0.5em; page-break-inside: avoid; }
+th, td { border: 1px solid #999; padding: 0.2em 0.6em; text-align: right; }
+th { background: #eee; }
+td.text, th.text { text-align: left; }
+.low { color: #b00; font-weight: bold; }
+.high { color: #a60; font-weight: bold; }
+.days { display: flex; flex-wrap: wrap; gap: 0 1.5em; }
+@page { margin: 1.5cm; }
+@media print {
+    body { margin: 0; }
+    th { background: none; }
+}
+"#;
+
+#[derive(Debug, Deserialize)]
+struct ReportQuery {
+    /// First day of the report, inclusive.
+    from: NaiveDate,
+    /// Last day of the report, inclusive.
+    to: NaiveDate,
+    /// Name of the timezone days are counted in.
+    #[serde(default = "default_timezone")]
+    tz: String,
+    #[serde(default)]
+    units: UnitSystem,
+}
+
+fn default_timezone() -> String {
+    "UTC".into()
+}
+
+/// Data of a report, with values in canonical units.
+struct Report {
+    person: Person,
+    from: NaiveDate,
+    to: NaiveDate,
+    timezone: Tz,
+    units: UnitSystem,
+    generated_at: DateTime<Utc>,
+    glucose: Vec<(DateTime<Tz>, GlucoseReading)>,
+    glucose_summary: Option<GlucoseSummary>,
+    blood_pressure: BloodPressureSummary,
+    weight: WeightTrend,
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn meal_label(meal: MealIndicator) -> &'static str {
+    match meal {
+        MealIndicator::NoIndication => "",
+        MealIndicator::NoMeal => "No meal",
+        MealIndicator::BeforeMeal => "Before meal",
+        MealIndicator::AfterMeal => "After meal",
+    }
+}
+
+fn category_label(category: Category) -> &'static str {
+    match category {
+        Category::Normal => "Normal",
+        Category::Elevated => "Elevated",
+        Category::Stage1 => "Hypertension stage 1",
+        Category::Stage2 => "Hypertension stage 2",
+        Category::Crisis => "Hypertensive crisis",
+    }
+}
+
+fn percent(share: f64) -> String {
+    format!("{:.0}%", share * 100.0)
+}
+
+impl Report {
+    fn amount(&self, value_type: ValueType, x: f64) -> String {
+        format!("{:.1}", self.units.amount_canonical_to(value_type, x))
+    }
+
+    fn unit(&self, value_type: ValueType) -> String {
+        self.units.unit_symbol(value_type).unwrap_or_default()
+    }
+
+    fn render(&self) -> String {
+        let mut html = String::new();
+        self.write(&mut html)
+            .expect("Writing to a string cannot fail");
+        html
+    }
+
+    fn write(&self, html: &mut String) -> fmt::Result {
+        let name = escape(&self.person.name);
+        writeln!(
+            html,
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+            <title>Health report: {name}</title>\n<style>{STYLE}</style>\n</head>\n<body>"
+        )?;
+        writeln!(
+            html,
+            "<h1>Health report: {name}</h1>\n\
+            <p class=\"meta\">Born {birth_date} (age {age}), height {height} cm<br>\
+            {from} to {to}, times in {timezone}<br>Generated {generated}</p>",
+            birth_date = self.person.birth_date,
+            age = self.person.age_at(self.to),
+            height = self.person.height_cm,
+            from = self.from,
+            to = self.to,
+            timezone = self.timezone,
+            generated = self
+                .generated_at
+                .with_timezone(&self.timezone)
+                .format("%Y-%m-%d %H:%M"),
+        )?;
+
+        self.write_glucose(html)?;
+        self.write_blood_pressure(html)?;
+        self.write_weight(html)?;
+
+        writeln!(html, "</body>\n</html>")
+    }
+
+    fn write_time_in_range(&self, html: &mut String, tir: &TimeInRange) -> fmt::Result {
+        write!(
+            html,
+            "<td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>",
+            percent(tir.very_low),
+            percent(tir.low),
+            percent(tir.in_range),
+            percent(tir.high),
+            percent(tir.very_high),
+        )
+    }
+
+    fn write_glucose(&self, html: &mut String) -> fmt::Result {
+        writeln!(html, "<h2>Glucose</h2>")?;
+        let Some(summary) = &self.glucose_summary else {
+            return writeln!(html, "<p>No readings.</p>");
+        };
+        let unit = self.unit(ValueType::Glucose);
+        let thresholds = GlucoseThresholds::default();
+        writeln!(
+            html,
+            "<table>\n<tr><th class=\"text\">Readings</th><td>{count}</td></tr>\n\
+            <tr><th class=\"text\">Mean</th><td>{mean} {unit}</td></tr>\n\
+            <tr><th class=\"text\">Standard deviation</th><td>{sd} {unit}</td></tr>\n\
+            <tr><th class=\"text\">Coefficient of variation</th><td>{cv:.1}%</td></tr>\n\
+            <tr><th class=\"text\">Estimated HbA1c</th><td>{a1c:.1}%</td></tr>\n\
+            <tr><th class=\"text\">Glucose management indicator</th><td>{gmi:.1}%</td></tr>\n\
+            </table>",
+            count = summary.count,
+            mean = self.amount(ValueType::Glucose, summary.mean),
+            sd = self.amount(ValueType::Glucose, summary.standard_deviation),
+            cv = summary.coefficient_of_variation,
+            a1c = summary.estimated_a1c,
+            gmi = summary.gmi,
+        )?;
+
+        write!(
+            html,
+            "<h3>Time in range</h3>\n<table>\n<tr><th class=\"text\"></th><th>Readings</th>\
+            <th>Mean ({unit})</th><th>Very low<br>&lt; {very_low}</th><th>Low</th>\
+            <th>In range<br>{low}–{high}</th><th>High</th><th>Very high<br>&gt; {very_high}</th></tr>\n\
+            <tr><th class=\"text\">All</th><td>{count}</td><td>{mean}</td>",
+            very_low = self.amount(ValueType::Glucose, thresholds.very_low),
+            low = self.amount(ValueType::Glucose, thresholds.low),
+            high = self.amount(ValueType::Glucose, thresholds.high),
+            very_high = self.amount(ValueType::Glucose, thresholds.very_high),
+            count = summary.count,
+            mean = self.amount(ValueType::Glucose, summary.mean),
+        )?;
+        self.write_time_in_range(html, &summary.time_in_range)?;
+        writeln!(html, "</tr>")?;
+        for meal in &summary.by_meal {
+            write!(
+                html,
+                "<tr><th class=\"text\">{label}</th><td>{count}</td><td>{mean}</td>",
+                label = match meal.meal {
+                    MealIndicator::NoIndication => "No indication",
+                    meal => meal_label(meal),
+                },
+                count = meal.count,
+                mean = self.amount(ValueType::Glucose, meal.mean),
+            )?;
+            self.write_time_in_range(html, &meal.time_in_range)?;
+            writeln!(html, "</tr>")?;
+        }
+        writeln!(html, "</table>")?;
+
+        writeln!(html, "<h3>Readings by day</h3>\n<div class=\"days\">")?;
+        for (date, readings) in &self.glucose.iter().group_by(|(t, _)| t.date_naive()) {
+            writeln!(
+                html,
+                "<table>\n<tr><th class=\"text\" colspan=\"3\">{}</th></tr>\n\
+                <tr><th class=\"text\">Time</th><th>{unit}</th><th class=\"text\">Meal</th></tr>",
+                date.format("%a %Y-%m-%d")
+            )?;
+            for (timestamp, reading) in readings {
+                let class = if reading.glucose < thresholds.low {
+                    " class=\"low\""
+                } else if reading.glucose > thresholds.high {
+                    " class=\"high\""
+                } else {
+                    ""
+                };
+                writeln!(
+                    html,
+                    "<tr><td class=\"text\">{time}</td><td{class}>{glucose}</td>\
+                    <td class=\"text\">{meal}</td></tr>",
+                    time = timestamp.format("%H:%M"),
+                    glucose = self.amount(ValueType::Glucose, reading.glucose),
+                    meal = meal_label(reading.meal),
+                )?;
+            }
+            writeln!(html, "</table>")?;
+        }
+        writeln!(html, "</div>")
+    }
+
+    fn write_average(&self, html: &mut String, label: &str, average: &Average) -> fmt::Result {
+        writeln!(
+            html,
+            "<tr><th class=\"text\">{label}</th><td>{count}</td><td>{systolic}</td>\
+            <td>{diastolic}</td><td>{heart_rate}</td><td class=\"text\">{category}</td></tr>",
+            count = average.count,
+            systolic = self.amount(ValueType::BloodPressureSystolic, average.systolic),
+            diastolic = self.amount(ValueType::BloodPressureDiastolic, average.diastolic),
+            heart_rate = average
+                .heart_rate
+                .map(|x| format!("{x:.0}"))
+                .unwrap_or_default(),
+            category = category_label(average.category),
+        )
+    }
+
+    fn write_blood_pressure(&self, html: &mut String) -> fmt::Result {
+        writeln!(html, "<h2>Blood pressure</h2>")?;
+        if self.blood_pressure.sessions.is_empty() {
+            return writeln!(html, "<p>No readings.</p>");
+        }
+        let unit = self.unit(ValueType::BloodPressureSystolic);
+        let columns = format!(
+            "<th>Systolic ({unit})</th><th>Diastolic ({unit})</th><th>Heart rate (bpm)</th>\
+            <th class=\"text\">Category</th>"
+        );
+
+        writeln!(
+            html,
+            "<table>\n<tr><th class=\"text\">Average</th><th>Sessions</th>{columns}</tr>"
+        )?;
+        for (label, average) in [
+            ("Overall", &self.blood_pressure.overall),
+            ("Morning", &self.blood_pressure.morning),
+            ("Evening", &self.blood_pressure.evening),
+        ] {
+            if let Some(average) = average {
+                self.write_average(html, label, average)?;
+            }
+        }
+        writeln!(html, "</table>")?;
+
+        writeln!(
+            html,
+            "<h3>Sessions</h3>\n<table>\n<tr><th class=\"text\">Time</th><th>Readings</th>{columns}</tr>"
+        )?;
+        for session in &self.blood_pressure.sessions {
+            let label = session
+                .start
+                .with_timezone(&self.timezone)
+                .format("%a %Y-%m-%d %H:%M")
+                .to_string();
+            self.write_average(html, &label, &session.average)?;
+        }
+        writeln!(html, "</table>")
+    }
+
+    fn write_weight(&self, html: &mut String) -> fmt::Result {
+        writeln!(html, "<h2>Weight</h2>")?;
+        let (Some(first), Some(last)) = (self.weight.points.first(), self.weight.points.last())
+        else {
+            return writeln!(html, "<p>No weighings.</p>");
+        };
+        let unit = self.unit(ValueType::Weight);
+        writeln!(
+            html,
+            "<table>\n<tr><th class=\"text\">Trend at start</th><td>{start} {unit}</td></tr>\n\
+            <tr><th class=\"text\">Trend at end</th><td>{end} {unit}</td></tr>",
+            start = self.amount(ValueType::Weight, first.trend),
+            end = self.amount(ValueType::Weight, last.trend),
+        )?;
+        if let Some(rate) = self.weight.weekly_rate {
+            writeln!(
+                html,
+                "<tr><th class=\"text\">Weekly rate</th><td>{:+.2} {unit}</td></tr>",
+                self.units.amount_canonical_to(ValueType::Weight, rate)
+            )?;
+        }
+        if let Some(goal) = self.weight.goal {
+            writeln!(
+                html,
+                "<tr><th class=\"text\">Goal</th><td>{} {unit}</td></tr>\n\
+                <tr><th class=\"text\">Projected to reach goal</th><td>{}</td></tr>",
+                self.amount(ValueType::Weight, goal),
+                self.weight
+                    .projected_goal_date
+                    .map_or("Not at the current rate".into(), |date| date.to_string()),
+            )?;
+        }
+        writeln!(html, "</table>")?;
+
+        writeln!(
+            html,
+            "<h3>Weighings</h3>\n<table>\n<tr><th class=\"text\">Time</th>\
+            <th>Weight ({unit})</th><th>Trend ({unit})</th></tr>"
+        )?;
+        for point in &self.weight.points {
+            writeln!(
+                html,
+                "<tr><td class=\"text\">{time}</td><td>{weight}</td><td>{trend}</td></tr>",
+                time = point
+                    .timestamp
+                    .with_timezone(&self.timezone)
+                    .format("%a %Y-%m-%d %H:%M"),
+                weight = self.amount(ValueType::Weight, point.weight),
+                trend = self.amount(ValueType::Weight, point.trend),
+            )?;
+        }
+        writeln!(html, "</table>")
+    }
+}
+
+/// Renders a printable report of glucose, blood pressure and weight of
+/// a person over given days, for their doctor.
+#[get("/persons/{id}/report")]
+async fn person_report(
+    person_repository: web::Data<PersonRepositoryImpl>,
+    measurement_repository: web::Data<MeasurementRepositoryImpl>,
+    id: web::Path<i64>,
+    query: web::Query<ReportQuery>,
+) -> impl Responder {
+    let id = PersonId::new(*id);
+    let Ok(timezone) = query.tz.parse::<Tz>() else {
+        return ApiError::InvalidRequest(format!("Invalid timezone: {}", query.tz))
+            .error_response();
+    };
+    if query.to < query.from {
+        return ApiError::InvalidRequest("Last day must not precede the first one".into())
+            .error_response();
+    }
+    if query
+        .from
+        .checked_add_days(Days::new(MAX_DAYS))
+        .is_some_and(|limit| limit <= query.to)
+    {
+        return ApiError::InvalidRequest(format!("Report cannot span more than {MAX_DAYS} days"))
+            .error_response();
+    }
+    let person = match person_repository.fetch_person(id).await {
+        Ok(Some(person)) => person,
+        Ok(None) => {
+            return ApiError::NotFound(format!("Person {id} does not exist")).error_response()
+        }
+        Err(e) => {
+            error!("Failed to fetch person {id}: {e}");
+            return ApiError::Internal.error_response();
+        }
+    };
+
+    let (Some(from), Some(to)) = (
+        local_midnight(&timezone, query.from),
+        query
+            .to
+            .checked_add_days(Days::new(1))
+            .and_then(|to| local_midnight(&timezone, to)),
+    ) else {
+        return ApiError::InvalidRequest("Range extends beyond supported dates".into())
+            .error_response();
+    };
+    let filter = RecordFilter {
+        select: vec![
+            ValueType::Glucose,
+            ValueType::Meal,
+            ValueType::BloodPressureSystolic,
+            ValueType::BloodPressureDiastolic,
+            ValueType::HeartRate,
+            ValueType::Weight,
+        ],
+        from: Some(from.fixed_offset()),
+        to: Some(to.fixed_offset()),
+        person: Some(id),
+        order: Order::Ascending,
+        ..Default::default()
+    };
+    let records = match measurement_repository.fetch_records(&filter, false).await {
+        Ok(records) => records,
+        Err(e) => {
+            error!("Failed to fetch records of person {id}: {e}");
+            return ApiError::Internal.error_response();
+        }
+    };
+
+    let glucose: Vec<_> = records
+        .iter()
+        .filter_map(|record| {
+            GlucoseReading::from_record(record)
+                .map(|reading| (record.timestamp.with_timezone(&timezone), reading))
+        })
+        .collect();
+    let glucose_summary = glucose::summarize(
+        &glucose.iter().map(|(_, r)| *r).collect::<Vec<_>>(),
+        &GlucoseThresholds::default(),
+    );
+    let blood_pressure = blood_pressure::summarize(
+        &records
+            .iter()
+            .filter_map(BloodPressureReading::from_record)
+            .collect::<Vec<_>>(),
+        TimeDelta::minutes(SESSION_WINDOW_MINUTES),
+    );
+    let weight = weight_trend::analyze(
+        &records
+            .iter()
+            .filter_map(weight_trend::weighing)
+            .collect::<Vec<_>>(),
+        DEFAULT_SMOOTHING,
+        person.goal_weight_kg,
+    );
+
+    let report = Report {
+        person,
+        from: query.from,
+        to: query.to,
+        timezone,
+        units: query.units,
+        generated_at: Utc::now(),
+        glucose,
+        glucose_summary,
+        blood_pressure,
+        weight,
+    };
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(report.render())
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(person_report);
+}
+
+#[cfg(test)]
+mod tests {
+    use healthpi_model::{body_composition::FormulaSet, person::Sex};
+
+    use super::*;
+
+    fn report(person: Person) -> Report {
+        Report {
+            person,
+            from: NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+            to: NaiveDate::from_ymd_opt(2024, 3, 31).unwrap(),
+            timezone: Tz::UTC,
+            units: UnitSystem::canonical(),
+            generated_at: DateTime::from_timestamp(1_712_000_000, 0).unwrap(),
+            glucose: Vec::new(),
+            glucose_summary: None,
+            blood_pressure: blood_pressure::summarize(&[], TimeDelta::minutes(10)),
+            weight: weight_trend::analyze(&[], DEFAULT_SMOOTHING, None),
+        }
+    }
+
+    fn person(name: &str) -> Person {
+        Person {
+            name: name.into(),
+            birth_date: NaiveDate::from_ymd_opt(1980, 5, 17).unwrap(),
+            sex: Sex::Female,
+            height_cm: 168,
+            activity_level: 2,
+            formula: FormulaSet::default(),
+            goal_weight_kg: None,
+        }
+    }
+
+    #[test]
+    fn escapes_person_name() {
+        let html = report(person("<script>alert(1)</script>")).render();
+
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+    }
+
+    #[test]
+    fn tables_glucose_readings_by_day() {
+        let mut report = report(person("Jane"));
+        let reading = |time: &str, glucose, meal| {
+            (
+                time.parse::<DateTime<Utc>>()
+                    .unwrap()
+                    .with_timezone(&Tz::UTC),
+                GlucoseReading { glucose, meal },
+            )
+        };
+        report.glucose = vec![
+            reading("2024-03-01T07:00:00Z", 65.0, MealIndicator::BeforeMeal),
+            reading("2024-03-01T09:00:00Z", 150.0, MealIndicator::AfterMeal),
+            reading("2024-03-02T07:00:00Z", 95.0, MealIndicator::NoIndication),
+        ];
+        report.glucose_summary = glucose::summarize(
+            &report.glucose.iter().map(|(_, r)| *r).collect::<Vec<_>>(),
+            &GlucoseThresholds::default(),
+        );
+
+        let html = report.render();
+
+        assert_eq!(
+            html.matches("<th class=\"text\">Time</th><th>mg/dL</th>")
+                .count(),
+            2
+        );
+        assert!(html.contains("Fri 2024-03-01"));
+        assert!(html.contains("<td class=\"text\">07:00</td><td class=\"low\">65.0</td>"));
+        assert!(html.contains("<td class=\"text\">After meal</td>"));
+        assert!(!html.contains("<script"));
+    }
+}
@@ -0,0 +1,93 @@
+use actix_web::{get, web, HttpResponse, Responder, ResponseError};
+use chrono::{DateTime, FixedOffset};
+use healthpi_model::{
+    filter::{Order, RecordFilter},
+    measurement::ValueType,
+    person::PersonId,
+    units::{Unit, UnitSystem},
+    weight_trend::{self, DEFAULT_SMOOTHING},
+};
+use log::error;
+use serde::Deserialize;
+
+use crate::{
+    db::{
+        measurement::{MeasurementRepository, MeasurementRepositoryImpl},
+        person::{PersonRepository, PersonRepositoryImpl},
+    },
+    error::ApiError,
+};
+
+#[derive(Debug, Deserialize)]
+struct WeightTrendQuery {
+    /// Earliest time of measurement, inclusive.
+    from: Option<DateTime<FixedOffset>>,
+    /// Latest time of measurement, exclusive.
+    to: Option<DateTime<FixedOffset>>,
+    #[serde(default)]
+    units: UnitSystem,
+    /// Fraction of the difference from the trend a daily weighing accounts for.
+    #[serde(default = "default_smoothing")]
+    smoothing: f64,
+}
+
+fn default_smoothing() -> f64 {
+    DEFAULT_SMOOTHING
+}
+
+/// Smooths weight of a person into a trend, and projects when it reaches
+/// their goal weight.
+#[get("/persons/{id}/weight-trend")]
+async fn person_weight_trend(
+    person_repository: web::Data<PersonRepositoryImpl>,
+    measurement_repository: web::Data<MeasurementRepositoryImpl>,
+    id: web::Path<i64>,
+    query: web::Query<WeightTrendQuery>,
+) -> impl Responder {
+    let id = PersonId::new(*id);
+    if !(query.smoothing > 0.0 && query.smoothing <= 1.0) {
+        return ApiError::InvalidRequest("Smoothing must be between 0 and 1".into())
+            .error_response();
+    }
+    let person = match person_repository.fetch_person(id).await {
+        Ok(Some(person)) => person,
+        Ok(None) => {
+            return ApiError::NotFound(format!("Person {id} does not exist")).error_response()
+        }
+        Err(e) => {
+            error!("Failed to fetch person {id}: {e}");
+            return ApiError::Internal.error_response();
+        }
+    };
+
+    let filter = RecordFilter {
+        select: vec![ValueType::Weight],
+        from: query.from,
+        to: query.to,
+        person: Some(id),
+        order: Order::Ascending,
+        ..Default::default()
+    };
+    let records = match measurement_repository.fetch_records(&filter, false).await {
+        Ok(records) => records,
+        Err(e) => {
+            error!("Failed to fetch weight of person {id}: {e}");
+            return ApiError::Internal.error_response();
+        }
+    };
+    let weighings: Vec<_> = records.iter().filter_map(weight_trend::weighing).collect();
+
+    let mut trend = weight_trend::analyze(&weighings, query.smoothing, person.goal_weight_kg);
+    let convert = |x| query.units.weight.canonical_to(x);
+    for point in &mut trend.points {
+        point.weight = convert(point.weight);
+        point.trend = convert(point.trend);
+    }
+    trend.weekly_rate = trend.weekly_rate.map(convert);
+    trend.goal = trend.goal.map(convert);
+    HttpResponse::Ok().json(trend)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(person_weight_trend);
+}
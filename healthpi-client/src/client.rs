@@ -1,19 +1,46 @@
 use async_trait::async_trait;
-use healthpi_model::measurement::{Record, ValueType};
+use healthpi_model::{
+    aggregate::{Aggregate, Bucket},
+    changes::{ChangeCursor, Changes},
+    correction::{Deletion, RecordPatch},
+    filter::RecordFilter,
+    measurement::{Record, RecordId, ValueType},
+    problem::{Problem, ProblemCode},
+    validation::RecordReport,
+};
 use itertools::Itertools;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("server unreachable")]
     CommunicationError,
-    #[error("internal server error")]
-    InternalServerError,
-    #[error("invalid request")]
-    RequestError,
+    /// The server refused the request or failed to handle it.
+    #[error("server responded with status {status}: {problem}")]
+    ApiError { status: u16, problem: Problem },
     #[error("incorrect server response")]
     ResponseError,
 }
 
+impl Error {
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            Error::ApiError { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    pub fn code(&self) -> Option<ProblemCode> {
+        match self {
+            Error::ApiError { problem, .. } => Some(problem.code),
+            _ => None,
+        }
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        self.code() == Some(ProblemCode::NotFound)
+    }
+}
+
 type Result<T> = std::result::Result<T, Error>;
 
 #[mockall::automock]
@@ -21,7 +48,26 @@ type Result<T> = std::result::Result<T, Error>;
 pub trait Client: Send + Sync {
     async fn get_records(&self) -> Result<Vec<Record>>;
     async fn get_records_with_value_types(&self, types: &[ValueType]) -> Result<Vec<Record>>;
-    async fn post_records(&self, records: &[Record]) -> Result<()>;
+    async fn get_records_matching(&self, filter: &RecordFilter) -> Result<Vec<Record>>;
+    /// Fetches records along with their raw data, as received from devices.
+    async fn get_records_with_raw_data(&self, filter: &RecordFilter) -> Result<Vec<Record>>;
+    /// Fetches all records changed since given cursor, following as many pages
+    /// as needed. The returned cursor can be used to fetch the following changes.
+    async fn get_changes(&self, since: ChangeCursor) -> Result<Changes>;
+    async fn post_records(&self, records: &[Record]) -> Result<Vec<RecordReport>>;
+    /// Summarizes values of records matching the filter over periods starting
+    /// at midnight in the named timezone. The filter must have both bounds set.
+    async fn get_aggregates(
+        &self,
+        bucket: Bucket,
+        timezone: &str,
+        filter: &RecordFilter,
+    ) -> Result<Vec<Aggregate>>;
+    async fn get_record(&self, id: RecordId) -> Result<Record>;
+    async fn update_record(&self, id: RecordId, patch: &RecordPatch) -> Result<Record>;
+    /// Deletes a record. It can be restored until the time in the returned deletion.
+    async fn delete_record(&self, id: RecordId) -> Result<Deletion>;
+    async fn restore_record(&self, id: RecordId) -> Result<Record>;
 }
 
 pub struct ClientImpl {
@@ -38,6 +84,80 @@ impl ClientImpl {
     }
 }
 
+/// Reads the body of a response, or turns an error response into an error,
+/// keeping the problem reported by the server. Responses of servers predating
+/// problem responses, or of proxies in front of them, get a problem made up
+/// from their status.
+async fn receive<T: serde::de::DeserializeOwned>(
+    response: reqwest::Result<reqwest::Response>,
+) -> Result<T> {
+    let resp = response.map_err(|_| Error::CommunicationError)?;
+    let status = resp.status();
+    if status.is_success() {
+        return resp.json().await.map_err(|_| Error::ResponseError);
+    }
+    let body = resp.text().await.unwrap_or_default();
+    let problem = serde_json::from_str(&body).unwrap_or_else(|_| {
+        let code = match status {
+            reqwest::StatusCode::NOT_FOUND => ProblemCode::NotFound,
+            status if status.is_server_error() => ProblemCode::Internal,
+            status if status.is_client_error() => ProblemCode::InvalidRequest,
+            _ => ProblemCode::Unknown,
+        };
+        let message = if body.is_empty() {
+            status.canonical_reason().unwrap_or_default().to_owned()
+        } else {
+            body
+        };
+        Problem::new(code, message)
+    });
+    Err(Error::ApiError {
+        status: status.as_u16(),
+        problem,
+    })
+}
+
+impl ClientImpl {
+    async fn fetch_records(&self, query: &[(&str, String)]) -> Result<Vec<Record>> {
+        receive(self.client.get(&self.url).query(query).send().await).await
+    }
+}
+
+impl ClientImpl {
+    fn record_url(&self, id: RecordId) -> String {
+        format!("{}/records/{}", self.url.trim_end_matches('/'), id)
+    }
+}
+
+fn filter_query(filter: &RecordFilter) -> Vec<(&'static str, String)> {
+    let mut query = vec![("order", filter.order.to_string())];
+    if !filter.select.is_empty() {
+        query.push((
+            "select",
+            filter.select.iter().map(|t| format!("{:?}", t)).join(","),
+        ));
+    }
+    if let Some(from) = filter.from {
+        query.push(("from", from.to_rfc3339()));
+    }
+    if let Some(to) = filter.to {
+        query.push(("to", to.to_rfc3339()));
+    }
+    if !filter.sources.is_empty() {
+        query.push((
+            "source",
+            filter.sources.iter().map(|s| format!("{:?}", s)).join(","),
+        ));
+    }
+    if !filter.devices.is_empty() {
+        query.push(("device", filter.devices.iter().join(",")));
+    }
+    if let Some(person) = filter.person {
+        query.push(("person", person.to_string()));
+    }
+    query
+}
+
 pub fn create(url: String) -> impl Client {
     ClientImpl::new(url)
 }
@@ -45,67 +165,102 @@ pub fn create(url: String) -> impl Client {
 #[async_trait]
 impl Client for ClientImpl {
     async fn get_records(&self) -> Result<Vec<Record>> {
-        self.client
-            .get(&self.url)
-            .send()
-            .await
-            .map_err(|_| Error::CommunicationError)
-            .and_then(|resp| {
-                if resp.status().is_client_error() {
-                    Err(Error::RequestError)
-                } else if resp.status().is_server_error() {
-                    Err(Error::InternalServerError)
-                } else {
-                    Ok(resp)
-                }
-            })?
-            .json()
-            .await
-            .map_err(|_| Error::ResponseError)
+        receive(self.client.get(&self.url).send().await).await
     }
 
     async fn get_records_with_value_types(&self, types: &[ValueType]) -> Result<Vec<Record>> {
-        self.client
-            .get(&self.url)
-            .query(&[
-                "select",
-                &types.iter().map(|t| format!("{:?}", t)).join(","),
-            ])
-            .send()
-            .await
-            .map_err(|_| Error::CommunicationError)
-            .and_then(|resp| {
-                if resp.status().is_client_error() {
-                    Err(Error::RequestError)
-                } else if resp.status().is_server_error() {
-                    Err(Error::InternalServerError)
-                } else {
-                    Ok(resp)
-                }
-            })?
-            .json()
+        self.get_records_matching(&RecordFilter::default().select(types))
             .await
-            .map_err(|_| Error::ResponseError)
     }
 
-    async fn post_records(&self, records: &[Record]) -> Result<()> {
-        self.client
-            .post(&self.url)
-            .json(&records)
-            .send()
-            .await
-            .map_err(|_| Error::CommunicationError)
-            .and_then(|resp| {
-                if resp.status().is_client_error() {
-                    Err(Error::RequestError)
-                } else if resp.status().is_server_error() {
-                    Err(Error::InternalServerError)
-                } else {
-                    Ok(resp)
-                }
-            })?
-            .json()
-            .await
-            .map_err(|_| Error::ResponseError)
+    async fn get_records_matching(&self, filter: &RecordFilter) -> Result<Vec<Record>> {
+        self.fetch_records(&filter_query(filter)).await
+    }
+
+    async fn get_records_with_raw_data(&self, filter: &RecordFilter) -> Result<Vec<Record>> {
+        let mut query = filter_query(filter);
+        query.push(("raw", "true".to_owned()));
+        self.fetch_records(&query).await
+    }
+
+    async fn get_changes(&self, since: ChangeCursor) -> Result<Changes> {
+        let mut changes = Changes {
+            records: Vec::new(),
+            deleted: Vec::new(),
+            next_cursor: since,
+            has_more: true,
+        };
+        while changes.has_more {
+            let mut page: Changes = receive(
+                self.client
+                    .get(format!("{}/changes", self.url.trim_end_matches('/')))
+                    .query(&[("since", changes.next_cursor.value())])
+                    .send()
+                    .await,
+            )
+            .await?;
+            // A page that has more after it, but does not move the cursor,
+            // would be followed by the very same page forever.
+            if page.has_more && page.next_cursor <= changes.next_cursor {
+                return Err(Error::ResponseError);
+            }
+            changes.records.append(&mut page.records);
+            changes.deleted.append(&mut page.deleted);
+            changes.next_cursor = page.next_cursor;
+            changes.has_more = page.has_more;
+        }
+        Ok(changes)
+    }
+
+    async fn post_records(&self, records: &[Record]) -> Result<Vec<RecordReport>> {
+        receive(self.client.post(&self.url).json(&records).send().await).await
+    }
+
+    async fn get_aggregates(
+        &self,
+        bucket: Bucket,
+        timezone: &str,
+        filter: &RecordFilter,
+    ) -> Result<Vec<Aggregate>> {
+        let mut query = filter_query(filter);
+        query.push(("bucket", bucket.to_string()));
+        query.push(("tz", timezone.to_owned()));
+        receive(
+            self.client
+                .get(format!("{}/aggregates", self.url.trim_end_matches('/')))
+                .query(&query)
+                .send()
+                .await,
+        )
+        .await
+    }
+
+    async fn get_record(&self, id: RecordId) -> Result<Record> {
+        receive(self.client.get(self.record_url(id)).send().await).await
+    }
+
+    async fn update_record(&self, id: RecordId, patch: &RecordPatch) -> Result<Record> {
+        receive(
+            self.client
+                .patch(self.record_url(id))
+                .json(patch)
+                .send()
+                .await,
+        )
+        .await
+    }
+
+    async fn delete_record(&self, id: RecordId) -> Result<Deletion> {
+        receive(self.client.delete(self.record_url(id)).send().await).await
+    }
+
+    async fn restore_record(&self, id: RecordId) -> Result<Record> {
+        receive(
+            self.client
+                .post(format!("{}/restore", self.record_url(id)))
+                .send()
+                .await,
+        )
+        .await
     }
 }
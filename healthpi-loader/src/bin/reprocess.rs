@@ -0,0 +1,74 @@
+use std::{env, error::Error};
+
+use healthpi_client::Client;
+use healthpi_loader::{
+    devices::{decoder::Decoder, device::read_paired_devices, timezone::DeviceTimezone},
+    reprocess::{reprocess, Reprocessed},
+};
+use healthpi_model::{device::DeviceId, filter::RecordFilter};
+use log::{info, warn};
+
+const USAGE: &str =
+    "Usage: reprocess <MAC address> <contour|shape200|systo-mc-400> [--url <URL>] [--apply]";
+const DEFAULT_URL: &str = "http://localhost:8080/";
+
+/// Decodes stored raw data of records from a single device with current decoders
+/// and reports values that would change. With `--apply`, submits the new values.
+/// The API is reached at `--url`, `HEALTHPI_API_URL` or the local default.
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<_> = env::args().skip(1).collect();
+    let (Some(device_id), Some(decoder)) = (args.first(), args.get(1)) else {
+        return Err(USAGE.into());
+    };
+    let device_id = DeviceId::new(device_id.clone());
+    let decoder: Decoder = decoder.parse()?;
+    let apply = args.iter().any(|arg| arg == "--apply");
+    let url = match args.iter().position(|arg| arg == "--url") {
+        Some(i) => args.get(i + 1).cloned().ok_or(USAGE)?,
+        None => env::var("HEALTHPI_API_URL").unwrap_or_else(|_| DEFAULT_URL.to_owned()),
+    };
+
+    log4rs::init_file("log4rs.yml", Default::default())?;
+
+    let timezone = read_paired_devices("devices.csv")
+        .ok()
+        .and_then(|devices| devices.get(&device_id).copied())
+        .unwrap_or(DeviceTimezone::Local);
+
+    let client = healthpi_client::create(url);
+    let records = client
+        .get_records_with_raw_data(&RecordFilter::default().devices(&[device_id]))
+        .await?;
+
+    let mut changed = Vec::new();
+    for record in &records {
+        match reprocess(record, decoder, &timezone) {
+            Reprocessed::Unchanged => {}
+            Reprocessed::Changed {
+                record: new,
+                previous,
+            } => {
+                info!("{}: {:?} -> {:?}", record.timestamp, previous, new.values);
+                changed.push(new);
+            }
+            Reprocessed::TimestampChanged(timestamp) => {
+                warn!("{}: decodes to {}, skipping", record.timestamp, timestamp)
+            }
+            Reprocessed::Undecodable => {
+                warn!("{}: cannot be decoded, skipping", record.timestamp)
+            }
+        }
+    }
+    if !apply {
+        info!("{} records would change", changed.len());
+        return Ok(());
+    }
+
+    if !changed.is_empty() {
+        client.post_records(&changed).await?;
+    }
+    info!("{} records changed", changed.len());
+
+    Ok(())
+}
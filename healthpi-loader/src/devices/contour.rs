@@ -2,7 +2,8 @@ use std::{collections::BTreeMap, error::Error, time::Duration};
 
 use async_trait::async_trait;
 use futures::StreamExt;
-use healthpi_bt::{BleCharacteristicEvent, BleDevice};
+use healthpi_bt::BleDevice;
+use healthpi_model::device::DeviceId;
 use healthpi_model::measurement::{MealIndicator, Record, Source, Value};
 use log::{debug, info};
 use tokio::time::timeout;
@@ -10,7 +11,7 @@ use uuid::Uuid;
 
 use crate::devices::utils;
 
-use super::device::Device;
+use super::{device::Device, timezone::DeviceTimezone};
 
 const GLUCOSE_SERVICE: Uuid = Uuid::from_u128(0x00001808_0000_1000_8000_00805f9b34fb);
 const GLUCOSE_CHARACTERISTIC: Uuid = Uuid::from_u128(0x00002a18_0000_1000_8000_00805f9b34fb);
@@ -20,27 +21,37 @@ const RACP_CHARACTERISTIC: Uuid = Uuid::from_u128(0x00002a52_0000_1000_8000_0080
 
 pub struct ElitePlus {
     ble_device: Box<dyn BleDevice>,
+    timezone: DeviceTimezone,
 }
 
 impl ElitePlus {
-    pub fn new(ble_device: Box<dyn BleDevice>) -> Self {
-        Self { ble_device }
+    pub fn new(ble_device: Box<dyn BleDevice>, timezone: DeviceTimezone) -> Self {
+        Self {
+            ble_device,
+            timezone,
+        }
     }
 
-    fn read_record(&self, event: BleCharacteristicEvent) -> Option<(u16, Record)> {
-        if event.value.len() < 13 {
+    /// Decodes a glucose measurement, along with its sequence number. Meal context
+    /// is reported separately, so it is not included.
+    pub(crate) fn read_record(
+        raw_data: Vec<u8>,
+        device_id: DeviceId,
+        timezone: &DeviceTimezone,
+    ) -> Option<(u16, Record)> {
+        if raw_data.len() < 13 {
             return None;
         }
-        let sequence_number = u16::from_be_bytes([event.value[2], event.value[1]]);
-        let timestamp = utils::naive_date_time_from_le_bytes(&event.value[3..10])?;
-        let glucose = u16::from_be_bytes([event.value[11], event.value[12]]);
+        let sequence_number = u16::from_be_bytes([raw_data[2], raw_data[1]]);
+        let timestamp = timezone.localize(utils::naive_date_time_from_le_bytes(&raw_data[3..10])?);
+        let glucose = u16::from_be_bytes([raw_data[11], raw_data[12]]);
         Some((
             sequence_number,
             Record::new(
                 timestamp,
-                vec![Value::Glucose(glucose as i32)],
-                event.value,
-                Source::Device(self.ble_device.id()),
+                vec![Value::Glucose(glucose as f64)],
+                raw_data,
+                Source::Device(device_id),
             ),
         ))
     }
@@ -88,7 +99,9 @@ impl Device for ElitePlus {
         info!("Processing measurement notifications");
         while let Ok(Some(event)) = timeout(Duration::from_secs(1), measurement_events.next()).await
         {
-            if let Some((sequence_number, record)) = self.read_record(event) {
+            if let Some((sequence_number, record)) =
+                Self::read_record(event.value, self.ble_device.id(), &self.timezone)
+            {
                 records.insert(sequence_number, record);
             }
         }
@@ -0,0 +1,68 @@
+use std::{fmt, str::FromStr};
+
+use healthpi_model::{device::DeviceId, measurement::Record};
+
+use super::{contour::ElitePlus, soehnle, timezone::DeviceTimezone};
+
+/// Supported device models, which determine how their payloads are decoded.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Decoder {
+    ElitePlus,
+    Shape200,
+    SystoMC400,
+}
+
+impl Decoder {
+    pub fn for_device_name(name: &str) -> Option<Self> {
+        if name.contains("Contour") {
+            Some(Decoder::ElitePlus)
+        } else if name.contains("Shape200") {
+            Some(Decoder::Shape200)
+        } else if name.contains("Systo MC 400") {
+            Some(Decoder::SystoMC400)
+        } else {
+            None
+        }
+    }
+
+    /// Decodes a single stored payload. Only values contained in the payload itself
+    /// are decoded, so e.g. meal context of glucose measurements or body composition
+    /// derived from weight are not included.
+    pub fn decode(
+        &self,
+        raw_data: Vec<u8>,
+        device_id: DeviceId,
+        timezone: &DeviceTimezone,
+    ) -> Option<Record> {
+        match self {
+            Decoder::ElitePlus => {
+                ElitePlus::read_record(raw_data, device_id, timezone).map(|(_, record)| record)
+            }
+            Decoder::Shape200 => soehnle::Shape200::read_measurement(raw_data, device_id, timezone),
+            Decoder::SystoMC400 => soehnle::SystoMC400::read_record(raw_data, device_id, timezone),
+        }
+    }
+}
+
+impl fmt::Display for Decoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Decoder::ElitePlus => write!(f, "contour"),
+            Decoder::Shape200 => write!(f, "shape200"),
+            Decoder::SystoMC400 => write!(f, "systo-mc-400"),
+        }
+    }
+}
+
+impl FromStr for Decoder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "contour" => Ok(Decoder::ElitePlus),
+            "shape200" => Ok(Decoder::Shape200),
+            "systo-mc-400" => Ok(Decoder::SystoMC400),
+            _ => Err(format!("Unknown device model: {s}")),
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use std::collections::HashMap;
-use std::io::{BufRead, BufReader};
-use std::{collections::HashSet, error::Error, fs::File};
+use std::io::{self, BufRead, BufReader};
+use std::{error::Error, fs::File};
 
 use async_trait::async_trait;
 use chrono::{DateTime, Local, Utc};
@@ -8,7 +8,32 @@ use healthpi_bt::BleDevice;
 use healthpi_model::{device::DeviceId, measurement::Record};
 use log::{debug, info, warn};
 
-use super::{contour, soehnle};
+use super::{contour, decoder::Decoder, soehnle, timezone::DeviceTimezone};
+
+/// Loads paired devices from a file containing one device per line, in the form
+/// of its MAC address optionally followed by a comma and the name of timezone
+/// its clock is set to, e.g. `12:34:56:78:9A:BC,Europe/Warsaw`. Devices without
+/// a timezone are assumed to be in the local timezone.
+pub fn read_paired_devices(path: &str) -> io::Result<HashMap<DeviceId, DeviceTimezone>> {
+    let file = File::open(path)?;
+    let paired_devices = BufReader::new(file)
+        .lines()
+        .map_while(|l| l.ok())
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| {
+            let (id, timezone) = l.split_once(',').unwrap_or((&l, ""));
+            let timezone = timezone
+                .trim()
+                .parse()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            Ok((DeviceId::new(id.trim().to_owned()), timezone))
+        })
+        .collect::<io::Result<HashMap<_, _>>>()?;
+
+    info!("Loaded {} paired devices from file", paired_devices.len());
+    debug!("Loaded devices: {:?}", paired_devices);
+    Ok(paired_devices)
+}
 
 #[async_trait]
 pub trait Device {
@@ -52,36 +77,28 @@ pub trait Factory: Send + Sync {
 }
 
 pub struct FactoryImpl {
-    paired_devices: HashSet<DeviceId>,
+    paired_devices: HashMap<DeviceId, DeviceTimezone>,
     backoff_table: BackoffTable,
 }
 
 impl FactoryImpl {
     #[allow(dead_code)]
-    pub fn new(paired_devices: HashSet<DeviceId>) -> Self {
+    pub fn new(paired_devices: HashMap<DeviceId, DeviceTimezone>) -> Self {
         Self {
             paired_devices,
             backoff_table: BackoffTable::new(),
         }
     }
 
-    pub fn from_file(path: &str) -> std::io::Result<Self> {
-        let file = File::open(path)?;
-        let paired_devices: HashSet<DeviceId> = BufReader::new(file)
-            .lines()
-            .map_while(|l| l.ok())
-            .map(DeviceId::new)
-            .collect();
-
-        info!("Loaded {} paired devices from file", paired_devices.len());
-        debug!("Loaded devices: {:?}", paired_devices);
-        Ok(Self::new(paired_devices))
+    pub fn from_file(path: &str) -> io::Result<Self> {
+        Ok(Self::new(read_paired_devices(path)?))
     }
 }
 
 impl Factory for FactoryImpl {
     fn make_device(&self, ble_device: Box<dyn BleDevice>) -> Option<Box<dyn Device>> {
-        if !ble_device.in_range() || !self.paired_devices.contains(&ble_device.id()) {
+        let timezone = *self.paired_devices.get(&ble_device.id())?;
+        if !ble_device.in_range() {
             None
         } else if self.backoff_table.check(&*ble_device) {
             debug!(
@@ -89,18 +106,25 @@ impl Factory for FactoryImpl {
                 ble_device.name()
             );
             None
-        } else if ble_device.name().contains("Contour") {
-            Some(Box::new(contour::ElitePlus::new(ble_device)))
-        } else if ble_device.name().contains("Shape200") {
-            Some(Box::new(soehnle::Shape200::new(ble_device)))
-        } else if ble_device.name().contains("Systo MC 400") {
-            Some(Box::new(soehnle::SystoMC400::new(ble_device)))
         } else {
-            warn!(
-                "Device with ID={} is not of any supported types",
-                ble_device.id()
-            );
-            None
+            match Decoder::for_device_name(&ble_device.name()) {
+                Some(Decoder::ElitePlus) => {
+                    Some(Box::new(contour::ElitePlus::new(ble_device, timezone)))
+                }
+                Some(Decoder::Shape200) => {
+                    Some(Box::new(soehnle::Shape200::new(ble_device, timezone)))
+                }
+                Some(Decoder::SystoMC400) => {
+                    Some(Box::new(soehnle::SystoMC400::new(ble_device, timezone)))
+                }
+                None => {
+                    warn!(
+                        "Device with ID={} is not of any supported types",
+                        ble_device.id()
+                    );
+                    None
+                }
+            }
         }
     }
 
@@ -1,6 +1,8 @@
 pub mod device;
 
 pub mod contour;
+pub mod decoder;
 pub mod soehnle;
+pub mod timezone;
 
 mod utils;
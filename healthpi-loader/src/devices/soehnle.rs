@@ -2,19 +2,21 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 
 use async_trait::async_trait;
-use chrono::{NaiveDateTime, Utc};
+use chrono::NaiveDate;
 use futures::StreamExt;
 use healthpi_bt::{BleCharacteristicEvent, BleDevice};
+use healthpi_model::body_composition::{self, FormulaSet, Impedance};
 use healthpi_model::device::DeviceId;
 use healthpi_model::measurement::{Record, Source, Value};
-use healthpi_model::user::User;
+use healthpi_model::person::{Person, Sex};
+use healthpi_model::units::{PressureUnit, Unit};
 use log::{debug, info, trace};
 use tokio::time::timeout;
 use uuid::Uuid;
 
 use crate::devices::utils;
 
-use super::device::Device;
+use super::{device::Device, timezone::DeviceTimezone};
 
 const WEIGHT_CUSTOM_CHARACTERISTIC: Uuid = Uuid::from_u128(0x352e3001_28e9_40b8_a361_6db4cca4147c);
 const CMD_CHARACTERISTIC: Uuid = Uuid::from_u128(0x352e3002_28e9_40b8_a361_6db4cca4147c);
@@ -24,53 +26,74 @@ const BLOOD_PRESSURE_CHARACTERISTIC: Uuid = Uuid::from_u128(0x00002a35_0000_1000
 
 pub struct Shape200 {
     ble_device: Box<dyn BleDevice>,
+    timezone: DeviceTimezone,
 }
 
 impl Shape200 {
-    pub fn new(ble_device: Box<dyn BleDevice>) -> Self {
-        Self { ble_device }
+    pub fn new(ble_device: Box<dyn BleDevice>, timezone: DeviceTimezone) -> Self {
+        Self {
+            ble_device,
+            timezone,
+        }
     }
 
-    fn read_record(&self, user: &User, event: BleCharacteristicEvent) -> Option<Record> {
-        if event.value.len() != 15 {
+    /// Decodes values measured by the scale, i.e. weight and impedance.
+    pub(crate) fn read_measurement(
+        raw_data: Vec<u8>,
+        device_id: DeviceId,
+        timezone: &DeviceTimezone,
+    ) -> Option<Record> {
+        if raw_data.len() != 15 {
             return None;
         }
-        let timestamp = utils::naive_date_time_from_be_bytes(&event.value[2..9])?;
+        let timestamp = timezone.localize(utils::naive_date_time_from_be_bytes(&raw_data[2..9])?);
 
-        let weight = u16::from_be_bytes([event.value[9], event.value[10]]) as f64 / 10.0;
-        let mut values = vec![
-            Value::Weight(weight),
-            Value::BodyMassIndex(get_body_mass_index(user, weight)),
-            Value::BasalMetabolicRate(get_basal_metabolic_rate(user, weight)),
-        ];
+        let weight = u16::from_be_bytes([raw_data[9], raw_data[10]]) as f64 / 10.0;
+        let imp5 = u16::from_be_bytes([raw_data[11], raw_data[12]]) as f64;
+        let imp50 = u16::from_be_bytes([raw_data[13], raw_data[14]]) as f64;
 
-        let imp5 = u16::from_be_bytes([event.value[11], event.value[12]]) as f64;
-        let imp50 = u16::from_be_bytes([event.value[13], event.value[14]]) as f64;
-        if imp50 > 0.0 && imp50 < 1600.0 {
-            // The upper bound for imp50 is not exact. A value of 1600 would contribute 100%
-            // to the body fat calculation, so any value above that is likely a result
-            // of incorrect measurement.
-            values.append(&mut vec![
-                Value::FatPercent(get_fat_percentage(user, weight, imp50)),
-                Value::WaterPercent(get_water_percentage(user, weight, imp50)),
-                Value::MusclePercent(get_muscle_percentage(user, weight, imp5, imp50)),
-            ]);
+        let mut values = vec![Value::Weight(weight)];
+        // Impedance is stored as well, so that derived values can be recomputed
+        // once more is known about the person.
+        if let Some(impedance) = Impedance::new(imp5, imp50) {
+            values.append(&mut impedance.values());
         }
         Some(Record::new(
             timestamp,
             values,
-            event.value,
-            Source::Device(self.ble_device.id()),
+            raw_data,
+            Source::Device(device_id),
         ))
     }
 
-    fn user_from_event(event: BleCharacteristicEvent) -> User {
-        User::new(
-            event.value[3],
-            event.value[4] != 0,
-            u16::from_be_bytes([event.value[5], event.value[6]]),
-            event.value[9],
-        )
+    fn read_record(&self, person: &Person, event: BleCharacteristicEvent) -> Option<Record> {
+        let mut record = Self::read_measurement(event.value, self.ble_device.id(), &self.timezone)?;
+        let Some(Value::Weight(weight)) = record.values.first() else {
+            return None;
+        };
+        let age = person.age_at(record.timestamp.date_naive());
+        let impedance = Impedance::from_values(&record.values);
+        let mut derived = body_composition::derive_values(person, age, *weight, impedance);
+        record.values.append(&mut derived);
+        Some(record)
+    }
+
+    /// The scale only knows the age of its users, so their birth date is estimated
+    /// from the age they are at the time of syncing.
+    fn person_from_event(event: BleCharacteristicEvent, today: NaiveDate) -> Person {
+        Person {
+            name: format!("Scale user {}", event.value[1]),
+            birth_date: Person::estimate_birth_date(event.value[3], today),
+            sex: if event.value[4] != 0 {
+                Sex::Female
+            } else {
+                Sex::Male
+            },
+            height_cm: u16::from_be_bytes([event.value[5], event.value[6]]),
+            activity_level: event.value[9],
+            formula: FormulaSet::Soehnle,
+            goal_weight_kg: None,
+        }
     }
 }
 
@@ -107,19 +130,20 @@ impl Device for Shape200 {
         cmd_characteristic.write_with_response(&[0x0c, 1]).await?;
 
         info!("Reading user data");
-        let user = if let Some(event) = events.next().await {
-            Self::user_from_event(event)
+        let today = self.timezone.now().date_naive();
+        let person = if let Some(event) = events.next().await {
+            Self::person_from_event(event, today)
         } else {
             panic!("Did not receive user data!")
         };
-        trace!("User: {:?}", user);
+        trace!("User: {:?}", person);
 
         // Consume remaining user data events before requesting measurement notifications.
         // Otherwise measurement events might not come.
         while let Ok(Some(event)) = timeout(Duration::from_secs(1), events.next()).await {
             trace!(
                 "Additional user received: {:?}",
-                Self::user_from_event(event)
+                Self::person_from_event(event, today)
             );
         }
 
@@ -130,7 +154,7 @@ impl Device for Shape200 {
         let mut records = Vec::new();
         while let Ok(Some(event)) = timeout(Duration::from_secs(1), events.next()).await {
             trace!("Event received: {:?}", event.value);
-            if let Some(record) = self.read_record(&user, event) {
+            if let Some(record) = self.read_record(&person, event) {
                 records.push(record);
             }
         }
@@ -140,113 +164,66 @@ impl Device for Shape200 {
     }
 }
 
-fn get_water_percentage(user: &User, weight: f64, imp50: f64) -> f64 {
-    let activity_correction_factor = match (user.activity_level(), user.is_female()) {
-        (1..=3, true) => 0.0,
-        (1..=3, false) => 2.83,
-        (4, true) => 0.4,
-        (4, false) => 3.93,
-        (5, true) => 1.4,
-        (5, false) => 5.33,
-        _ => 0.0,
-    };
-
-    (0.3674 * (user.height_cm() as f64).powf(2.0) / imp50 + 0.17530 * weight
-        - 0.11 * user.age() as f64
-        + (6.53 + activity_correction_factor))
-        / weight
-        * 100.0
-}
-
-fn get_muscle_percentage(user: &User, weight: f64, imp5: f64, imp50: f64) -> f64 {
-    let activity_correction_factor = match (user.activity_level(), user.is_female()) {
-        (1..=3, true) => 0.0,
-        (1..=3, false) => 3.6224,
-        (4, true) => 0.0,
-        (4, false) => 4.3904,
-        (5, true) => 1.664,
-        (5, false) => 5.4144,
-        _ => 0.0,
-    };
-    ((0.47027 / imp50 - 0.24196 / imp5) * (user.height_cm() as f64).powf(2.0) + 0.13796 * weight
-        - 0.1152 * user.age() as f64
-        + (5.12 + activity_correction_factor))
-        / weight
-        * 100.0
-}
-
-fn get_fat_percentage(user: &User, weight: f64, imp50: f64) -> f64 {
-    let activity_correction_factor = match (user.activity_level(), user.is_female()) {
-        (4, true) => 2.3,
-        (4, false) => 2.5,
-        (5, true) => 4.1,
-        (5, false) => 4.3,
-        _ => 0.0,
-    };
-
-    let (sex_correction_factor, activity_sex_div) = if user.is_female() {
-        (0.214, 55.1)
-    } else {
-        (0.250, 65.5)
-    };
-
-    1.847 * weight / user.height_m().powf(2.0)
-        + sex_correction_factor * user.age() as f64
-        + 0.062 * imp50
-        - (activity_sex_div - activity_correction_factor)
-}
-
-fn get_body_mass_index(user: &User, weight: f64) -> f64 {
-    weight / user.height_m().powf(2.0)
-}
-
-fn get_basal_metabolic_rate(user: &User, weight: f64) -> f64 {
-    if user.is_female() {
-        447.593 + 9.247 * weight + 3.098 * user.height_cm() as f64 - 4.330 * user.age() as f64
-    } else {
-        88.362 + 13.397 * weight + 4.799 * user.height_cm() as f64 - 5.677 * user.age() as f64
-    }
-}
-
 pub struct SystoMC400 {
     ble_device: Box<dyn BleDevice>,
+    timezone: DeviceTimezone,
     data_processed: AtomicBool,
 }
 
 impl SystoMC400 {
-    pub fn new(ble_device: Box<dyn BleDevice>) -> Self {
+    pub fn new(ble_device: Box<dyn BleDevice>, timezone: DeviceTimezone) -> Self {
         Self {
             ble_device,
+            timezone,
             data_processed: AtomicBool::new(false),
         }
     }
 
-    fn read_record(raw_data: Vec<u8>, device_id: DeviceId) -> Option<Record> {
+    pub(crate) fn read_record(
+        raw_data: Vec<u8>,
+        device_id: DeviceId,
+        timezone: &DeviceTimezone,
+    ) -> Option<Record> {
+        if raw_data.len() < 7 {
+            return None;
+        }
         let mut i = 1;
 
         let mut values = Vec::new();
         let systolic_raw: u32 = u16::from_be_bytes([raw_data[i + 1], raw_data[i]]).into();
         let diastolic_raw: u32 = u16::from_be_bytes([raw_data[i + 3], raw_data[i + 2]]).into();
         let (systolic, diastolic) = if raw_data[0] & 1 == 0 {
-            (systolic_raw, diastolic_raw)
+            (systolic_raw as f64, diastolic_raw as f64)
         } else {
-            (systolic_raw * 15 / 2000, diastolic_raw * 15 / 2000)
+            // Pressure in kPa is reported in pascals. The device only has a resolution
+            // of 1 mmHg, so anything past that is an artifact of the conversion.
+            let to_mmhg = |pascals: u32| {
+                PressureUnit::Kilopascal
+                    .convert(pascals as f64 / 1000.0, PressureUnit::MillimetreOfMercury)
+                    .round()
+            };
+            (to_mmhg(systolic_raw), to_mmhg(diastolic_raw))
         };
         values.append(&mut vec![
-            Value::BloodPressureSystolic(systolic as i32),
-            Value::BloodPressureDiastolic(diastolic as i32),
+            Value::BloodPressureSystolic(systolic),
+            Value::BloodPressureDiastolic(diastolic),
         ]);
         i += 6;
 
         let timestamp = if raw_data[0] & 2 == 0 {
-            Utc::now().naive_local()
+            timezone.now()
         } else {
-            let t = utils::naive_date_time_from_le_bytes(&raw_data[i..i + 7])?;
+            let t = timezone.localize(utils::naive_date_time_from_le_bytes(
+                raw_data.get(i..i + 7)?,
+            )?);
             i += 7;
             t
         };
 
         if raw_data[0] & 4 != 0 {
+            if raw_data.len() < i + 2 {
+                return None;
+            }
             let heart_rate = u16::from_be_bytes([raw_data[i + 1], raw_data[i]]);
             values.push(Value::HeartRate(heart_rate as i32));
         }
@@ -292,16 +269,18 @@ impl Device for SystoMC400 {
 
         info!("Processing notifications");
         let mut records = Vec::new();
-        let mut prev_timestamp = NaiveDateTime::MIN;
+        let mut prev_timestamp = None;
         let mut timestamp_duplicate_count = 0;
         while let Ok(Some(event)) = timeout(Duration::from_millis(5000), events.next()).await {
-            if let Some(mut record) = Self::read_record(event.value, self.ble_device.id()) {
-                if record.timestamp == prev_timestamp {
+            if let Some(mut record) =
+                Self::read_record(event.value, self.ble_device.id(), &self.timezone)
+            {
+                if Some(record.timestamp) == prev_timestamp {
                     timestamp_duplicate_count += 1;
                     record.timestamp += chrono::Duration::seconds(timestamp_duplicate_count);
                 } else {
                     timestamp_duplicate_count = 0;
-                    prev_timestamp = record.timestamp
+                    prev_timestamp = Some(record.timestamp)
                 }
                 records.push(record);
             }
@@ -321,7 +300,9 @@ impl Device for SystoMC400 {
 mod tests {
     use super::*;
 
-    use chrono::{NaiveDate, NaiveTime};
+    use chrono::{FixedOffset, NaiveDate, NaiveDateTime, NaiveTime};
+
+    const TIMEZONE: DeviceTimezone = DeviceTimezone::Named(chrono_tz::Europe::Warsaw);
 
     #[test]
     fn read_record_all_fields_present() {
@@ -333,17 +314,19 @@ mod tests {
             NaiveDateTime::new(
                 NaiveDate::from_ymd_opt(2022, 8, 4).unwrap(),
                 NaiveTime::from_hms_opt(13, 49, 0).unwrap(),
-            ),
+            )
+            .and_local_timezone(FixedOffset::east_opt(2 * 3600).unwrap())
+            .unwrap(),
             vec![
-                Value::BloodPressureSystolic(128),
-                Value::BloodPressureDiastolic(75),
+                Value::BloodPressureSystolic(128.0),
+                Value::BloodPressureDiastolic(75.0),
                 Value::HeartRate(80),
             ],
             raw_data.clone(),
             Source::Device(device_id.clone()),
         );
 
-        let record = SystoMC400::read_record(raw_data.clone(), device_id).unwrap();
+        let record = SystoMC400::read_record(raw_data.clone(), device_id, &TIMEZONE).unwrap();
 
         assert_eq!(record, expected);
     }
@@ -360,32 +343,54 @@ mod tests {
             NaiveDateTime::new(
                 NaiveDate::from_ymd_opt(2022, 8, 4).unwrap(),
                 NaiveTime::from_hms_opt(13, 49, 0).unwrap(),
-            ),
+            )
+            .and_local_timezone(FixedOffset::east_opt(2 * 3600).unwrap())
+            .unwrap(),
             vec![
-                Value::BloodPressureSystolic(128),
-                Value::BloodPressureDiastolic(75),
+                Value::BloodPressureSystolic(128.0),
+                Value::BloodPressureDiastolic(75.0),
                 Value::HeartRate(80),
             ],
             raw_data.clone(),
             Source::Device(device_id.clone()),
         );
 
-        let record = SystoMC400::read_record(raw_data.clone(), device_id).unwrap();
+        let record = SystoMC400::read_record(raw_data.clone(), device_id, &TIMEZONE).unwrap();
 
         assert_eq!(record, expected);
     }
 
+    #[test]
+    fn read_record_kpa_rounds_to_nearest_mmhg() {
+        let device_id = DeviceId::new("12:34:56:78:9A:BC".into());
+        // 16070 Pa = 62 * 256 + 198 = 120.54 mmHg
+        // 10600 Pa = 41 * 256 + 104 = 79.51 mmHg
+        let raw_data = vec![1, 198, 62, 104, 41, 93, 0];
+        let expected_values = vec![
+            Value::BloodPressureSystolic(121.0),
+            Value::BloodPressureDiastolic(80.0),
+        ];
+
+        let record =
+            SystoMC400::read_record(raw_data.clone(), device_id.clone(), &TIMEZONE).unwrap();
+
+        assert_eq!(record.raw_data, raw_data);
+        assert_eq!(record.source, Source::Device(device_id));
+        assert_eq!(record.values, expected_values);
+    }
+
     #[test]
     fn read_record_without_timestamp() {
         let device_id = DeviceId::new("12:34:56:78:9A:BC".into());
         let raw_data = vec![28, 128, 0, 75, 0, 93, 0, 80, 0, 0, 0, 0];
         let expected_values = vec![
-            Value::BloodPressureSystolic(128),
-            Value::BloodPressureDiastolic(75),
+            Value::BloodPressureSystolic(128.0),
+            Value::BloodPressureDiastolic(75.0),
             Value::HeartRate(80),
         ];
 
-        let record = SystoMC400::read_record(raw_data.clone(), device_id.clone()).unwrap();
+        let record =
+            SystoMC400::read_record(raw_data.clone(), device_id.clone(), &TIMEZONE).unwrap();
 
         assert_eq!(record.raw_data, raw_data);
         assert_eq!(record.source, Source::Device(device_id));
@@ -400,16 +405,18 @@ mod tests {
             NaiveDateTime::new(
                 NaiveDate::from_ymd_opt(2022, 8, 4).unwrap(),
                 NaiveTime::from_hms_opt(13, 49, 0).unwrap(),
-            ),
+            )
+            .and_local_timezone(FixedOffset::east_opt(2 * 3600).unwrap())
+            .unwrap(),
             vec![
-                Value::BloodPressureSystolic(128),
-                Value::BloodPressureDiastolic(75),
+                Value::BloodPressureSystolic(128.0),
+                Value::BloodPressureDiastolic(75.0),
             ],
             raw_data.clone(),
             Source::Device(device_id.clone()),
         );
 
-        let record = SystoMC400::read_record(raw_data.clone(), device_id).unwrap();
+        let record = SystoMC400::read_record(raw_data.clone(), device_id, &TIMEZONE).unwrap();
 
         assert_eq!(record, expected);
     }
@@ -0,0 +1,115 @@
+use std::str::FromStr;
+
+use chrono::{
+    DateTime, Duration, FixedOffset, Local, LocalResult, NaiveDateTime, Offset, TimeZone, Utc,
+};
+use chrono_tz::Tz;
+
+/// Timezone a device's clock is set to. Devices only report local time,
+/// so this is needed to tell which offset applied to each measurement.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DeviceTimezone {
+    /// Timezone of the machine running the loader.
+    #[default]
+    Local,
+    Named(Tz),
+}
+
+impl DeviceTimezone {
+    pub fn now(&self) -> DateTime<FixedOffset> {
+        match self {
+            Self::Local => Local::now().fixed_offset(),
+            Self::Named(tz) => Utc::now().with_timezone(tz).fixed_offset(),
+        }
+    }
+
+    /// Attaches the offset that applied at given local time.
+    pub fn localize(&self, timestamp: NaiveDateTime) -> DateTime<FixedOffset> {
+        match self {
+            Self::Local => localize_in(&Local, timestamp),
+            Self::Named(tz) => localize_in(tz, timestamp),
+        }
+    }
+}
+
+fn localize_in<T: TimeZone>(tz: &T, timestamp: NaiveDateTime) -> DateTime<FixedOffset> {
+    match tz.from_local_datetime(&timestamp) {
+        // When clocks go back, the same local time happens twice. There is no
+        // way to tell which one the device meant, so pick the first one.
+        LocalResult::Single(t) | LocalResult::Ambiguous(t, _) => t.fixed_offset(),
+        // When clocks go forward, some local times never happen. The device clock
+        // most likely has not been adjusted yet, so use the offset from before.
+        LocalResult::None => {
+            let offset = tz
+                .offset_from_local_datetime(&(timestamp - Duration::hours(1)))
+                .earliest()
+                .map(|o| o.fix())
+                .unwrap_or(FixedOffset::east_opt(0).unwrap());
+            timestamp.and_local_timezone(offset).unwrap()
+        }
+    }
+}
+
+impl FromStr for DeviceTimezone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" | "local" => Ok(Self::Local),
+            name => Tz::from_str(name)
+                .map(Self::Named)
+                .map_err(|_| format!("Invalid timezone: {}", name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{NaiveDate, NaiveTime};
+
+    fn warsaw() -> DeviceTimezone {
+        DeviceTimezone::Named(chrono_tz::Europe::Warsaw)
+    }
+
+    fn naive(month: u32, day: u32, hour: u32, min: u32) -> NaiveDateTime {
+        NaiveDateTime::new(
+            NaiveDate::from_ymd_opt(2024, month, day).unwrap(),
+            NaiveTime::from_hms_opt(hour, min, 0).unwrap(),
+        )
+    }
+
+    #[test]
+    fn localize_applies_daylight_saving_time() {
+        assert_eq!(
+            warsaw().localize(naive(1, 15, 8, 0)).to_rfc3339(),
+            "2024-01-15T08:00:00+01:00"
+        );
+        assert_eq!(
+            warsaw().localize(naive(7, 15, 8, 0)).to_rfc3339(),
+            "2024-07-15T08:00:00+02:00"
+        );
+    }
+
+    #[test]
+    fn localize_handles_clock_changes() {
+        // 2024-03-31 02:30 never happened in Warsaw.
+        assert_eq!(
+            warsaw().localize(naive(3, 31, 2, 30)).to_rfc3339(),
+            "2024-03-31T02:30:00+01:00"
+        );
+        // 2024-10-27 02:30 happened twice in Warsaw.
+        assert_eq!(
+            warsaw().localize(naive(10, 27, 2, 30)).to_rfc3339(),
+            "2024-10-27T02:30:00+02:00"
+        );
+    }
+
+    #[test]
+    fn parses_timezones() {
+        assert_eq!("".parse(), Ok(DeviceTimezone::Local));
+        assert_eq!("Europe/Warsaw".parse(), Ok(warsaw()));
+        assert!("Mars/Olympus_Mons".parse::<DeviceTimezone>().is_err());
+    }
+}
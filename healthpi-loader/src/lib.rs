@@ -1,4 +1,5 @@
 pub mod devices;
+pub mod reprocess;
 
 use std::{
     error::Error,
@@ -11,6 +12,7 @@ use std::{
 
 use futures::lock::Mutex;
 use healthpi_bt::BleSession;
+use healthpi_model::{problem::ProblemCode, validation::RecordStatus};
 use log::{debug, error, info, warn};
 use tokio::time;
 
@@ -96,9 +98,26 @@ impl Loader {
                 }
 
                 info!("Storing records in database");
-                if let Err(e) = self.api_client.post_records(&records).await {
-                    error!("Failed to store records in database, skipping: {}", e);
-                    continue;
+                match self.api_client.post_records(&records).await {
+                    Ok(reports) => reports
+                        .iter()
+                        .filter(|r| r.status != RecordStatus::Stored)
+                        .for_each(|r| {
+                            warn!(
+                                "Record {:?} {:?}: {:?}",
+                                records.get(r.index).map(|r| r.timestamp),
+                                r.status,
+                                r.issues
+                            )
+                        }),
+                    // Sending the same impossible values again would not help.
+                    Err(e) if e.code() == Some(ProblemCode::InvalidValues) => {
+                        warn!("Records rejected: {}", e);
+                    }
+                    Err(e) => {
+                        error!("Failed to store records in database, skipping: {}", e);
+                        continue;
+                    }
                 }
 
                 info!("Device processed successfully");
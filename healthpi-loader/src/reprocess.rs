@@ -0,0 +1,136 @@
+use chrono::{DateTime, FixedOffset};
+use healthpi_model::measurement::{Record, Source, Value};
+
+use crate::devices::{decoder::Decoder, timezone::DeviceTimezone};
+
+/// Outcome of decoding the raw data of a stored record again.
+#[derive(Debug, PartialEq)]
+pub enum Reprocessed {
+    Unchanged,
+    /// Some of the decoded values differ from the stored ones. Holds the stored
+    /// record with only the new values, ready to be submitted.
+    Changed {
+        record: Record,
+        previous: Vec<Value>,
+    },
+    /// The payload decodes to a different time. Such records cannot be corrected,
+    /// since records are identified by their time.
+    TimestampChanged(DateTime<FixedOffset>),
+    /// The payload cannot be decoded at all, e.g. because it was not stored.
+    Undecodable,
+}
+
+pub fn reprocess(stored: &Record, decoder: Decoder, timezone: &DeviceTimezone) -> Reprocessed {
+    let Source::Device(device_id) = &stored.source else {
+        return Reprocessed::Undecodable;
+    };
+    if stored.raw_data.is_empty() {
+        return Reprocessed::Undecodable;
+    }
+    let Some(decoded) = decoder.decode(stored.raw_data.clone(), device_id.clone(), timezone) else {
+        return Reprocessed::Undecodable;
+    };
+    if decoded.timestamp.naive_local() != stored.timestamp.naive_local() {
+        return Reprocessed::TimestampChanged(decoded.timestamp);
+    }
+
+    let (values, previous): (Vec<_>, Vec<_>) = decoded
+        .values
+        .into_iter()
+        .filter_map(|value| {
+            let previous = stored
+                .values
+                .iter()
+                .find(|v| v.value_type() == value.value_type());
+            match previous {
+                Some(previous) if *previous == value => None,
+                _ => Some((value, previous.cloned())),
+            }
+        })
+        .unzip();
+    if values.is_empty() {
+        return Reprocessed::Unchanged;
+    }
+
+    Reprocessed::Changed {
+        record: Record::new(
+            stored.timestamp,
+            values,
+            stored.raw_data.clone(),
+            stored.source.clone(),
+        ),
+        previous: previous.into_iter().flatten().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+    use healthpi_model::device::DeviceId;
+
+    use super::*;
+
+    const TIMEZONE: DeviceTimezone = DeviceTimezone::Named(chrono_tz::Europe::Warsaw);
+    const RAW_DATA: [u8; 19] = [
+        30, 128, 0, 75, 0, 93, 0, 230, 7, 8, 4, 13, 49, 0, 80, 0, 0, 0, 0,
+    ];
+
+    fn stored(values: Vec<Value>) -> Record {
+        Record::new(
+            NaiveDateTime::new(
+                NaiveDate::from_ymd_opt(2022, 8, 4).unwrap(),
+                NaiveTime::from_hms_opt(13, 49, 0).unwrap(),
+            )
+            .and_local_timezone(FixedOffset::east_opt(2 * 3600).unwrap())
+            .unwrap(),
+            values,
+            RAW_DATA.to_vec(),
+            Source::Device(DeviceId::new("12:34:56:78:9A:BC".into())),
+        )
+    }
+
+    #[test]
+    fn reports_no_changes_for_correct_values() {
+        let record = stored(vec![
+            Value::BloodPressureSystolic(128.0),
+            Value::BloodPressureDiastolic(75.0),
+            Value::HeartRate(80),
+        ]);
+
+        assert_eq!(
+            reprocess(&record, Decoder::SystoMC400, &TIMEZONE),
+            Reprocessed::Unchanged
+        );
+    }
+
+    #[test]
+    fn reports_changed_and_missing_values() {
+        let record = stored(vec![
+            Value::BloodPressureSystolic(128.0),
+            Value::BloodPressureDiastolic(57.0),
+        ]);
+
+        let Reprocessed::Changed { record, previous } =
+            reprocess(&record, Decoder::SystoMC400, &TIMEZONE)
+        else {
+            panic!("Expected changes");
+        };
+
+        assert_eq!(
+            record.values,
+            vec![Value::BloodPressureDiastolic(75.0), Value::HeartRate(80)]
+        );
+        assert_eq!(previous, vec![Value::BloodPressureDiastolic(57.0)]);
+    }
+
+    #[test]
+    fn does_not_reprocess_records_without_raw_data() {
+        let mut record = stored(vec![Value::BloodPressureSystolic(128.0)]);
+        record.raw_data.clear();
+
+        assert_eq!(
+            reprocess(&record, Decoder::SystoMC400, &TIMEZONE),
+            Reprocessed::Undecodable
+        );
+    }
+}
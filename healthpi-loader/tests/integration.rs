@@ -7,7 +7,7 @@ use chrono::Utc;
 use futures::stream;
 use healthpi_bt::{BleCharacteristicEvent, MockBleCharacteristic, MockBleDevice, MockBleSession};
 use healthpi_loader::{
-    devices::{device::MockFactory, soehnle::Shape200},
+    devices::{device::MockFactory, soehnle::Shape200, timezone::DeviceTimezone},
     Loader,
 };
 use mockall::predicate::eq;
@@ -63,14 +63,14 @@ async fn shape_200_returns_no_records() {
     let mut factory = MockFactory::new();
     factory
         .expect_make_device()
-        .returning(|ble_device| Some(Box::new(Shape200::new(ble_device))));
+        .returning(|ble_device| Some(Box::new(Shape200::new(ble_device, DeviceTimezone::Local))));
     factory.expect_mark_processed().returning(|_| Utc::now());
 
     let mut measurement_repository = healthpi_client::MockClient::new();
     measurement_repository
         .expect_post_records()
         .with(eq(vec![]))
-        .returning(|_| Ok(()));
+        .returning(|_| Ok(vec![]));
 
     let loader = Arc::new(Loader::new(
         Box::new(ble_session),
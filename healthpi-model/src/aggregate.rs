@@ -0,0 +1,246 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{DateTime, Datelike, Days, FixedOffset, Months, NaiveDate, TimeDelta, TimeZone};
+
+use crate::measurement::ValueType;
+
+/// Length of periods values are aggregated over. Periods start at local
+/// midnight, weeks on Monday and months on their first day.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_with::DeserializeFromStr, serde_with::SerializeDisplay)
+)]
+pub enum Bucket {
+    Day,
+    Week,
+    Month,
+}
+
+/// Start of a period, inclusive, and its end, exclusive.
+pub type Period<Tz> = (DateTime<Tz>, DateTime<Tz>);
+
+impl Bucket {
+    fn period_start(self, date: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            Bucket::Day => Some(date),
+            Bucket::Week => {
+                date.checked_sub_days(Days::new(date.weekday().num_days_from_monday().into()))
+            }
+            Bucket::Month => date.with_day(1),
+        }
+    }
+
+    fn next_period_start(self, start: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            Bucket::Day => start.checked_add_days(Days::new(1)),
+            Bucket::Week => start.checked_add_days(Days::new(7)),
+            Bucket::Month => start.checked_add_months(Months::new(1)),
+        }
+    }
+
+    /// Splits time into periods covering the range from `from`, inclusive,
+    /// to `to`, exclusive. The first and last periods may extend beyond it.
+    /// Days skipped in the timezone have no periods of their own.
+    /// Fails as soon as there would be more than `limit` of them.
+    pub fn periods<Tz: TimeZone>(
+        self,
+        from: &DateTime<Tz>,
+        to: &DateTime<Tz>,
+        limit: usize,
+    ) -> Result<Vec<Period<Tz>>, PeriodError> {
+        let timezone = from.timezone();
+        let mut periods = Vec::new();
+        let mut start = self
+            .period_start(from.date_naive())
+            .ok_or(PeriodError::OutOfRange)?;
+        let mut start_time = local_midnight(&timezone, start).ok_or(PeriodError::OutOfRange)?;
+        while start_time < *to {
+            if periods.len() == limit {
+                return Err(PeriodError::TooMany(limit));
+            }
+            let end = self
+                .next_period_start(start)
+                .ok_or(PeriodError::OutOfRange)?;
+            let end_time = local_midnight(&timezone, end).ok_or(PeriodError::OutOfRange)?;
+            if start_time < end_time {
+                periods.push((start_time, end_time.clone()));
+            }
+            (start, start_time) = (end, end_time);
+        }
+        Ok(periods)
+    }
+}
+
+/// Reason a range cannot be split into periods.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PeriodError {
+    /// There are more periods than the given limit.
+    TooMany(usize),
+    /// Periods would start at dates chrono cannot represent.
+    OutOfRange,
+}
+
+impl fmt::Display for PeriodError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeriodError::TooMany(limit) => write!(f, "Range spans more than {limit} periods"),
+            PeriodError::OutOfRange => write!(f, "Range extends beyond supported dates"),
+        }
+    }
+}
+
+/// Start of a day in given timezone. Where midnight is skipped by a change
+/// of the offset, the day starts once the clock has been moved forward, and
+/// where the whole day is skipped, with the following one. Only fails for
+/// dates at the limits of the supported range.
+pub fn local_midnight<Tz: TimeZone>(timezone: &Tz, date: NaiveDate) -> Option<DateTime<Tz>> {
+    let midnight = date.and_hms_opt(0, 0, 0)?;
+    // Offsets are whole quarters of an hour, and no change skips more than
+    // a day, e.g. Samoa moving across the date line.
+    (0..=2 * 24 * 4).find_map(|quarters| {
+        let time = midnight.checked_add_signed(TimeDelta::minutes(15 * quarters))?;
+        timezone.from_local_datetime(&time).earliest()
+    })
+}
+
+impl fmt::Display for Bucket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Bucket::Day => write!(f, "day"),
+            Bucket::Week => write!(f, "week"),
+            Bucket::Month => write!(f, "month"),
+        }
+    }
+}
+
+impl FromStr for Bucket {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "day" => Ok(Bucket::Day),
+            "week" => Ok(Bucket::Week),
+            "month" => Ok(Bucket::Month),
+            _ => Err(format!("Invalid bucket: {}", s)),
+        }
+    }
+}
+
+/// Summary of values of a single type measured within a period.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct Aggregate {
+    /// Start of the period, in the timezone the periods were computed in.
+    pub start: DateTime<FixedOffset>,
+    pub value_type: ValueType,
+    pub count: u32,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub median: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(offset_hours: i32, date: (i32, u32, u32), hour: u32) -> DateTime<FixedOffset> {
+        FixedOffset::east_opt(offset_hours * 3600)
+            .unwrap()
+            .with_ymd_and_hms(date.0, date.1, date.2, hour, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn splits_range_into_days_starting_at_local_midnight() {
+        let periods = Bucket::Day
+            .periods(&at(2, (2024, 7, 15), 8), &at(2, (2024, 7, 17), 0), 100)
+            .unwrap();
+
+        assert_eq!(
+            periods,
+            vec![
+                (at(2, (2024, 7, 15), 0), at(2, (2024, 7, 16), 0)),
+                (at(2, (2024, 7, 16), 0), at(2, (2024, 7, 17), 0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn weeks_start_on_monday() {
+        // 2024-07-17 is a Wednesday.
+        let periods = Bucket::Week
+            .periods(&at(0, (2024, 7, 17), 12), &at(0, (2024, 7, 23), 0), 100)
+            .unwrap();
+
+        assert_eq!(
+            periods,
+            vec![
+                (at(0, (2024, 7, 15), 0), at(0, (2024, 7, 22), 0)),
+                (at(0, (2024, 7, 22), 0), at(0, (2024, 7, 29), 0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn months_have_varying_lengths() {
+        let periods = Bucket::Month
+            .periods(&at(0, (2024, 1, 31), 0), &at(0, (2024, 3, 1), 0), 100)
+            .unwrap();
+
+        assert_eq!(
+            periods,
+            vec![
+                (at(0, (2024, 1, 1), 0), at(0, (2024, 2, 1), 0)),
+                (at(0, (2024, 2, 1), 0), at(0, (2024, 3, 1), 0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_range_has_no_periods() {
+        let time = at(0, (2024, 7, 15), 12);
+
+        assert!(Bucket::Day
+            .periods(&time, &at(0, (2024, 7, 15), 0), 100)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn stops_past_limit() {
+        let from = at(0, (1, 1, 1), 0);
+        let to = at(0, (9999, 12, 31), 0);
+
+        assert_eq!(
+            Bucket::Day.periods(&from, &to, 100),
+            Err(PeriodError::TooMany(100))
+        );
+    }
+
+    #[test]
+    fn skipped_day_starts_with_the_following_one() {
+        // Samoa skipped 2011-12-30, moving from UTC-10 to UTC+14.
+        let timezone = chrono_tz::Pacific::Apia;
+        let from = timezone.with_ymd_and_hms(2011, 12, 29, 0, 0, 0).unwrap();
+        let to = timezone.with_ymd_and_hms(2012, 1, 1, 0, 0, 0).unwrap();
+
+        let periods = Bucket::Day.periods(&from, &to, 100).unwrap();
+
+        let midnight_31 = timezone.with_ymd_and_hms(2011, 12, 31, 0, 0, 0).unwrap();
+        assert_eq!(periods, vec![(from, midnight_31), (midnight_31, to)]);
+    }
+
+    #[test]
+    fn rejects_range_beyond_supported_dates() {
+        let from = at(0, (262_142, 12, 1), 0);
+        let to = at(0, (262_142, 12, 31), 0);
+
+        assert_eq!(
+            Bucket::Month.periods(&from, &to, 100),
+            Err(PeriodError::OutOfRange)
+        );
+    }
+}
@@ -0,0 +1,214 @@
+//! Rules notifying about worrying measurements of a person.
+
+use std::fmt;
+
+use chrono::{DateTime, FixedOffset, NaiveTime, TimeDelta, Utc};
+
+use crate::{
+    measurement::{RecordId, ValueType},
+    person::PersonId,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct AlertRuleId(i64);
+
+impl AlertRuleId {
+    pub fn new(id: i64) -> Self {
+        Self(id)
+    }
+
+    pub fn value(&self) -> i64 {
+        self.0
+    }
+}
+
+impl fmt::Display for AlertRuleId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct AlertId(i64);
+
+impl AlertId {
+    pub fn new(id: i64) -> Self {
+        Self(id)
+    }
+
+    pub fn value(&self) -> i64 {
+        self.0
+    }
+}
+
+impl fmt::Display for AlertId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// What triggers an alert. Values are in canonical units.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(rename_all = "camelCase", rename_all_fields = "camelCase")
+)]
+pub enum AlertCondition {
+    /// A value lower than the threshold.
+    Below {
+        value_type: ValueType,
+        threshold: f64,
+    },
+    /// A value higher than the threshold.
+    Above {
+        value_type: ValueType,
+        threshold: f64,
+    },
+    /// A value differing by more than `max_change` from the previous value
+    /// measured within given number of hours before it.
+    RateOfChange {
+        value_type: ValueType,
+        max_change: f64,
+        within_hours: u32,
+    },
+    /// No value measured on a day by given local time.
+    MissedMeasurement {
+        value_type: ValueType,
+        deadline: NaiveTime,
+    },
+}
+
+impl AlertCondition {
+    pub fn value_type(&self) -> ValueType {
+        match self {
+            AlertCondition::Below { value_type, .. }
+            | AlertCondition::Above { value_type, .. }
+            | AlertCondition::RateOfChange { value_type, .. }
+            | AlertCondition::MissedMeasurement { value_type, .. } => *value_type,
+        }
+    }
+
+    /// How long before a value the previous one is looked for, if the
+    /// condition depends on it.
+    pub fn lookback(&self) -> Option<TimeDelta> {
+        match self {
+            AlertCondition::RateOfChange { within_hours, .. } => {
+                Some(TimeDelta::hours((*within_hours).into()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Checks a measured value, along with the previous value of the same type
+    /// measured within [`lookback`](Self::lookback). Returns a description of
+    /// the problem, if the condition is met.
+    pub fn check(&self, value: f64, previous: Option<f64>) -> Option<String> {
+        match self {
+            AlertCondition::Below {
+                value_type,
+                threshold,
+            } => (value < *threshold)
+                .then(|| format!("{value_type:?} of {value} is below {threshold}")),
+            AlertCondition::Above {
+                value_type,
+                threshold,
+            } => (value > *threshold)
+                .then(|| format!("{value_type:?} of {value} is above {threshold}")),
+            AlertCondition::RateOfChange {
+                value_type,
+                max_change,
+                within_hours,
+            } => previous
+                .filter(|previous| (value - previous).abs() > *max_change)
+                .map(|previous| {
+                    format!(
+                        "{value_type:?} changed from {previous} to {value} within {within_hours} h"
+                    )
+                }),
+            AlertCondition::MissedMeasurement { .. } => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct AlertRule {
+    pub condition: AlertCondition,
+    /// URL alerts are posted to, as JSON.
+    pub webhook_url: String,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    /// All attempts to deliver the alert failed.
+    Failed,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct Alert {
+    pub id: AlertId,
+    pub rule: AlertRuleId,
+    pub person: PersonId,
+    pub triggered_at: DateTime<Utc>,
+    /// Time of the measurement that triggered the alert, if any.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub measured_at: Option<DateTime<FixedOffset>>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub record: Option<RecordId>,
+    pub message: String,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_thresholds() {
+        let low = AlertCondition::Below {
+            value_type: ValueType::Glucose,
+            threshold: 70.0,
+        };
+
+        assert_eq!(
+            low.check(62.0, None),
+            Some("Glucose of 62 is below 70".into())
+        );
+        assert_eq!(low.check(70.0, None), None);
+    }
+
+    #[test]
+    fn checks_rate_of_change_against_previous_value() {
+        let change = AlertCondition::RateOfChange {
+            value_type: ValueType::Weight,
+            max_change: 2.0,
+            within_hours: 24,
+        };
+
+        assert_eq!(change.check(80.0, None), None);
+        assert_eq!(change.check(80.0, Some(78.5)), None);
+        assert_eq!(
+            change.check(80.0, Some(77.5)),
+            Some("Weight changed from 77.5 to 80 within 24 h".into())
+        );
+    }
+}
@@ -0,0 +1,320 @@
+//! Sessions of blood pressure readings and their classification.
+//!
+//! Blood pressure is usually measured two or three times in one sitting, and
+//! the readings are only meaningful together, so they are grouped into sessions
+//! and averaged before being classified.
+
+use chrono::{DateTime, FixedOffset, TimeDelta, Timelike};
+
+use crate::{
+    measurement::{Record, Source, Value},
+    person::PersonId,
+};
+
+/// Category of blood pressure according to the 2017 ACC/AHA guideline.
+/// The higher of the categories of both pressures applies.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub enum Category {
+    Normal,
+    Elevated,
+    Stage1,
+    Stage2,
+    /// Hypertensive crisis, requiring immediate attention.
+    Crisis,
+}
+
+impl Category {
+    /// Classifies pressures given in mmHg.
+    pub fn classify(systolic: f64, diastolic: f64) -> Self {
+        let by_systolic = match systolic {
+            x if x > 180.0 => Category::Crisis,
+            x if x >= 140.0 => Category::Stage2,
+            x if x >= 130.0 => Category::Stage1,
+            x if x >= 120.0 => Category::Elevated,
+            _ => Category::Normal,
+        };
+        let by_diastolic = match diastolic {
+            x if x > 120.0 => Category::Crisis,
+            x if x >= 90.0 => Category::Stage2,
+            x if x >= 80.0 => Category::Stage1,
+            _ => Category::Normal,
+        };
+        by_systolic.max(by_diastolic)
+    }
+}
+
+/// A single blood pressure reading, in mmHg.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BloodPressureReading {
+    pub timestamp: DateTime<FixedOffset>,
+    pub systolic: f64,
+    pub diastolic: f64,
+    pub heart_rate: Option<i32>,
+    /// Person and source the reading came from. Only readings of the same
+    /// person taken with the same device make up a session.
+    pub person: Option<PersonId>,
+    pub source: Source,
+}
+
+impl BloodPressureReading {
+    /// Takes the blood pressure reading out of a record, if it has both pressures.
+    pub fn from_record(record: &Record) -> Option<Self> {
+        let mut systolic = None;
+        let mut diastolic = None;
+        let mut heart_rate = None;
+        for value in &record.values {
+            match value {
+                Value::BloodPressureSystolic(x) => systolic = Some(*x),
+                Value::BloodPressureDiastolic(x) => diastolic = Some(*x),
+                Value::HeartRate(x) => heart_rate = Some(*x),
+                _ => {}
+            }
+        }
+        Some(Self {
+            timestamp: record.timestamp,
+            systolic: systolic?,
+            diastolic: diastolic?,
+            heart_rate,
+            person: record.person,
+            source: record.source.clone(),
+        })
+    }
+}
+
+/// Averages of a number of readings or sessions, in mmHg and beats per minute.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct Average {
+    pub count: usize,
+    pub systolic: f64,
+    pub diastolic: f64,
+    /// Missing if none of the readings had one.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub heart_rate: Option<f64>,
+    pub category: Category,
+}
+
+impl Average {
+    fn of(items: impl Iterator<Item = (f64, f64, Option<f64>)> + Clone) -> Option<Self> {
+        let count = items.clone().count();
+        if count == 0 {
+            return None;
+        }
+        let systolic = items.clone().map(|(s, _, _)| s).sum::<f64>() / count as f64;
+        let diastolic = items.clone().map(|(_, d, _)| d).sum::<f64>() / count as f64;
+        let heart_rates: Vec<_> = items.flat_map(|(_, _, h)| h).collect();
+        let heart_rate = (!heart_rates.is_empty())
+            .then(|| heart_rates.iter().sum::<f64>() / heart_rates.len() as f64);
+        Some(Self {
+            count,
+            systolic,
+            diastolic,
+            heart_rate,
+            category: Category::classify(systolic, diastolic),
+        })
+    }
+}
+
+/// Readings taken in one sitting.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct Session {
+    /// Time of the first reading.
+    pub start: DateTime<FixedOffset>,
+    /// Time of the last reading.
+    pub end: DateTime<FixedOffset>,
+    /// Average of the readings, where `count` is the number of readings.
+    pub average: Average,
+}
+
+impl Session {
+    fn of(readings: &[BloodPressureReading]) -> Self {
+        Self {
+            start: readings[0].timestamp,
+            end: readings[readings.len() - 1].timestamp,
+            average: Average::of(
+                readings
+                    .iter()
+                    .map(|r| (r.systolic, r.diastolic, r.heart_rate.map(f64::from))),
+            )
+            .unwrap(),
+        }
+    }
+
+    /// Sessions starting between 4:00 and noon, local time.
+    pub fn is_morning(&self) -> bool {
+        (4..12).contains(&self.start.hour())
+    }
+
+    /// Sessions starting between 18:00 and midnight, local time.
+    pub fn is_evening(&self) -> bool {
+        (18..24).contains(&self.start.hour())
+    }
+}
+
+/// Groups readings into sessions, in order of their start. A session includes
+/// all readings of the same person and source taken within `window` from its
+/// first reading.
+pub fn sessions(readings: &[BloodPressureReading], window: TimeDelta) -> Vec<Session> {
+    let mut sittings: Vec<Vec<BloodPressureReading>> = Vec::new();
+    for reading in readings {
+        match sittings
+            .iter_mut()
+            .find(|s| s[0].person == reading.person && s[0].source == reading.source)
+        {
+            Some(sitting) => sitting.push(reading.clone()),
+            None => sittings.push(vec![reading.clone()]),
+        }
+    }
+
+    let mut sessions = Vec::new();
+    for mut readings in sittings {
+        readings.sort_by_key(|r| r.timestamp);
+        let mut first = 0;
+        for i in 1..=readings.len() {
+            if i == readings.len() || readings[i].timestamp - readings[first].timestamp > window {
+                sessions.push(Session::of(&readings[first..i]));
+                first = i;
+            }
+        }
+    }
+    sessions.sort_by_key(|s| s.start);
+    sessions
+}
+
+/// Averages of sessions over a period. Each session counts once, regardless
+/// of how many readings it consists of.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct BloodPressureSummary {
+    pub sessions: Vec<Session>,
+    pub overall: Option<Average>,
+    pub morning: Option<Average>,
+    pub evening: Option<Average>,
+}
+
+pub fn summarize(readings: &[BloodPressureReading], window: TimeDelta) -> BloodPressureSummary {
+    let sessions = sessions(readings, window);
+    let average = |filter: fn(&Session) -> bool| {
+        Average::of(sessions.iter().filter(move |s| filter(s)).map(|s| {
+            (
+                s.average.systolic,
+                s.average.diastolic,
+                s.average.heart_rate,
+            )
+        }))
+    };
+    BloodPressureSummary {
+        overall: average(|_| true),
+        morning: average(Session::is_morning),
+        evening: average(Session::is_evening),
+        sessions,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::device::DeviceId;
+
+    fn reading(hour: u32, minute: u32, systolic: f64, diastolic: f64) -> BloodPressureReading {
+        BloodPressureReading {
+            timestamp: FixedOffset::east_opt(2 * 3600)
+                .unwrap()
+                .with_ymd_and_hms(2024, 7, 15, hour, minute, 0)
+                .unwrap(),
+            systolic,
+            diastolic,
+            heart_rate: Some(70),
+            person: None,
+            source: Source::Device(DeviceId::new("omron".into())),
+        }
+    }
+
+    #[test]
+    fn classifies_by_higher_category() {
+        assert_eq!(Category::classify(115.0, 75.0), Category::Normal);
+        assert_eq!(Category::classify(125.0, 75.0), Category::Elevated);
+        assert_eq!(Category::classify(125.0, 85.0), Category::Stage1);
+        assert_eq!(Category::classify(135.0, 95.0), Category::Stage2);
+        assert_eq!(Category::classify(185.0, 95.0), Category::Crisis);
+        assert_eq!(Category::classify(115.0, 125.0), Category::Crisis);
+    }
+
+    #[test]
+    fn groups_readings_within_window() {
+        let readings = [
+            reading(7, 12, 132.0, 84.0),
+            reading(7, 0, 140.0, 90.0),
+            reading(7, 5, 136.0, 86.0),
+            reading(7, 30, 120.0, 80.0),
+        ];
+
+        let sessions = sessions(&readings, TimeDelta::minutes(15));
+
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].start, readings[1].timestamp);
+        assert_eq!(sessions[0].end, readings[0].timestamp);
+        assert_eq!(sessions[0].average.count, 3);
+        assert_eq!(sessions[0].average.systolic, 136.0);
+        assert_eq!(sessions[0].average.diastolic, 86.66666666666667);
+        assert_eq!(sessions[0].average.category, Category::Stage1);
+        assert_eq!(sessions[1].average.count, 1);
+    }
+
+    #[test]
+    fn keeps_sessions_of_different_persons_apart() {
+        let mut other = reading(7, 2, 120.0, 80.0);
+        other.person = Some(PersonId::new(2));
+        let readings = [
+            reading(7, 0, 140.0, 90.0),
+            other,
+            reading(7, 4, 130.0, 80.0),
+        ];
+
+        let sessions = sessions(&readings, TimeDelta::minutes(15));
+
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].average.count, 2);
+        assert_eq!(sessions[0].average.systolic, 135.0);
+        assert_eq!(sessions[1].average.count, 1);
+        assert_eq!(sessions[1].start, readings[1].timestamp);
+    }
+
+    #[test]
+    fn averages_sessions_by_time_of_day() {
+        let readings = [
+            reading(7, 0, 140.0, 90.0),
+            reading(7, 2, 130.0, 80.0),
+            reading(8, 0, 120.0, 70.0),
+            reading(21, 0, 110.0, 70.0),
+        ];
+
+        let summary = summarize(&readings, TimeDelta::minutes(10));
+
+        let morning = summary.morning.unwrap();
+        assert_eq!(morning.count, 2);
+        assert_eq!(morning.systolic, 127.5);
+        assert_eq!(morning.diastolic, 77.5);
+        assert_eq!(summary.evening.unwrap().count, 1);
+        assert_eq!(summary.overall.unwrap().count, 3);
+    }
+
+    #[test]
+    fn does_not_average_no_sessions() {
+        let summary = summarize(&[], TimeDelta::minutes(10));
+
+        assert!(summary.sessions.is_empty());
+        assert_eq!(summary.overall, None);
+    }
+}
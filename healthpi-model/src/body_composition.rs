@@ -0,0 +1,376 @@
+use crate::{measurement::Value, person::Person};
+
+/// Impedance of the body measured by a scale at 5 kHz and 50 kHz, in ohms.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Impedance {
+    pub imp5: f64,
+    pub imp50: f64,
+}
+
+impl Impedance {
+    /// Returns impedance, if it can be used to estimate body composition.
+    pub fn new(imp5: f64, imp50: f64) -> Option<Self> {
+        // The upper bound for imp50 is not exact. A value of 1600 would contribute 100%
+        // to the body fat calculation, so any value above that is likely a result
+        // of incorrect measurement.
+        if imp50 > 0.0 && imp50 < 1600.0 {
+            Some(Self { imp5, imp50 })
+        } else {
+            None
+        }
+    }
+
+    /// Finds impedance among values of a record.
+    pub fn from_values(values: &[Value]) -> Option<Self> {
+        let imp5 = values.iter().find_map(|v| match v {
+            Value::Impedance5kHz(x) => Some(*x),
+            _ => None,
+        })?;
+        let imp50 = values.iter().find_map(|v| match v {
+            Value::Impedance50kHz(x) => Some(*x),
+            _ => None,
+        })?;
+        Self::new(imp5, imp50)
+    }
+
+    pub fn values(&self) -> Vec<Value> {
+        vec![
+            Value::Impedance5kHz(self.imp5),
+            Value::Impedance50kHz(self.imp50),
+        ]
+    }
+}
+
+/// A set of formulas estimating body composition of a person from their weight,
+/// age and, if the formula needs it, impedance.
+pub trait BodyCompositionFormula {
+    fn basal_metabolic_rate(&self, person: &Person, age: u8, weight: f64) -> f64;
+
+    fn fat_percentage(
+        &self,
+        person: &Person,
+        age: u8,
+        weight: f64,
+        impedance: Option<Impedance>,
+    ) -> Option<f64>;
+
+    fn water_percentage(
+        &self,
+        _person: &Person,
+        _age: u8,
+        _weight: f64,
+        _impedance: Option<Impedance>,
+    ) -> Option<f64> {
+        None
+    }
+
+    fn muscle_percentage(
+        &self,
+        _person: &Person,
+        _age: u8,
+        _weight: f64,
+        _impedance: Option<Impedance>,
+    ) -> Option<f64> {
+        None
+    }
+
+    /// Computes all values this formula can estimate. Lean body mass and fat free
+    /// mass index are computed whenever fat percentage is known.
+    fn derive_values(
+        &self,
+        person: &Person,
+        age: u8,
+        weight: f64,
+        impedance: Option<Impedance>,
+    ) -> Vec<Value> {
+        let mut values = vec![
+            Value::BodyMassIndex(weight / person.height_m().powf(2.0)),
+            Value::BasalMetabolicRate(self.basal_metabolic_rate(person, age, weight)),
+        ];
+        let fat = self.fat_percentage(person, age, weight, impedance);
+        values.extend(fat.map(Value::FatPercent));
+        values.extend(
+            self.water_percentage(person, age, weight, impedance)
+                .map(Value::WaterPercent),
+        );
+        values.extend(
+            self.muscle_percentage(person, age, weight, impedance)
+                .map(Value::MusclePercent),
+        );
+        if let Some(fat) = fat {
+            let lean_body_mass = weight * (1.0 - fat / 100.0);
+            values.push(Value::LeanBodyMass(lean_body_mass));
+            values.push(Value::FatFreeMassIndex(
+                lean_body_mass / person.height_m().powf(2.0),
+            ));
+        }
+        values
+    }
+}
+
+/// Formulas used by Soehnle scales. Fat, water and muscle percentages are only
+/// estimated if impedance is known.
+pub struct Soehnle;
+
+impl BodyCompositionFormula for Soehnle {
+    fn basal_metabolic_rate(&self, person: &Person, age: u8, weight: f64) -> f64 {
+        get_basal_metabolic_rate(person, age, weight)
+    }
+
+    fn fat_percentage(
+        &self,
+        person: &Person,
+        age: u8,
+        weight: f64,
+        impedance: Option<Impedance>,
+    ) -> Option<f64> {
+        impedance.map(|i| get_fat_percentage(person, age, weight, i.imp50))
+    }
+
+    fn water_percentage(
+        &self,
+        person: &Person,
+        age: u8,
+        weight: f64,
+        impedance: Option<Impedance>,
+    ) -> Option<f64> {
+        impedance.map(|i| get_water_percentage(person, age, weight, i.imp50))
+    }
+
+    fn muscle_percentage(
+        &self,
+        person: &Person,
+        age: u8,
+        weight: f64,
+        impedance: Option<Impedance>,
+    ) -> Option<f64> {
+        impedance.map(|i| get_muscle_percentage(person, age, weight, i.imp5, i.imp50))
+    }
+}
+
+/// Formulas that only need weight, height, age and sex, so they also work
+/// for scales that do not measure impedance: Mifflin-St Jeor equation for
+/// basal metabolic rate and Deurenberg equation for body fat percentage.
+pub struct Anthropometric;
+
+impl BodyCompositionFormula for Anthropometric {
+    fn basal_metabolic_rate(&self, person: &Person, age: u8, weight: f64) -> f64 {
+        let sex_correction = if person.is_female() { -161.0 } else { 5.0 };
+        10.0 * weight + 6.25 * person.height_cm as f64 - 5.0 * age as f64 + sex_correction
+    }
+
+    fn fat_percentage(
+        &self,
+        person: &Person,
+        age: u8,
+        weight: f64,
+        _impedance: Option<Impedance>,
+    ) -> Option<f64> {
+        let body_mass_index = weight / person.height_m().powf(2.0);
+        let sex = if person.is_female() { 0.0 } else { 1.0 };
+        Some(1.2 * body_mass_index + 0.23 * age as f64 - 10.8 * sex - 5.4)
+    }
+}
+
+/// Set of formulas chosen for a person.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub enum FormulaSet {
+    #[default]
+    Soehnle,
+    Anthropometric,
+}
+
+impl FormulaSet {
+    pub fn formula(self) -> &'static dyn BodyCompositionFormula {
+        match self {
+            FormulaSet::Soehnle => &Soehnle,
+            FormulaSet::Anthropometric => &Anthropometric,
+        }
+    }
+}
+
+/// Computes values derived from weight of a person at given age, using
+/// the formula set chosen for that person.
+pub fn derive_values(
+    person: &Person,
+    age: u8,
+    weight: f64,
+    impedance: Option<Impedance>,
+) -> Vec<Value> {
+    person
+        .formula
+        .formula()
+        .derive_values(person, age, weight, impedance)
+}
+
+fn get_water_percentage(person: &Person, age: u8, weight: f64, imp50: f64) -> f64 {
+    let activity_correction_factor = match (person.activity_level, person.is_female()) {
+        (1..=3, true) => 0.0,
+        (1..=3, false) => 2.83,
+        (4, true) => 0.4,
+        (4, false) => 3.93,
+        (5, true) => 1.4,
+        (5, false) => 5.33,
+        _ => 0.0,
+    };
+
+    (0.3674 * (person.height_cm as f64).powf(2.0) / imp50 + 0.17530 * weight - 0.11 * age as f64
+        + (6.53 + activity_correction_factor))
+        / weight
+        * 100.0
+}
+
+fn get_muscle_percentage(person: &Person, age: u8, weight: f64, imp5: f64, imp50: f64) -> f64 {
+    let activity_correction_factor = match (person.activity_level, person.is_female()) {
+        (1..=3, true) => 0.0,
+        (1..=3, false) => 3.6224,
+        (4, true) => 0.0,
+        (4, false) => 4.3904,
+        (5, true) => 1.664,
+        (5, false) => 5.4144,
+        _ => 0.0,
+    };
+    ((0.47027 / imp50 - 0.24196 / imp5) * (person.height_cm as f64).powf(2.0) + 0.13796 * weight
+        - 0.1152 * age as f64
+        + (5.12 + activity_correction_factor))
+        / weight
+        * 100.0
+}
+
+fn get_fat_percentage(person: &Person, age: u8, weight: f64, imp50: f64) -> f64 {
+    let activity_correction_factor = match (person.activity_level, person.is_female()) {
+        (4, true) => 2.3,
+        (4, false) => 2.5,
+        (5, true) => 4.1,
+        (5, false) => 4.3,
+        _ => 0.0,
+    };
+
+    let (sex_correction_factor, activity_sex_div) = if person.is_female() {
+        (0.214, 55.1)
+    } else {
+        (0.250, 65.5)
+    };
+
+    1.847 * weight / person.height_m().powf(2.0)
+        + sex_correction_factor * age as f64
+        + 0.062 * imp50
+        - (activity_sex_div - activity_correction_factor)
+}
+
+fn get_basal_metabolic_rate(person: &Person, age: u8, weight: f64) -> f64 {
+    if person.is_female() {
+        447.593 + 9.247 * weight + 3.098 * person.height_cm as f64 - 4.330 * age as f64
+    } else {
+        88.362 + 13.397 * weight + 4.799 * person.height_cm as f64 - 5.677 * age as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::{measurement::ValueType, person::Sex};
+
+    fn person() -> Person {
+        Person {
+            name: "Test".into(),
+            birth_date: NaiveDate::from_ymd_opt(1990, 1, 1).unwrap(),
+            sex: Sex::Male,
+            height_cm: 180,
+            activity_level: 3,
+            formula: FormulaSet::Soehnle,
+            goal_weight_kg: None,
+        }
+    }
+
+    #[test]
+    fn ignores_implausible_impedance() {
+        assert_eq!(Impedance::new(500.0, 0.0), None);
+        assert_eq!(Impedance::new(500.0, 1700.0), None);
+        assert_eq!(
+            Impedance::from_values(&[
+                Value::Weight(80.0),
+                Value::Impedance5kHz(520.0),
+                Value::Impedance50kHz(450.0),
+            ]),
+            Some(Impedance {
+                imp5: 520.0,
+                imp50: 450.0
+            })
+        );
+        assert_eq!(Impedance::from_values(&[Value::Impedance5kHz(520.0)]), None);
+    }
+
+    #[test]
+    fn derives_composition_only_with_impedance() {
+        let types = |values: Vec<Value>| values.iter().map(Value::value_type).collect::<Vec<_>>();
+
+        assert_eq!(
+            types(derive_values(&person(), 34, 80.0, None)),
+            vec![ValueType::BodyMassIndex, ValueType::BasalMetabolicRate]
+        );
+        assert_eq!(
+            types(derive_values(
+                &person(),
+                34,
+                80.0,
+                Impedance::new(520.0, 450.0)
+            )),
+            vec![
+                ValueType::BodyMassIndex,
+                ValueType::BasalMetabolicRate,
+                ValueType::FatPercent,
+                ValueType::WaterPercent,
+                ValueType::MusclePercent,
+                ValueType::LeanBodyMass,
+                ValueType::FatFreeMassIndex,
+            ]
+        );
+    }
+
+    #[test]
+    fn body_mass_index_depends_on_height() {
+        let mut person = person();
+        let Value::BodyMassIndex(bmi) = derive_values(&person, 34, 81.0, None)[0] else {
+            panic!("Expected body mass index");
+        };
+        assert!((bmi - 25.0).abs() < 1e-9);
+
+        person.height_cm = 90;
+        let Value::BodyMassIndex(bmi) = derive_values(&person, 34, 81.0, None)[0] else {
+            panic!("Expected body mass index");
+        };
+        assert!((bmi - 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn uses_formula_set_of_person() {
+        let person = Person {
+            formula: FormulaSet::Anthropometric,
+            goal_weight_kg: None,
+            ..person()
+        };
+
+        let values = derive_values(&person, 34, 80.0, None);
+
+        assert_eq!(values[1], Value::BasalMetabolicRate(1760.0));
+        let Value::FatPercent(fat) = values[2] else {
+            panic!("Expected fat percentage");
+        };
+        assert!((fat - 21.2496).abs() < 1e-3);
+        assert_eq!(
+            values.iter().map(Value::value_type).collect::<Vec<_>>(),
+            vec![
+                ValueType::BodyMassIndex,
+                ValueType::BasalMetabolicRate,
+                ValueType::FatPercent,
+                ValueType::LeanBodyMass,
+                ValueType::FatFreeMassIndex,
+            ]
+        );
+    }
+}
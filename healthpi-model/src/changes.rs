@@ -0,0 +1,36 @@
+use crate::measurement::{Record, RecordId};
+
+/// Position in the sequence of changes to stored records. Whenever a record
+/// or any of its values changes, it moves to the end of the sequence.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct ChangeCursor(i64);
+
+impl ChangeCursor {
+    /// Position before any changes, i.e. one to fetch all records from.
+    pub const START: ChangeCursor = ChangeCursor(0);
+
+    pub fn new(position: i64) -> Self {
+        Self(position)
+    }
+
+    pub fn value(&self) -> i64 {
+        self.0
+    }
+}
+
+/// Records changed since some point in the sequence of changes.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct Changes {
+    pub records: Vec<Record>,
+    /// Records deleted since that point.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub deleted: Vec<RecordId>,
+    /// Position to continue from, in order to fetch the following changes.
+    pub next_cursor: ChangeCursor,
+    /// Whether there are more changes to fetch, past the next cursor.
+    pub has_more: bool,
+}
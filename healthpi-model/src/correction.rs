@@ -0,0 +1,53 @@
+use chrono::{DateTime, Utc};
+
+use crate::{
+    measurement::{Value, ValueType},
+    person::PersonId,
+};
+
+/// Changes to a stored record. Its time and source cannot be changed,
+/// since they identify the measurement.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde_with::serde_as)]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct RecordPatch {
+    /// Values to add, replacing values of the same type.
+    #[cfg_attr(feature = "serde", serde(default))]
+    #[cfg_attr(feature = "serde", serde_as(as = "EnumMap"))]
+    pub values: Vec<Value>,
+    /// Types of values to remove.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub remove: Vec<ValueType>,
+    /// Person to attribute the record to.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub person: Option<PersonId>,
+}
+
+impl RecordPatch {
+    pub fn values(mut self, values: &[Value]) -> Self {
+        self.values = values.to_vec();
+        self
+    }
+
+    pub fn remove(mut self, remove: &[ValueType]) -> Self {
+        self.remove = remove.to_vec();
+        self
+    }
+
+    pub fn person(mut self, person: PersonId) -> Self {
+        self.person = Some(person);
+        self
+    }
+}
+
+/// Outcome of deleting a record. Deleted records can be restored for a while.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct Deletion {
+    pub restorable_until: DateTime<Utc>,
+}
@@ -0,0 +1,96 @@
+use std::fmt;
+
+use chrono::{DateTime, FixedOffset, NaiveDate};
+
+use crate::{
+    device::DeviceId,
+    measurement::{SourceKind, ValueType},
+    person::PersonId,
+};
+
+/// Order of records by time of measurement.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum Order {
+    #[cfg_attr(feature = "serde", serde(rename = "asc"))]
+    Ascending,
+    #[default]
+    #[cfg_attr(feature = "serde", serde(rename = "desc"))]
+    Descending,
+}
+
+impl fmt::Display for Order {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Order::Ascending => write!(f, "asc"),
+            Order::Descending => write!(f, "desc"),
+        }
+    }
+}
+
+/// Criteria to select records by. Empty lists and missing bounds do not
+/// restrict the selection.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecordFilter {
+    /// Types of values to include. Records without any of them are left out.
+    pub select: Vec<ValueType>,
+    /// Earliest time of measurement, inclusive.
+    pub from: Option<DateTime<FixedOffset>>,
+    /// Latest time of measurement, exclusive.
+    pub to: Option<DateTime<FixedOffset>>,
+    /// Earliest date of measurement, inclusive, in the offset of the record.
+    pub from_date: Option<NaiveDate>,
+    /// Latest date of measurement, inclusive, in the offset of the record.
+    pub to_date: Option<NaiveDate>,
+    pub sources: Vec<SourceKind>,
+    pub devices: Vec<DeviceId>,
+    pub person: Option<PersonId>,
+    pub order: Order,
+}
+
+impl RecordFilter {
+    pub fn select(mut self, select: &[ValueType]) -> Self {
+        self.select = select.to_vec();
+        self
+    }
+
+    pub fn from(mut self, from: DateTime<FixedOffset>) -> Self {
+        self.from = Some(from);
+        self
+    }
+
+    pub fn to(mut self, to: DateTime<FixedOffset>) -> Self {
+        self.to = Some(to);
+        self
+    }
+
+    pub fn from_date(mut self, from_date: NaiveDate) -> Self {
+        self.from_date = Some(from_date);
+        self
+    }
+
+    pub fn to_date(mut self, to_date: NaiveDate) -> Self {
+        self.to_date = Some(to_date);
+        self
+    }
+
+    pub fn sources(mut self, sources: &[SourceKind]) -> Self {
+        self.sources = sources.to_vec();
+        self
+    }
+
+    pub fn devices(mut self, devices: &[DeviceId]) -> Self {
+        self.devices = devices.to_vec();
+        self
+    }
+
+    pub fn person(mut self, person: PersonId) -> Self {
+        self.person = Some(person);
+        self
+    }
+
+    pub fn order(mut self, order: Order) -> Self {
+        self.order = order;
+        self
+    }
+}
@@ -0,0 +1,249 @@
+//! Summaries of glucose readings over a period of time.
+//!
+//! Readings from a glucometer are taken at irregular times, so shares of time
+//! spent in each range are estimated by shares of readings, and estimates of
+//! HbA1c are less reliable than those computed from continuous monitoring.
+
+use crate::measurement::{MealIndicator, Record, Value};
+
+/// Boundaries of glucose ranges in mg/dL. A reading on a boundary belongs
+/// to the range closer to the target.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct GlucoseThresholds {
+    pub very_low: f64,
+    pub low: f64,
+    pub high: f64,
+    pub very_high: f64,
+}
+
+impl Default for GlucoseThresholds {
+    /// Targets of the international consensus on time in range.
+    fn default() -> Self {
+        Self {
+            very_low: 54.0,
+            low: 70.0,
+            high: 180.0,
+            very_high: 250.0,
+        }
+    }
+}
+
+/// Shares of readings in each range, as fractions adding up to one.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct TimeInRange {
+    pub very_low: f64,
+    pub low: f64,
+    pub in_range: f64,
+    pub high: f64,
+    pub very_high: f64,
+}
+
+impl TimeInRange {
+    fn of(values: &[f64], thresholds: &GlucoseThresholds) -> Self {
+        let share = |predicate: &dyn Fn(f64) -> bool| {
+            values.iter().filter(|x| predicate(**x)).count() as f64 / values.len() as f64
+        };
+        Self {
+            very_low: share(&|x| x < thresholds.very_low),
+            low: share(&|x| (thresholds.very_low..thresholds.low).contains(&x)),
+            in_range: share(&|x| (thresholds.low..=thresholds.high).contains(&x)),
+            high: share(&|x| x > thresholds.high && x <= thresholds.very_high),
+            very_high: share(&|x| x > thresholds.very_high),
+        }
+    }
+}
+
+/// Summary of readings taken in the same relation to a meal.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct MealSummary {
+    pub meal: MealIndicator,
+    pub count: usize,
+    pub mean: f64,
+    pub time_in_range: TimeInRange,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct GlucoseSummary {
+    pub count: usize,
+    pub mean: f64,
+    pub standard_deviation: f64,
+    /// Standard deviation relative to the mean, in percent. Values above 36%
+    /// are considered unstable.
+    pub coefficient_of_variation: f64,
+    /// HbA1c in percent estimated from the mean with the ADAG formula.
+    pub estimated_a1c: f64,
+    /// Glucose management indicator, in percent.
+    pub gmi: f64,
+    pub time_in_range: TimeInRange,
+    /// Summaries for each meal indicator readings were taken with. Readings
+    /// without one are counted as [`MealIndicator::NoIndication`].
+    pub by_meal: Vec<MealSummary>,
+}
+
+/// A glucose reading in mg/dL, along with its relation to a meal.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GlucoseReading {
+    pub glucose: f64,
+    pub meal: MealIndicator,
+}
+
+impl GlucoseReading {
+    /// Takes the glucose reading out of a record, if it has one.
+    pub fn from_record(record: &Record) -> Option<Self> {
+        let glucose = record.values.iter().find_map(|value| match value {
+            Value::Glucose(x) => Some(*x),
+            _ => None,
+        })?;
+        let meal = record
+            .values
+            .iter()
+            .find_map(|value| match value {
+                Value::Meal(meal) => Some(*meal),
+                _ => None,
+            })
+            .unwrap_or(MealIndicator::NoIndication);
+        Some(Self { glucose, meal })
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Summarizes glucose readings, unless there are none.
+pub fn summarize(
+    readings: &[GlucoseReading],
+    thresholds: &GlucoseThresholds,
+) -> Option<GlucoseSummary> {
+    if readings.is_empty() {
+        return None;
+    }
+
+    let values: Vec<_> = readings.iter().map(|r| r.glucose).collect();
+    let mean = mean(&values);
+    let standard_deviation = if values.len() > 1 {
+        (values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64).sqrt()
+    } else {
+        0.0
+    };
+
+    let by_meal = MealIndicator::ALL
+        .iter()
+        .filter_map(|meal| {
+            let values: Vec<_> = readings
+                .iter()
+                .filter(|r| r.meal == *meal)
+                .map(|r| r.glucose)
+                .collect();
+            (!values.is_empty()).then(|| MealSummary {
+                meal: *meal,
+                count: values.len(),
+                mean: self::mean(&values),
+                time_in_range: TimeInRange::of(&values, thresholds),
+            })
+        })
+        .collect();
+
+    Some(GlucoseSummary {
+        count: values.len(),
+        mean,
+        standard_deviation,
+        coefficient_of_variation: standard_deviation / mean * 100.0,
+        estimated_a1c: (mean + 46.7) / 28.7,
+        gmi: 3.31 + 0.02392 * mean,
+        time_in_range: TimeInRange::of(&values, thresholds),
+        by_meal,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading(glucose: f64, meal: MealIndicator) -> GlucoseReading {
+        GlucoseReading { glucose, meal }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-3,
+            "{} is not close to {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn splits_readings_into_ranges() {
+        let readings: Vec<_> = [50.0, 54.0, 70.0, 120.0, 180.0, 200.0, 250.0, 300.0]
+            .into_iter()
+            .map(|x| reading(x, MealIndicator::NoIndication))
+            .collect();
+
+        let summary = summarize(&readings, &GlucoseThresholds::default()).unwrap();
+
+        assert_eq!(
+            summary.time_in_range,
+            TimeInRange {
+                very_low: 0.125,
+                low: 0.125,
+                in_range: 0.375,
+                high: 0.25,
+                very_high: 0.125,
+            }
+        );
+    }
+
+    #[test]
+    fn estimates_a1c_and_variability() {
+        let readings = [
+            reading(100.0, MealIndicator::BeforeMeal),
+            reading(154.0, MealIndicator::AfterMeal),
+            reading(208.0, MealIndicator::AfterMeal),
+        ];
+
+        let summary = summarize(&readings, &GlucoseThresholds::default()).unwrap();
+
+        assert_close(summary.mean, 154.0);
+        assert_close(summary.standard_deviation, 54.0);
+        assert_close(summary.coefficient_of_variation, 35.065);
+        assert_close(summary.estimated_a1c, 6.993);
+        assert_close(summary.gmi, 6.994);
+    }
+
+    #[test]
+    fn breaks_readings_down_by_meal() {
+        let readings = [
+            reading(90.0, MealIndicator::BeforeMeal),
+            reading(150.0, MealIndicator::AfterMeal),
+            reading(210.0, MealIndicator::AfterMeal),
+        ];
+
+        let summary = summarize(&readings, &GlucoseThresholds::default()).unwrap();
+
+        assert_eq!(
+            summary
+                .by_meal
+                .iter()
+                .map(|m| (m.meal, m.count, m.mean, m.time_in_range.in_range))
+                .collect::<Vec<_>>(),
+            vec![
+                (MealIndicator::BeforeMeal, 1, 90.0, 1.0),
+                (MealIndicator::AfterMeal, 2, 180.0, 0.5),
+            ]
+        );
+    }
+
+    #[test]
+    fn does_not_summarize_no_readings() {
+        assert_eq!(summarize(&[], &GlucoseThresholds::default()), None);
+    }
+}
@@ -1,3 +1,15 @@
+pub mod aggregate;
+pub mod alert;
+pub mod blood_pressure;
+pub mod body_composition;
+pub mod changes;
+pub mod correction;
 pub mod device;
+pub mod filter;
+pub mod glucose;
 pub mod measurement;
-pub mod user;
+pub mod person;
+pub mod problem;
+pub mod units;
+pub mod validation;
+pub mod weight_trend;
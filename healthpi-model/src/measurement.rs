@@ -1,35 +1,115 @@
-use chrono::NaiveDateTime;
-use num::FromPrimitive;
-use num_derive::FromPrimitive;
+use std::fmt;
 
-use crate::device::DeviceId;
+use chrono::{DateTime, FixedOffset};
 
-#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
-#[cfg_attr(feature = "serde", derive(serde::Deserialize, strum::EnumString))]
+use crate::{device::DeviceId, person::PersonId};
+
+/// Type of a measured value.
+///
+/// Each type has a numeric code, under which its values are persisted. Codes are
+/// part of the storage format: they must never change, and codes of removed types
+/// must never be reused. New types should get the next unused code, regardless
+/// of where they are declared. Every code also needs a row in the `value_types`
+/// table, added through a migration.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize, strum::EnumString)
+)]
+#[repr(u32)]
 pub enum ValueType {
-    Weight,
-    BodyMassIndex,
-    BasalMetabolicRate,
-    WaterPercent,
-    MusclePercent,
-    FatPercent,
-    Glucose,
-    Meal,
-    BloodPressureSystolic,
-    BloodPressureDiastolic,
-    HeartRate,
-}
-
-#[derive(Debug, FromPrimitive, PartialEq)]
+    Weight = 0,
+    BodyMassIndex = 1,
+    BasalMetabolicRate = 2,
+    WaterPercent = 3,
+    MusclePercent = 4,
+    FatPercent = 5,
+    Glucose = 6,
+    Meal = 7,
+    BloodPressureSystolic = 8,
+    BloodPressureDiastolic = 9,
+    HeartRate = 10,
+    BodyTemperature = 11,
+    OxygenSaturation = 12,
+    RespiratoryRate = 13,
+    Ketones = 14,
+    HbA1c = 15,
+    Steps = 16,
+    Impedance5kHz = 17,
+    Impedance50kHz = 18,
+    LeanBodyMass = 19,
+    FatFreeMassIndex = 20,
+}
+
+impl ValueType {
+    pub const ALL: &'static [ValueType] = &[
+        ValueType::Weight,
+        ValueType::BodyMassIndex,
+        ValueType::BasalMetabolicRate,
+        ValueType::WaterPercent,
+        ValueType::MusclePercent,
+        ValueType::FatPercent,
+        ValueType::Glucose,
+        ValueType::Meal,
+        ValueType::BloodPressureSystolic,
+        ValueType::BloodPressureDiastolic,
+        ValueType::HeartRate,
+        ValueType::BodyTemperature,
+        ValueType::OxygenSaturation,
+        ValueType::RespiratoryRate,
+        ValueType::Ketones,
+        ValueType::HbA1c,
+        ValueType::Steps,
+        ValueType::Impedance5kHz,
+        ValueType::Impedance50kHz,
+        ValueType::LeanBodyMass,
+        ValueType::FatFreeMassIndex,
+    ];
+
+    pub const fn code(self) -> u32 {
+        self as u32
+    }
+
+    pub fn from_code(code: u32) -> Option<Self> {
+        Self::ALL.iter().copied().find(|t| t.code() == code)
+    }
+}
+
+/// Meal context of a glucose measurement.
+///
+/// Persisted as the value of [`ValueType::Meal`] using its code, so the same
+/// rules apply as to [`ValueType`] codes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[repr(u32)]
 pub enum MealIndicator {
-    NoIndication,
-    NoMeal,
-    BeforeMeal,
-    AfterMeal,
+    NoIndication = 0,
+    NoMeal = 1,
+    BeforeMeal = 2,
+    AfterMeal = 3,
 }
 
-#[derive(Debug, PartialEq)]
+impl MealIndicator {
+    pub const ALL: &'static [MealIndicator] = &[
+        MealIndicator::NoIndication,
+        MealIndicator::NoMeal,
+        MealIndicator::BeforeMeal,
+        MealIndicator::AfterMeal,
+    ];
+
+    pub const fn code(self) -> u32 {
+        self as u32
+    }
+
+    pub fn from_code(code: u32) -> Option<Self> {
+        Self::ALL.iter().copied().find(|m| m.code() == code)
+    }
+}
+
+/// A single measured value. Unless stated otherwise, values are expressed in
+/// the canonical units of [`UnitSystem`](crate::units::UnitSystem): kilograms,
+/// mg/dL, mmHg and degrees Celsius.
+#[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
 pub enum Value {
@@ -39,53 +119,119 @@ pub enum Value {
     WaterPercent(f64),
     MusclePercent(f64),
     FatPercent(f64),
-    Glucose(i32),
+    Glucose(f64),
     Meal(MealIndicator),
-    BloodPressureSystolic(i32),
-    BloodPressureDiastolic(i32),
+    BloodPressureSystolic(f64),
+    BloodPressureDiastolic(f64),
     HeartRate(i32),
+    BodyTemperature(f64),
+    /// Blood oxygen saturation (SpO2) in percent.
+    OxygenSaturation(f64),
+    /// Breaths per minute.
+    RespiratoryRate(i32),
+    /// Blood ketones (beta-hydroxybutyrate) in mmol/L.
+    Ketones(f64),
+    /// Glycated hemoglobin in percent (NGSP).
+    HbA1c(f64),
+    Steps(i32),
+    /// Body impedance measured at 5 kHz, in ohms.
+    Impedance5kHz(f64),
+    /// Body impedance measured at 50 kHz, in ohms.
+    Impedance50kHz(f64),
+    /// Weight of everything but body fat.
+    LeanBodyMass(f64),
+    /// Lean body mass divided by square of height, in kg/m².
+    FatFreeMassIndex(f64),
 }
 
-impl From<Value> for (usize, f64) {
-    fn from(val: Value) -> Self {
-        match val {
-            Value::Weight(x) => (ValueType::Weight as usize, x),
-            Value::BodyMassIndex(x) => (ValueType::BodyMassIndex as usize, x),
-            Value::BasalMetabolicRate(x) => (ValueType::BasalMetabolicRate as usize, x),
-            Value::WaterPercent(x) => (ValueType::WaterPercent as usize, x),
-            Value::MusclePercent(x) => (ValueType::MusclePercent as usize, x),
-            Value::FatPercent(x) => (ValueType::FatPercent as usize, x),
-            Value::Glucose(x) => (ValueType::Glucose as usize, x as f64),
-            Value::Meal(x) => (ValueType::Meal as usize, x as u8 as f64),
-            Value::BloodPressureSystolic(x) => {
-                (ValueType::BloodPressureSystolic as usize, x as f64)
-            }
-            Value::BloodPressureDiastolic(x) => {
-                (ValueType::BloodPressureDiastolic as usize, x as f64)
-            }
-            Value::HeartRate(x) => (ValueType::HeartRate as usize, x as f64),
+impl Value {
+    pub fn value_type(&self) -> ValueType {
+        match self {
+            Value::Weight(_) => ValueType::Weight,
+            Value::BodyMassIndex(_) => ValueType::BodyMassIndex,
+            Value::BasalMetabolicRate(_) => ValueType::BasalMetabolicRate,
+            Value::WaterPercent(_) => ValueType::WaterPercent,
+            Value::MusclePercent(_) => ValueType::MusclePercent,
+            Value::FatPercent(_) => ValueType::FatPercent,
+            Value::Glucose(_) => ValueType::Glucose,
+            Value::Meal(_) => ValueType::Meal,
+            Value::BloodPressureSystolic(_) => ValueType::BloodPressureSystolic,
+            Value::BloodPressureDiastolic(_) => ValueType::BloodPressureDiastolic,
+            Value::HeartRate(_) => ValueType::HeartRate,
+            Value::BodyTemperature(_) => ValueType::BodyTemperature,
+            Value::OxygenSaturation(_) => ValueType::OxygenSaturation,
+            Value::RespiratoryRate(_) => ValueType::RespiratoryRate,
+            Value::Ketones(_) => ValueType::Ketones,
+            Value::HbA1c(_) => ValueType::HbA1c,
+            Value::Steps(_) => ValueType::Steps,
+            Value::Impedance5kHz(_) => ValueType::Impedance5kHz,
+            Value::Impedance50kHz(_) => ValueType::Impedance50kHz,
+            Value::LeanBodyMass(_) => ValueType::LeanBodyMass,
+            Value::FatFreeMassIndex(_) => ValueType::FatFreeMassIndex,
+        }
+    }
+
+    /// Numeric representation of the value, as it is persisted.
+    pub fn as_f64(&self) -> f64 {
+        match *self {
+            Value::Weight(x)
+            | Value::BodyMassIndex(x)
+            | Value::BasalMetabolicRate(x)
+            | Value::WaterPercent(x)
+            | Value::MusclePercent(x)
+            | Value::FatPercent(x)
+            | Value::Glucose(x)
+            | Value::BloodPressureSystolic(x)
+            | Value::BloodPressureDiastolic(x)
+            | Value::BodyTemperature(x)
+            | Value::OxygenSaturation(x)
+            | Value::Ketones(x)
+            | Value::HbA1c(x)
+            | Value::Impedance5kHz(x)
+            | Value::Impedance50kHz(x)
+            | Value::LeanBodyMass(x)
+            | Value::FatFreeMassIndex(x) => x,
+            Value::Meal(x) => x.code() as f64,
+            Value::HeartRate(x) | Value::RespiratoryRate(x) | Value::Steps(x) => x as f64,
         }
     }
 }
 
-impl TryFrom<(usize, f64)> for Value {
+impl From<Value> for (u32, f64) {
+    fn from(val: Value) -> Self {
+        (val.value_type().code(), val.as_f64())
+    }
+}
+
+impl TryFrom<(u32, f64)> for Value {
     type Error = &'static str;
 
-    fn try_from((index, x): (usize, f64)) -> Result<Self, Self::Error> {
-        match ValueType::from_usize(index) {
+    fn try_from((code, x): (u32, f64)) -> Result<Self, Self::Error> {
+        match ValueType::from_code(code) {
             Some(ValueType::Weight) => Ok(Value::Weight(x)),
             Some(ValueType::BodyMassIndex) => Ok(Value::BodyMassIndex(x)),
             Some(ValueType::BasalMetabolicRate) => Ok(Value::BasalMetabolicRate(x)),
             Some(ValueType::WaterPercent) => Ok(Value::WaterPercent(x)),
             Some(ValueType::MusclePercent) => Ok(Value::MusclePercent(x)),
             Some(ValueType::FatPercent) => Ok(Value::FatPercent(x)),
-            Some(ValueType::Glucose) => Ok(Value::Glucose(x as i32)),
-            Some(ValueType::Meal) => num::FromPrimitive::from_f64(x)
+            Some(ValueType::Glucose) => Ok(Value::Glucose(x)),
+            Some(ValueType::Meal) => MealIndicator::from_code(x as u32)
+                .filter(|_| x.fract() == 0.0 && x >= 0.0)
                 .map(Value::Meal)
                 .ok_or("Invalid meal indicator"),
-            Some(ValueType::BloodPressureSystolic) => Ok(Value::BloodPressureSystolic(x as i32)),
-            Some(ValueType::BloodPressureDiastolic) => Ok(Value::BloodPressureDiastolic(x as i32)),
+            Some(ValueType::BloodPressureSystolic) => Ok(Value::BloodPressureSystolic(x)),
+            Some(ValueType::BloodPressureDiastolic) => Ok(Value::BloodPressureDiastolic(x)),
             Some(ValueType::HeartRate) => Ok(Value::HeartRate(x as i32)),
+            Some(ValueType::BodyTemperature) => Ok(Value::BodyTemperature(x)),
+            Some(ValueType::OxygenSaturation) => Ok(Value::OxygenSaturation(x)),
+            Some(ValueType::RespiratoryRate) => Ok(Value::RespiratoryRate(x as i32)),
+            Some(ValueType::Ketones) => Ok(Value::Ketones(x)),
+            Some(ValueType::HbA1c) => Ok(Value::HbA1c(x)),
+            Some(ValueType::Steps) => Ok(Value::Steps(x as i32)),
+            Some(ValueType::Impedance5kHz) => Ok(Value::Impedance5kHz(x)),
+            Some(ValueType::Impedance50kHz) => Ok(Value::Impedance50kHz(x)),
+            Some(ValueType::LeanBodyMass) => Ok(Value::LeanBodyMass(x)),
+            Some(ValueType::FatFreeMassIndex) => Ok(Value::FatFreeMassIndex(x)),
             None => Err("Invalid value type"),
         }
     }
@@ -95,32 +241,110 @@ impl TryFrom<(usize, f64)> for Value {
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub enum Source {
     Device(DeviceId),
+    /// Entered by hand, e.g. read off a device that cannot be connected to.
+    Manual {
+        entered_by: String,
+    },
+    /// Imported from a file, e.g. an export of another application.
+    Import {
+        importer: String,
+        file: String,
+    },
     Unknown(String),
 }
 
-#[derive(Debug, PartialEq)]
+impl Source {
+    pub fn kind(&self) -> SourceKind {
+        match self {
+            Source::Device(_) => SourceKind::Device,
+            Source::Manual { .. } => SourceKind::Manual,
+            Source::Import { .. } => SourceKind::Import,
+            Source::Unknown(_) => SourceKind::Unknown,
+        }
+    }
+}
+
+/// Kind of a [`Source`], regardless of its details.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize, strum::EnumString)
+)]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+#[cfg_attr(
+    feature = "serde",
+    strum(serialize_all = "camelCase", ascii_case_insensitive)
+)]
+pub enum SourceKind {
+    Device,
+    Manual,
+    Import,
+    Unknown,
+}
+
+/// Identifier of a stored record. Unlike the time and source of a record,
+/// it never changes and is not reused after the record is deleted.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct RecordId(i64);
+
+impl RecordId {
+    pub fn new(id: i64) -> Self {
+        Self(id)
+    }
+
+    pub fn value(&self) -> i64 {
+        self.0
+    }
+}
+
+impl fmt::Display for RecordId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 #[cfg_attr(feature = "serde", serde_with::serde_as)]
 pub struct Record {
-    pub timestamp: NaiveDateTime,
+    /// Identifier assigned when the record is stored, if it has been.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub id: Option<RecordId>,
+    /// Time of the measurement, in the local time of the device that took it,
+    /// along with the UTC offset that applied at the time.
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "deserialize_timestamp"))]
+    pub timestamp: DateTime<FixedOffset>,
     #[cfg_attr(feature = "serde", serde_as(as = "EnumMap"))]
     pub values: Vec<Value>,
     pub raw_data: Vec<u8>,
     pub source: Source,
+    /// Person the measurement was taken of, if known.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub person: Option<PersonId>,
 }
 
 impl Record {
     pub fn new(
-        timestamp: NaiveDateTime,
+        timestamp: DateTime<FixedOffset>,
         values: Vec<Value>,
         raw_data: Vec<u8>,
         source: Source,
     ) -> Self {
         Self {
+            id: None,
             timestamp,
             values,
             raw_data,
             source,
+            person: None,
         }
     }
 
@@ -128,3 +352,152 @@ impl Record {
         self.values.push(value)
     }
 }
+
+/// Accepts timestamps with an explicit offset, as well as timestamps without one
+/// for compatibility with older clients. The latter are assumed to be in UTC,
+/// which is how they used to be stored.
+#[cfg(feature = "serde")]
+fn deserialize_timestamp<'de, D>(deserializer: D) -> Result<DateTime<FixedOffset>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::{de, Deserialize};
+
+    let s = String::deserialize(deserializer)?;
+    s.parse::<DateTime<FixedOffset>>()
+        .or_else(|_| {
+            s.parse::<chrono::NaiveDateTime>()
+                .map(|t| t.and_utc().fixed_offset())
+        })
+        .map_err(de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn value_type_codes_are_stable() {
+        let codes: Vec<_> = ValueType::ALL.iter().map(|t| (*t, t.code())).collect();
+
+        // Changing any of these breaks existing databases. Only append.
+        assert_eq!(
+            codes,
+            vec![
+                (ValueType::Weight, 0),
+                (ValueType::BodyMassIndex, 1),
+                (ValueType::BasalMetabolicRate, 2),
+                (ValueType::WaterPercent, 3),
+                (ValueType::MusclePercent, 4),
+                (ValueType::FatPercent, 5),
+                (ValueType::Glucose, 6),
+                (ValueType::Meal, 7),
+                (ValueType::BloodPressureSystolic, 8),
+                (ValueType::BloodPressureDiastolic, 9),
+                (ValueType::HeartRate, 10),
+                (ValueType::BodyTemperature, 11),
+                (ValueType::OxygenSaturation, 12),
+                (ValueType::RespiratoryRate, 13),
+                (ValueType::Ketones, 14),
+                (ValueType::HbA1c, 15),
+                (ValueType::Steps, 16),
+                (ValueType::Impedance5kHz, 17),
+                (ValueType::Impedance50kHz, 18),
+                (ValueType::LeanBodyMass, 19),
+                (ValueType::FatFreeMassIndex, 20),
+            ]
+        );
+    }
+
+    #[test]
+    fn codes_are_unique_and_round_trip() {
+        let value_type_codes: HashSet<_> = ValueType::ALL.iter().map(|t| t.code()).collect();
+        assert_eq!(value_type_codes.len(), ValueType::ALL.len());
+        for value_type in ValueType::ALL {
+            assert_eq!(ValueType::from_code(value_type.code()), Some(*value_type));
+        }
+
+        let meal_codes: HashSet<_> = MealIndicator::ALL.iter().map(|m| m.code()).collect();
+        assert_eq!(meal_codes.len(), MealIndicator::ALL.len());
+        for meal in MealIndicator::ALL {
+            assert_eq!(MealIndicator::from_code(meal.code()), Some(*meal));
+        }
+    }
+
+    #[test]
+    fn all_lists_every_variant() {
+        // Adding a variant fails to compile here until it is matched, which
+        // is the time to bump the count. Codes are unique, so `ALL` then has
+        // to list the new variant for the assertions to pass.
+        let value_types = |value_type| match value_type {
+            ValueType::Weight
+            | ValueType::BodyMassIndex
+            | ValueType::BasalMetabolicRate
+            | ValueType::WaterPercent
+            | ValueType::MusclePercent
+            | ValueType::FatPercent
+            | ValueType::Glucose
+            | ValueType::Meal
+            | ValueType::BloodPressureSystolic
+            | ValueType::BloodPressureDiastolic
+            | ValueType::HeartRate
+            | ValueType::BodyTemperature
+            | ValueType::OxygenSaturation
+            | ValueType::RespiratoryRate
+            | ValueType::Ketones
+            | ValueType::HbA1c
+            | ValueType::Steps
+            | ValueType::Impedance5kHz
+            | ValueType::Impedance50kHz
+            | ValueType::LeanBodyMass
+            | ValueType::FatFreeMassIndex => 21,
+        };
+        let meals = |meal| match meal {
+            MealIndicator::NoIndication
+            | MealIndicator::NoMeal
+            | MealIndicator::BeforeMeal
+            | MealIndicator::AfterMeal => 4,
+        };
+
+        assert_eq!(ValueType::ALL.len(), value_types(ValueType::Weight));
+        assert_eq!(MealIndicator::ALL.len(), meals(MealIndicator::NoIndication));
+    }
+
+    #[test]
+    fn values_round_trip_through_codes() {
+        let (code, x): (u32, f64) = Value::Meal(MealIndicator::AfterMeal).into();
+
+        assert_eq!((code, x), (7, 3.0));
+        assert_eq!(
+            Value::try_from((code, x)),
+            Ok(Value::Meal(MealIndicator::AfterMeal))
+        );
+        assert!(Value::try_from((7, 3.5)).is_err());
+        assert!(Value::try_from((u32::MAX, 1.0)).is_err());
+    }
+
+    #[test]
+    fn sources_have_kinds() {
+        assert_eq!(
+            Source::Device(DeviceId::new("12:34:56:78:9A:BC".into())).kind(),
+            SourceKind::Device
+        );
+        assert_eq!(
+            Source::Manual {
+                entered_by: "Ann".into()
+            }
+            .kind(),
+            SourceKind::Manual
+        );
+        assert_eq!(
+            Source::Import {
+                importer: "csv".into(),
+                file: "export.csv".into()
+            }
+            .kind(),
+            SourceKind::Import
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use std::fmt;
+
+use chrono::{Datelike, NaiveDate};
+
+use crate::body_composition::FormulaSet;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct PersonId(i64);
+
+impl PersonId {
+    pub fn new(id: i64) -> Self {
+        Self(id)
+    }
+
+    pub fn value(&self) -> i64 {
+        self.0
+    }
+}
+
+impl fmt::Display for PersonId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub enum Sex {
+    Female,
+    Male,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct Person {
+    pub name: String,
+    pub birth_date: NaiveDate,
+    pub sex: Sex,
+    pub height_cm: u16,
+    /// Activity level on a scale from 1 (sedentary) to 5 (very active).
+    pub activity_level: u8,
+    /// Formulas used to estimate body composition of the person.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub formula: FormulaSet,
+    /// Weight the person aims to reach.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub goal_weight_kg: Option<f64>,
+}
+
+impl Person {
+    pub fn age_at(&self, date: NaiveDate) -> u8 {
+        date.years_since(self.birth_date).unwrap_or(0) as u8
+    }
+
+    pub fn is_female(&self) -> bool {
+        self.sex == Sex::Female
+    }
+
+    pub fn height_m(&self) -> f64 {
+        self.height_cm as f64 / 100.0
+    }
+
+    /// Estimates birth date of a person, when only their age on given day is known,
+    /// e.g. because that is all a device reports.
+    pub fn estimate_birth_date(age: u8, today: NaiveDate) -> NaiveDate {
+        let year = today.year() - age as i32;
+        today
+            .with_year(year)
+            // February 29th in a year that is not a leap year.
+            .or_else(|| NaiveDate::from_ymd_opt(year, 2, 28))
+            .unwrap_or(today)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn age_changes_on_birthday() {
+        let person = Person {
+            name: "Test".into(),
+            birth_date: date(1990, 6, 15),
+            sex: Sex::Female,
+            height_cm: 170,
+            activity_level: 3,
+            formula: FormulaSet::Soehnle,
+            goal_weight_kg: None,
+        };
+
+        assert_eq!(person.age_at(date(2024, 6, 14)), 33);
+        assert_eq!(person.age_at(date(2024, 6, 15)), 34);
+        assert_eq!(person.age_at(date(1980, 1, 1)), 0);
+    }
+
+    #[test]
+    fn estimated_birth_date_gives_reported_age() {
+        assert_eq!(
+            Person::estimate_birth_date(29, date(2024, 2, 29)),
+            date(1995, 2, 28)
+        );
+        let birth_date = Person::estimate_birth_date(34, date(2024, 6, 15));
+        assert_eq!(date(2024, 6, 15).years_since(birth_date), Some(34));
+    }
+}
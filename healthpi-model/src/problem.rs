@@ -0,0 +1,91 @@
+//! Errors reported by the API, in responses with `application/problem+json`
+//! content type.
+
+use std::fmt;
+
+use crate::validation::Issue;
+
+/// Kind of a problem, telling clients how to react to it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ProblemCode {
+    /// The request is malformed, or its parameters are out of range.
+    InvalidRequest,
+    NotFound,
+    /// Records refer to persons that do not exist.
+    UnknownPerson,
+    /// Values are impossible, so they cannot be stored.
+    InvalidValues,
+    /// The server failed to handle a valid request.
+    Internal,
+    /// A code introduced by a newer version of the API.
+    #[cfg_attr(feature = "serde", serde(other))]
+    Unknown,
+}
+
+impl fmt::Display for ProblemCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProblemCode::InvalidRequest => write!(f, "invalid_request"),
+            ProblemCode::NotFound => write!(f, "not_found"),
+            ProblemCode::UnknownPerson => write!(f, "unknown_person"),
+            ProblemCode::InvalidValues => write!(f, "invalid_values"),
+            ProblemCode::Internal => write!(f, "internal"),
+            ProblemCode::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// Problem with a single submitted record.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct RecordProblem {
+    /// Position of the record in the submitted list. Missing if a single
+    /// record was submitted.
+    #[cfg_attr(feature = "serde", serde(default))]
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub index: Option<usize>,
+    pub message: String,
+    #[cfg_attr(feature = "serde", serde(default))]
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub issues: Vec<Issue>,
+}
+
+/// Body of an error response of the API.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct Problem {
+    pub code: ProblemCode,
+    /// Description of the problem, meant for people rather than programs.
+    pub message: String,
+    /// Problems with particular records, if they were the cause.
+    #[cfg_attr(feature = "serde", serde(default))]
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub records: Vec<RecordProblem>,
+}
+
+impl Problem {
+    pub fn new(code: ProblemCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            records: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.code)?;
+        for record in &self.records {
+            match record.index {
+                Some(index) => write!(f, "; record #{index}: {}", record.message)?,
+                None => write!(f, "; {}", record.message)?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,421 @@
+use std::{fmt, str::FromStr};
+
+use crate::measurement::{Value, ValueType};
+
+/// A unit of measurement that can be converted to and from the canonical
+/// unit of its quantity, which is the unit values are stored in.
+pub trait Unit: Copy + fmt::Display + FromStr {
+    const CANONICAL: Self;
+
+    /// How many canonical units one of this unit is worth.
+    fn factor(self) -> f64;
+
+    /// Value in canonical units corresponding to zero of this unit,
+    /// for units whose scales do not share the zero point.
+    fn offset(self) -> f64 {
+        0.0
+    }
+
+    fn to_canonical(self, x: f64) -> f64 {
+        x * self.factor() + self.offset()
+    }
+
+    fn canonical_to(self, x: f64) -> f64 {
+        (x - self.offset()) / self.factor()
+    }
+
+    fn convert(self, x: f64, to: Self) -> f64 {
+        to.canonical_to(self.to_canonical(x))
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_with::DeserializeFromStr, serde_with::SerializeDisplay)
+)]
+pub enum WeightUnit {
+    #[default]
+    Kilogram,
+    Pound,
+}
+
+impl Unit for WeightUnit {
+    const CANONICAL: Self = Self::Kilogram;
+
+    fn factor(self) -> f64 {
+        match self {
+            Self::Kilogram => 1.0,
+            Self::Pound => 0.453_592_37,
+        }
+    }
+}
+
+impl fmt::Display for WeightUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Kilogram => write!(f, "kg"),
+            Self::Pound => write!(f, "lb"),
+        }
+    }
+}
+
+impl FromStr for WeightUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "kg" => Ok(Self::Kilogram),
+            "lb" | "lbs" => Ok(Self::Pound),
+            _ => Err(format!("Invalid weight unit: {}", s)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_with::DeserializeFromStr, serde_with::SerializeDisplay)
+)]
+pub enum GlucoseUnit {
+    #[default]
+    MilligramsPerDecilitre,
+    MillimolesPerLitre,
+}
+
+impl Unit for GlucoseUnit {
+    const CANONICAL: Self = Self::MilligramsPerDecilitre;
+
+    fn factor(self) -> f64 {
+        match self {
+            Self::MilligramsPerDecilitre => 1.0,
+            // Molar mass of glucose is 180.156 g/mol.
+            Self::MillimolesPerLitre => 18.0156,
+        }
+    }
+}
+
+impl fmt::Display for GlucoseUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MilligramsPerDecilitre => write!(f, "mg/dL"),
+            Self::MillimolesPerLitre => write!(f, "mmol/L"),
+        }
+    }
+}
+
+impl FromStr for GlucoseUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mg/dL" => Ok(Self::MilligramsPerDecilitre),
+            "mmol/L" => Ok(Self::MillimolesPerLitre),
+            _ => Err(format!("Invalid glucose unit: {}", s)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_with::DeserializeFromStr, serde_with::SerializeDisplay)
+)]
+pub enum PressureUnit {
+    #[default]
+    MillimetreOfMercury,
+    Kilopascal,
+}
+
+impl Unit for PressureUnit {
+    const CANONICAL: Self = Self::MillimetreOfMercury;
+
+    fn factor(self) -> f64 {
+        match self {
+            Self::MillimetreOfMercury => 1.0,
+            Self::Kilopascal => 7.500_616_827,
+        }
+    }
+}
+
+impl fmt::Display for PressureUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MillimetreOfMercury => write!(f, "mmHg"),
+            Self::Kilopascal => write!(f, "kPa"),
+        }
+    }
+}
+
+impl FromStr for PressureUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mmHg" => Ok(Self::MillimetreOfMercury),
+            "kPa" => Ok(Self::Kilopascal),
+            _ => Err(format!("Invalid pressure unit: {}", s)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_with::DeserializeFromStr, serde_with::SerializeDisplay)
+)]
+pub enum TemperatureUnit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+}
+
+impl Unit for TemperatureUnit {
+    const CANONICAL: Self = Self::Celsius;
+
+    fn factor(self) -> f64 {
+        match self {
+            Self::Celsius => 1.0,
+            Self::Fahrenheit => 5.0 / 9.0,
+        }
+    }
+
+    fn offset(self) -> f64 {
+        match self {
+            Self::Celsius => 0.0,
+            Self::Fahrenheit => -32.0 * 5.0 / 9.0,
+        }
+    }
+}
+
+impl fmt::Display for TemperatureUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Celsius => write!(f, "°C"),
+            Self::Fahrenheit => write!(f, "°F"),
+        }
+    }
+}
+
+impl FromStr for TemperatureUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "°C" | "degC" => Ok(Self::Celsius),
+            "°F" | "degF" => Ok(Self::Fahrenheit),
+            _ => Err(format!("Invalid temperature unit: {}", s)),
+        }
+    }
+}
+
+/// Units to express each convertible quantity in. The default unit system
+/// is the canonical one, in which values are stored.
+///
+/// Parses from a comma separated list of unit symbols, e.g. `lb,mmol/L`.
+/// Quantities without a unit on the list are left in their canonical unit.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_with::DeserializeFromStr, serde_with::SerializeDisplay)
+)]
+pub struct UnitSystem {
+    pub weight: WeightUnit,
+    pub glucose: GlucoseUnit,
+    pub pressure: PressureUnit,
+    pub temperature: TemperatureUnit,
+}
+
+impl UnitSystem {
+    pub fn canonical() -> Self {
+        Self::default()
+    }
+
+    /// Symbol of the unit values of given type are expressed in, if the type
+    /// has a unit at all.
+    pub fn unit_symbol(&self, value_type: ValueType) -> Option<String> {
+        match value_type {
+            ValueType::Weight | ValueType::LeanBodyMass => Some(self.weight.to_string()),
+            ValueType::Glucose => Some(self.glucose.to_string()),
+            ValueType::BloodPressureSystolic | ValueType::BloodPressureDiastolic => {
+                Some(self.pressure.to_string())
+            }
+            ValueType::BasalMetabolicRate => Some("kcal".into()),
+            ValueType::WaterPercent | ValueType::MusclePercent | ValueType::FatPercent => {
+                Some("%".into())
+            }
+            ValueType::BodyTemperature => Some(self.temperature.to_string()),
+            ValueType::HeartRate => Some("bpm".into()),
+            ValueType::OxygenSaturation | ValueType::HbA1c => Some("%".into()),
+            ValueType::RespiratoryRate => Some("/min".into()),
+            ValueType::Ketones => Some("mmol/L".into()),
+            ValueType::Impedance5kHz | ValueType::Impedance50kHz => Some("Ω".into()),
+            ValueType::BodyMassIndex
+            | ValueType::FatFreeMassIndex
+            | ValueType::Meal
+            | ValueType::Steps => None,
+        }
+    }
+
+    /// Converts a value expressed in this unit system to the canonical one.
+    pub fn to_canonical(&self, value: Value) -> Value {
+        match value {
+            Value::Weight(x) => Value::Weight(self.weight.to_canonical(x)),
+            Value::LeanBodyMass(x) => Value::LeanBodyMass(self.weight.to_canonical(x)),
+            Value::Glucose(x) => Value::Glucose(self.glucose.to_canonical(x)),
+            Value::BloodPressureSystolic(x) => {
+                Value::BloodPressureSystolic(self.pressure.to_canonical(x))
+            }
+            Value::BloodPressureDiastolic(x) => {
+                Value::BloodPressureDiastolic(self.pressure.to_canonical(x))
+            }
+            Value::BodyTemperature(x) => Value::BodyTemperature(self.temperature.to_canonical(x)),
+            value => value,
+        }
+    }
+
+    /// Converts a value expressed in the canonical unit system to this one.
+    pub fn canonical_to(&self, value: Value) -> Value {
+        match value {
+            Value::Weight(x) => Value::Weight(self.weight.canonical_to(x)),
+            Value::LeanBodyMass(x) => Value::LeanBodyMass(self.weight.canonical_to(x)),
+            Value::Glucose(x) => Value::Glucose(self.glucose.canonical_to(x)),
+            Value::BloodPressureSystolic(x) => {
+                Value::BloodPressureSystolic(self.pressure.canonical_to(x))
+            }
+            Value::BloodPressureDiastolic(x) => {
+                Value::BloodPressureDiastolic(self.pressure.canonical_to(x))
+            }
+            Value::BodyTemperature(x) => Value::BodyTemperature(self.temperature.canonical_to(x)),
+            value => value,
+        }
+    }
+
+    /// Converts an amount of given type expressed in the canonical unit system
+    /// to this one. An amount is a bare number, not wrapped in a [`Value`],
+    /// such as an average or a threshold, so its type is given separately.
+    pub fn amount_canonical_to(&self, value_type: ValueType, x: f64) -> f64 {
+        match value_type {
+            ValueType::Weight | ValueType::LeanBodyMass => self.weight.canonical_to(x),
+            ValueType::Glucose => self.glucose.canonical_to(x),
+            ValueType::BloodPressureSystolic | ValueType::BloodPressureDiastolic => {
+                self.pressure.canonical_to(x)
+            }
+            ValueType::BodyTemperature => self.temperature.canonical_to(x),
+            ValueType::BodyMassIndex
+            | ValueType::BasalMetabolicRate
+            | ValueType::WaterPercent
+            | ValueType::MusclePercent
+            | ValueType::FatPercent
+            | ValueType::Meal
+            | ValueType::HeartRate
+            | ValueType::OxygenSaturation
+            | ValueType::RespiratoryRate
+            | ValueType::Ketones
+            | ValueType::HbA1c
+            | ValueType::Steps
+            | ValueType::Impedance5kHz
+            | ValueType::Impedance50kHz
+            | ValueType::FatFreeMassIndex => x,
+        }
+    }
+}
+
+impl fmt::Display for UnitSystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{},{},{},{}",
+            self.weight, self.glucose, self.pressure, self.temperature
+        )
+    }
+}
+
+impl FromStr for UnitSystem {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut units = Self::canonical();
+        for symbol in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            if let Ok(unit) = symbol.parse() {
+                units.weight = unit;
+            } else if let Ok(unit) = symbol.parse() {
+                units.glucose = unit;
+            } else if let Ok(unit) = symbol.parse() {
+                units.pressure = unit;
+            } else if let Ok(unit) = symbol.parse() {
+                units.temperature = unit;
+            } else {
+                return Err(format!("Invalid unit: {}", symbol));
+            }
+        }
+        Ok(units)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-3,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn converts_between_units() {
+        assert_close(
+            WeightUnit::Pound.convert(220.0, WeightUnit::Kilogram),
+            99.790,
+        );
+        assert_close(
+            GlucoseUnit::MilligramsPerDecilitre.convert(90.0, GlucoseUnit::MillimolesPerLitre),
+            4.996,
+        );
+        assert_close(
+            PressureUnit::Kilopascal.convert(17.1, PressureUnit::MillimetreOfMercury),
+            128.261,
+        );
+        assert_close(
+            TemperatureUnit::Fahrenheit.convert(98.6, TemperatureUnit::Celsius),
+            37.0,
+        );
+        assert_close(
+            TemperatureUnit::Celsius.convert(-40.0, TemperatureUnit::Fahrenheit),
+            -40.0,
+        );
+    }
+
+    #[test]
+    fn parses_unit_system() {
+        let units: UnitSystem = "lb, mmol/L".parse().unwrap();
+
+        assert_eq!(
+            units,
+            UnitSystem {
+                weight: WeightUnit::Pound,
+                glucose: GlucoseUnit::MillimolesPerLitre,
+                pressure: PressureUnit::MillimetreOfMercury,
+                temperature: TemperatureUnit::Celsius,
+            }
+        );
+        assert_eq!(units.to_string().parse(), Ok(units));
+        assert_eq!("".parse(), Ok(UnitSystem::canonical()));
+        assert!("lb,stone".parse::<UnitSystem>().is_err());
+    }
+
+    #[test]
+    fn round_trips_values_through_canonical() {
+        let units: UnitSystem = "lb,mmol/L,kPa".parse().unwrap();
+
+        let canonical = units.to_canonical(Value::Glucose(5.5));
+        assert!(matches!(canonical, Value::Glucose(x) if (x - 99.086).abs() < 1e-3));
+        assert_eq!(units.canonical_to(canonical), Value::Glucose(5.5));
+        assert_eq!(
+            units.to_canonical(Value::HeartRate(80)),
+            Value::HeartRate(80)
+        );
+    }
+}
@@ -0,0 +1,194 @@
+use std::ops::RangeInclusive;
+
+use crate::measurement::{Record, Value, ValueType};
+
+/// How likely it is that a value reflects an actual measurement.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub enum Plausibility {
+    Valid,
+    /// Physiologically possible, but unusual enough to be worth a second look.
+    Suspicious,
+    /// Could not have been measured on a living person.
+    Impossible,
+}
+
+struct Limits {
+    possible: RangeInclusive<f64>,
+    typical: RangeInclusive<f64>,
+}
+
+/// Limits are expressed in canonical units and are deliberately generous,
+/// so that only values which are most likely measurement or decoding errors
+/// are considered impossible.
+fn limits(value_type: ValueType) -> Option<Limits> {
+    let (possible, typical) = match value_type {
+        ValueType::Weight => (0.5..=650.0, 2.0..=300.0),
+        ValueType::BodyMassIndex => (1.0..=250.0, 12.0..=60.0),
+        ValueType::BasalMetabolicRate => (1.0..=10000.0, 500.0..=4000.0),
+        ValueType::WaterPercent => (0.0..=100.0, 25.0..=80.0),
+        ValueType::MusclePercent => (0.0..=100.0, 15.0..=70.0),
+        ValueType::FatPercent => (0.0..=100.0, 2.0..=70.0),
+        ValueType::Glucose => (1.0..=2000.0, 20.0..=600.0),
+        ValueType::BloodPressureSystolic => (20.0..=350.0, 60.0..=250.0),
+        ValueType::BloodPressureDiastolic => (10.0..=250.0, 30.0..=150.0),
+        ValueType::HeartRate => (10.0..=350.0, 30.0..=220.0),
+        ValueType::BodyTemperature => (25.0..=45.0, 34.0..=42.0),
+        ValueType::OxygenSaturation => (30.0..=100.0, 80.0..=100.0),
+        ValueType::RespiratoryRate => (1.0..=100.0, 6.0..=40.0),
+        ValueType::Ketones => (0.0..=30.0, 0.0..=8.0),
+        ValueType::HbA1c => (2.0..=25.0, 4.0..=15.0),
+        ValueType::Steps => (0.0..=200000.0, 0.0..=60000.0),
+        ValueType::Impedance5kHz | ValueType::Impedance50kHz => (1.0..=3000.0, 200.0..=1200.0),
+        ValueType::LeanBodyMass => (0.5..=400.0, 2.0..=150.0),
+        ValueType::FatFreeMassIndex => (1.0..=100.0, 8.0..=35.0),
+        ValueType::Meal => return None,
+    };
+    Some(Limits { possible, typical })
+}
+
+pub fn check_value(value: &Value) -> Plausibility {
+    let Some(limits) = limits(value.value_type()) else {
+        return Plausibility::Valid;
+    };
+    let x = value.as_f64();
+    if limits.typical.contains(&x) {
+        Plausibility::Valid
+    } else if limits.possible.contains(&x) {
+        Plausibility::Suspicious
+    } else {
+        Plausibility::Impossible
+    }
+}
+
+/// A value of a record that is not valid.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct Issue {
+    pub value_type: ValueType,
+    pub value: f64,
+    pub plausibility: Plausibility,
+}
+
+/// Lists all values of a record that are not valid.
+pub fn check_record(record: &Record) -> Vec<Issue> {
+    check_values(&record.values)
+}
+
+/// Lists all given values that are not valid.
+pub fn check_values(values: &[Value]) -> Vec<Issue> {
+    values
+        .iter()
+        .map(|value| (value, check_value(value)))
+        .filter(|(_, plausibility)| *plausibility != Plausibility::Valid)
+        .map(|(value, plausibility)| Issue {
+            value_type: value.value_type(),
+            value: value.as_f64(),
+            plausibility,
+        })
+        .collect()
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub enum RecordStatus {
+    Stored,
+    /// Stored as it is, although some of its values are unusual enough to be
+    /// worth a second look. Only this report tells about them.
+    Suspicious,
+    /// Not stored, because some of its values are impossible.
+    Rejected,
+}
+
+/// Outcome of submitting a single record, identified by its position
+/// in the submitted list.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct RecordReport {
+    pub index: usize,
+    pub status: RecordStatus,
+    pub issues: Vec<Issue>,
+}
+
+impl RecordReport {
+    pub fn new(index: usize, issues: Vec<Issue>) -> Self {
+        let status = match issues.iter().map(|i| i.plausibility).max() {
+            None | Some(Plausibility::Valid) => RecordStatus::Stored,
+            Some(Plausibility::Suspicious) => RecordStatus::Suspicious,
+            Some(Plausibility::Impossible) => RecordStatus::Rejected,
+        };
+        Self {
+            index,
+            status,
+            issues,
+        }
+    }
+
+    pub fn is_rejected(&self) -> bool {
+        self.status == RecordStatus::Rejected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::measurement::Source;
+
+    #[test]
+    fn classifies_values() {
+        assert_eq!(check_value(&Value::Glucose(95.0)), Plausibility::Valid);
+        assert_eq!(
+            check_value(&Value::Glucose(700.0)),
+            Plausibility::Suspicious
+        );
+        assert_eq!(check_value(&Value::Glucose(0.0)), Plausibility::Impossible);
+        assert_eq!(
+            check_value(&Value::FatPercent(-12.0)),
+            Plausibility::Impossible
+        );
+        assert_eq!(check_value(&Value::HeartRate(80)), Plausibility::Valid);
+        assert_eq!(
+            check_value(&Value::Weight(f64::NAN)),
+            Plausibility::Impossible
+        );
+    }
+
+    #[test]
+    fn reports_worst_issue_of_record() {
+        let record = Record::new(
+            Utc::now().fixed_offset(),
+            vec![
+                Value::Weight(80.0),
+                Value::FatPercent(75.0),
+                Value::WaterPercent(140.0),
+            ],
+            vec![],
+            Source::Unknown("test".into()),
+        );
+
+        let report = RecordReport::new(3, check_record(&record));
+
+        assert_eq!(report.status, RecordStatus::Rejected);
+        assert_eq!(
+            report.issues,
+            vec![
+                Issue {
+                    value_type: ValueType::FatPercent,
+                    value: 75.0,
+                    plausibility: Plausibility::Suspicious,
+                },
+                Issue {
+                    value_type: ValueType::WaterPercent,
+                    value: 140.0,
+                    plausibility: Plausibility::Impossible,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,190 @@
+//! Trend of weight, smoothing out day to day fluctuations.
+//!
+//! The trend is an exponential moving average: each weighing moves the trend
+//! by a fraction of its difference from the trend. Weighings are not taken at
+//! regular intervals, so the fraction grows with time since the previous one,
+//! such that it matches the smoothing factor for daily weighings.
+
+use chrono::{DateTime, FixedOffset, NaiveDate, TimeDelta};
+
+use crate::measurement::{Record, Value};
+
+/// Fraction of the difference from the trend a daily weighing accounts for.
+pub const DEFAULT_SMOOTHING: f64 = 0.1;
+
+/// Time the weekly rate of change is computed over.
+const RATE_WINDOW_DAYS: i64 = 28;
+
+/// Furthest into the future a goal is projected.
+const MAX_PROJECTION_DAYS: f64 = 3650.0;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct TrendPoint {
+    pub timestamp: DateTime<FixedOffset>,
+    pub weight: f64,
+    pub trend: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct WeightTrend {
+    pub points: Vec<TrendPoint>,
+    /// Change of the trend per week, fitted over the last four weeks. Missing
+    /// if there are not enough weighings.
+    pub weekly_rate: Option<f64>,
+    pub goal: Option<f64>,
+    /// Day the trend reaches the goal at the current rate. Missing if it moves
+    /// away from the goal, or would take more than ten years to reach it.
+    pub projected_goal_date: Option<NaiveDate>,
+}
+
+/// Takes weight out of a record, if it has one.
+pub fn weighing(record: &Record) -> Option<(DateTime<FixedOffset>, f64)> {
+    record.values.iter().find_map(|value| match value {
+        Value::Weight(x) => Some((record.timestamp, *x)),
+        _ => None,
+    })
+}
+
+/// Smooths weighings, which need not be sorted, into a trend.
+pub fn trend(weighings: &[(DateTime<FixedOffset>, f64)], smoothing: f64) -> Vec<TrendPoint> {
+    let mut weighings = weighings.to_vec();
+    weighings.sort_by_key(|(timestamp, _)| *timestamp);
+
+    let mut points: Vec<TrendPoint> = Vec::with_capacity(weighings.len());
+    for (timestamp, weight) in weighings {
+        let trend = match points.last() {
+            None => weight,
+            Some(previous) => {
+                let days = (timestamp - previous.timestamp).num_seconds() as f64 / 86400.0;
+                let factor = 1.0 - (1.0 - smoothing).powf(days);
+                previous.trend + factor * (weight - previous.trend)
+            }
+        };
+        points.push(TrendPoint {
+            timestamp,
+            weight,
+            trend,
+        });
+    }
+    points
+}
+
+/// Fits a line to the trend over the last weeks, by least squares.
+fn weekly_rate(points: &[TrendPoint]) -> Option<f64> {
+    let last = points.last()?.timestamp;
+    let recent: Vec<_> = points
+        .iter()
+        .filter(|p| last - p.timestamp <= TimeDelta::days(RATE_WINDOW_DAYS))
+        .map(|p| ((p.timestamp - last).num_seconds() as f64 / 86400.0, p.trend))
+        .collect();
+    if recent.len() < 2 {
+        return None;
+    }
+
+    let n = recent.len() as f64;
+    let mean_day = recent.iter().map(|(d, _)| d).sum::<f64>() / n;
+    let mean_trend = recent.iter().map(|(_, t)| t).sum::<f64>() / n;
+    let variance: f64 = recent.iter().map(|(d, _)| (d - mean_day).powi(2)).sum();
+    if variance == 0.0 {
+        return None;
+    }
+    let covariance: f64 = recent
+        .iter()
+        .map(|(d, t)| (d - mean_day) * (t - mean_trend))
+        .sum();
+    Some(covariance / variance * 7.0)
+}
+
+pub fn analyze(
+    weighings: &[(DateTime<FixedOffset>, f64)],
+    smoothing: f64,
+    goal: Option<f64>,
+) -> WeightTrend {
+    let points = trend(weighings, smoothing);
+    let weekly_rate = weekly_rate(&points);
+    let projected_goal_date = match (points.last(), weekly_rate, goal) {
+        (Some(last), Some(rate), Some(goal)) => {
+            let days = (goal - last.trend) / rate * 7.0;
+            (days.is_finite() && (0.0..=MAX_PROJECTION_DAYS).contains(&days))
+                .then(|| last.timestamp.date_naive() + TimeDelta::days(days.ceil() as i64))
+        }
+        _ => None,
+    };
+    WeightTrend {
+        points,
+        weekly_rate,
+        goal,
+        projected_goal_date,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn day(day: u32) -> DateTime<FixedOffset> {
+        FixedOffset::east_opt(0)
+            .unwrap()
+            .with_ymd_and_hms(2024, 7, day, 7, 0, 0)
+            .unwrap()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{} is not close to {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn smooths_daily_weighings() {
+        let points = trend(&[(day(2), 81.0), (day(1), 80.0), (day(3), 79.0)], 0.1);
+
+        assert_eq!(points[0].trend, 80.0);
+        assert_close(points[1].trend, 80.1);
+        assert_close(points[2].trend, 79.99);
+    }
+
+    #[test]
+    fn skipped_days_move_trend_further() {
+        let points = trend(&[(day(1), 80.0), (day(3), 81.0)], 0.1);
+
+        assert_close(points[1].trend, 80.19);
+    }
+
+    #[test]
+    fn projects_reaching_goal_at_current_rate() {
+        // Trend falls by exactly 0.1 kg a day.
+        let weighings: Vec<_> = (1..=15)
+            .map(|d| (day(d), 90.0 - 0.1 * f64::from(d)))
+            .collect();
+
+        let trend = analyze(&weighings, 1.0, Some(88.0));
+
+        assert_close(trend.weekly_rate.unwrap(), -0.7);
+        assert_eq!(
+            trend.projected_goal_date,
+            NaiveDate::from_ymd_opt(2024, 7, 20)
+        );
+    }
+
+    #[test]
+    fn does_not_project_goal_in_opposite_direction() {
+        let weighings: Vec<_> = (1..=15)
+            .map(|d| (day(d), 90.0 + 0.1 * f64::from(d)))
+            .collect();
+
+        let trend = analyze(&weighings, 1.0, Some(80.0));
+
+        assert!(trend.weekly_rate.unwrap() > 0.0);
+        assert_eq!(trend.projected_goal_date, None);
+    }
+}
@@ -0,0 +1,23 @@
+CREATE TABLE
+    record_values_old (
+        record_ref BLOB NOT NULL,
+        value DOUBLE NOT NULL,
+        value_type INTEGER NOT NULL,
+        PRIMARY KEY(record_ref, value_type),
+        FOREIGN KEY(record_ref) REFERENCES records(record_ref)
+    );
+
+INSERT INTO
+    record_values_old (record_ref, value, value_type)
+SELECT
+    record_ref,
+    value,
+    value_type
+FROM
+    record_values;
+
+DROP TABLE record_values;
+
+ALTER TABLE record_values_old RENAME TO record_values;
+
+DROP TABLE value_types;
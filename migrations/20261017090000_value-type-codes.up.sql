@@ -0,0 +1,51 @@
+-- Value types are persisted by their explicit code (see `ValueType` in healthpi-model).
+-- Codes must never change or be reused; every new code needs a row added here
+-- through a new migration.
+CREATE TABLE
+    value_types (
+        code INTEGER NOT NULL PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE
+    );
+
+INSERT INTO
+    value_types (code, name)
+VALUES
+    (0, 'Weight'),
+    (1, 'BodyMassIndex'),
+    (2, 'BasalMetabolicRate'),
+    (3, 'WaterPercent'),
+    (4, 'MusclePercent'),
+    (5, 'FatPercent'),
+    (6, 'Glucose'),
+    (7, 'Meal'),
+    (8, 'BloodPressureSystolic'),
+    (9, 'BloodPressureDiastolic'),
+    (10, 'HeartRate');
+
+-- Codes were previously derived from declaration order, which matches the codes above,
+-- so existing rows keep their meaning. Any row with a code outside of the table could
+-- not have been read back anyway, so it is dropped.
+CREATE TABLE
+    record_values_new (
+        record_ref BLOB NOT NULL,
+        value DOUBLE NOT NULL,
+        value_type INTEGER NOT NULL,
+        PRIMARY KEY(record_ref, value_type),
+        FOREIGN KEY(record_ref) REFERENCES records(record_ref),
+        FOREIGN KEY(value_type) REFERENCES value_types(code)
+    );
+
+INSERT INTO
+    record_values_new (record_ref, value, value_type)
+SELECT
+    record_ref,
+    value,
+    value_type
+FROM
+    record_values
+WHERE
+    value_type IN (SELECT code FROM value_types);
+
+DROP TABLE record_values;
+
+ALTER TABLE record_values_new RENAME TO record_values;
@@ -0,0 +1,41 @@
+-- Timestamps go back to device local time stored as if it was UTC, and
+-- the table to being keyed on timestamp and source.
+CREATE TABLE
+    record_values_backup AS
+SELECT
+    *
+FROM
+    record_values;
+
+DELETE FROM record_values;
+
+CREATE TABLE
+    records_new (
+        timestamp BIGINT NOT NULL,
+        source TEXT NOT NULL,
+        record_ref BLOB NOT NULL UNIQUE,
+        PRIMARY KEY (timestamp, source)
+    );
+
+INSERT INTO
+    records_new (timestamp, source, record_ref)
+SELECT
+    timestamp + utc_offset,
+    source,
+    record_ref
+FROM
+    records;
+
+DROP TABLE records;
+
+ALTER TABLE records_new
+RENAME TO records;
+
+INSERT INTO
+    record_values
+SELECT
+    *
+FROM
+    record_values_backup;
+
+DROP TABLE record_values_backup;
@@ -0,0 +1,49 @@
+-- Timestamps are now stored in UTC, with the offset of the device's local time
+-- in seconds. Existing timestamps are device local time stored as if it was UTC,
+-- and the actual offset cannot be recovered, so they are assigned an offset of 0.
+-- Such records get their correct timestamp when uploaded again by the loader.
+--
+-- Correcting timestamps can make two readings of a device share one, so the
+-- table is rebuilt keyed on record_ref alone. Values are set aside meanwhile,
+-- as the table they refer to cannot be dropped under them.
+CREATE TABLE
+    record_values_backup AS
+SELECT
+    *
+FROM
+    record_values;
+
+DELETE FROM record_values;
+
+CREATE TABLE
+    records_new (
+        timestamp BIGINT NOT NULL,
+        utc_offset INTEGER NOT NULL DEFAULT 0,
+        source TEXT NOT NULL,
+        record_ref BLOB NOT NULL PRIMARY KEY
+    );
+
+INSERT INTO
+    records_new (timestamp, source, record_ref)
+SELECT
+    timestamp,
+    source,
+    record_ref
+FROM
+    records;
+
+DROP TABLE records;
+
+ALTER TABLE records_new
+RENAME TO records;
+
+CREATE INDEX records_timestamp ON records (timestamp, source);
+
+INSERT INTO
+    record_values
+SELECT
+    *
+FROM
+    record_values_backup;
+
+DROP TABLE record_values_backup;
@@ -0,0 +1,7 @@
+DELETE FROM record_values
+WHERE
+    value_type IN (11, 12, 13, 14, 15, 16);
+
+DELETE FROM value_types
+WHERE
+    code IN (11, 12, 13, 14, 15, 16);
@@ -0,0 +1,9 @@
+INSERT INTO
+    value_types (code, name)
+VALUES
+    (11, 'BodyTemperature'),
+    (12, 'OxygenSaturation'),
+    (13, 'RespiratoryRate'),
+    (14, 'Ketones'),
+    (15, 'HbA1c'),
+    (16, 'Steps');
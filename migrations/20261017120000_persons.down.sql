@@ -0,0 +1,6 @@
+DROP INDEX records_person_id;
+
+ALTER TABLE records
+DROP COLUMN person_id;
+
+DROP TABLE persons;
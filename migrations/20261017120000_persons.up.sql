@@ -0,0 +1,16 @@
+CREATE TABLE
+    persons (
+        id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL,
+        birth_date TEXT NOT NULL,
+        sex TEXT NOT NULL,
+        height_cm INTEGER NOT NULL,
+        activity_level INTEGER NOT NULL
+    );
+
+-- Not declared as a foreign key, so that the column can be dropped again.
+-- Consistency is maintained by the API instead.
+ALTER TABLE records
+ADD COLUMN person_id INTEGER;
+
+CREATE INDEX records_person_id ON records (person_id);
@@ -0,0 +1,7 @@
+DELETE FROM record_values
+WHERE
+    value_type IN (17, 18);
+
+DELETE FROM value_types
+WHERE
+    code IN (17, 18);
@@ -0,0 +1,5 @@
+INSERT INTO
+    value_types (code, name)
+VALUES
+    (17, 'Impedance5kHz'),
+    (18, 'Impedance50kHz');
@@ -0,0 +1,10 @@
+ALTER TABLE persons
+DROP COLUMN formula;
+
+DELETE FROM record_values
+WHERE
+    value_type IN (19, 20);
+
+DELETE FROM value_types
+WHERE
+    code IN (19, 20);
@@ -0,0 +1,8 @@
+INSERT INTO
+    value_types (code, name)
+VALUES
+    (19, 'LeanBodyMass'),
+    (20, 'FatFreeMassIndex');
+
+ALTER TABLE persons
+ADD COLUMN formula TEXT NOT NULL DEFAULT 'soehnle';
@@ -0,0 +1,2 @@
+ALTER TABLE records
+ADD COLUMN raw_data BLOB;
@@ -0,0 +1,16 @@
+DROP TRIGGER record_values_delete_change;
+
+DROP TRIGGER record_values_update_change;
+
+DROP TRIGGER record_values_insert_change;
+
+DROP TRIGGER records_update_change;
+
+DROP TRIGGER records_insert_change;
+
+DROP INDEX records_change_seq;
+
+ALTER TABLE records
+DROP COLUMN change_seq;
+
+DROP TABLE change_sequence;
@@ -0,0 +1,122 @@
+-- Single row holding the last position in the sequence of changes.
+CREATE TABLE
+    change_sequence (
+        id INTEGER NOT NULL PRIMARY KEY CHECK (id = 0),
+        value INTEGER NOT NULL
+    );
+
+ALTER TABLE records
+ADD COLUMN change_seq INTEGER NOT NULL DEFAULT 0;
+
+UPDATE records
+SET
+    change_seq = rowid;
+
+INSERT INTO
+    change_sequence (id, value)
+SELECT
+    0,
+    COALESCE(MAX(change_seq), 0)
+FROM
+    records;
+
+CREATE INDEX records_change_seq ON records (change_seq);
+
+-- Any change to a record or its values moves the record to the end of the sequence.
+CREATE TRIGGER records_insert_change AFTER INSERT ON records BEGIN
+UPDATE change_sequence
+SET
+    value = value + 1;
+
+UPDATE records
+SET
+    change_seq = (
+        SELECT
+            value
+        FROM
+            change_sequence
+    )
+WHERE
+    record_ref = NEW.record_ref;
+
+END;
+
+CREATE TRIGGER records_update_change AFTER
+UPDATE OF timestamp,
+utc_offset,
+person_id,
+raw_data ON records WHEN OLD.timestamp IS NOT NEW.timestamp
+OR OLD.utc_offset IS NOT NEW.utc_offset
+OR OLD.person_id IS NOT NEW.person_id
+OR OLD.raw_data IS NOT NEW.raw_data BEGIN
+UPDATE change_sequence
+SET
+    value = value + 1;
+
+UPDATE records
+SET
+    change_seq = (
+        SELECT
+            value
+        FROM
+            change_sequence
+    )
+WHERE
+    record_ref = NEW.record_ref;
+
+END;
+
+CREATE TRIGGER record_values_insert_change AFTER INSERT ON record_values BEGIN
+UPDATE change_sequence
+SET
+    value = value + 1;
+
+UPDATE records
+SET
+    change_seq = (
+        SELECT
+            value
+        FROM
+            change_sequence
+    )
+WHERE
+    record_ref = NEW.record_ref;
+
+END;
+
+CREATE TRIGGER record_values_update_change AFTER
+UPDATE ON record_values WHEN OLD.value IS NOT NEW.value BEGIN
+UPDATE change_sequence
+SET
+    value = value + 1;
+
+UPDATE records
+SET
+    change_seq = (
+        SELECT
+            value
+        FROM
+            change_sequence
+    )
+WHERE
+    record_ref = NEW.record_ref;
+
+END;
+
+CREATE TRIGGER record_values_delete_change AFTER DELETE ON record_values BEGIN
+UPDATE change_sequence
+SET
+    value = value + 1;
+
+UPDATE records
+SET
+    change_seq = (
+        SELECT
+            value
+        FROM
+            change_sequence
+    )
+WHERE
+    record_ref = OLD.record_ref;
+
+END;
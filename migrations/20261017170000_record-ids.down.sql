@@ -0,0 +1,53 @@
+DROP TRIGGER records_update_change;
+
+CREATE TRIGGER records_update_change AFTER
+UPDATE OF timestamp,
+utc_offset,
+person_id,
+raw_data ON records WHEN OLD.timestamp IS NOT NEW.timestamp
+OR OLD.utc_offset IS NOT NEW.utc_offset
+OR OLD.person_id IS NOT NEW.person_id
+OR OLD.raw_data IS NOT NEW.raw_data BEGIN
+UPDATE change_sequence
+SET
+    value = value + 1;
+
+UPDATE records
+SET
+    change_seq = (
+        SELECT
+            value
+        FROM
+            change_sequence
+    )
+WHERE
+    record_ref = NEW.record_ref;
+
+END;
+
+DROP TRIGGER records_insert_id;
+
+DROP INDEX records_id;
+
+DELETE FROM record_values
+WHERE
+    record_ref IN (
+        SELECT
+            record_ref
+        FROM
+            records
+        WHERE
+            deleted_at IS NOT NULL
+    );
+
+DELETE FROM records
+WHERE
+    deleted_at IS NOT NULL;
+
+ALTER TABLE records
+DROP COLUMN deleted_at;
+
+ALTER TABLE records
+DROP COLUMN id;
+
+DROP TABLE record_id_sequence;
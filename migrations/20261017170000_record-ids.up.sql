@@ -0,0 +1,74 @@
+-- Single row holding the last record ID given out.
+CREATE TABLE
+    record_id_sequence (
+        id INTEGER NOT NULL PRIMARY KEY CHECK (id = 0),
+        value INTEGER NOT NULL
+    );
+
+ALTER TABLE records
+ADD COLUMN id INTEGER;
+
+ALTER TABLE records
+ADD COLUMN deleted_at BIGINT;
+
+UPDATE records
+SET
+    id = rowid;
+
+INSERT INTO
+    record_id_sequence (id, value)
+SELECT
+    0,
+    COALESCE(MAX(id), 0)
+FROM
+    records;
+
+CREATE UNIQUE INDEX records_id ON records (id);
+
+CREATE TRIGGER records_insert_id AFTER INSERT ON records BEGIN
+UPDATE record_id_sequence
+SET
+    value = value + 1;
+
+UPDATE records
+SET
+    id = (
+        SELECT
+            value
+        FROM
+            record_id_sequence
+    )
+WHERE
+    record_ref = NEW.record_ref;
+
+END;
+
+-- Deleting and restoring records are changes as well.
+DROP TRIGGER records_update_change;
+
+CREATE TRIGGER records_update_change AFTER
+UPDATE OF timestamp,
+utc_offset,
+person_id,
+raw_data,
+deleted_at ON records WHEN OLD.timestamp IS NOT NEW.timestamp
+OR OLD.utc_offset IS NOT NEW.utc_offset
+OR OLD.person_id IS NOT NEW.person_id
+OR OLD.raw_data IS NOT NEW.raw_data
+OR OLD.deleted_at IS NOT NEW.deleted_at BEGIN
+UPDATE change_sequence
+SET
+    value = value + 1;
+
+UPDATE records
+SET
+    change_seq = (
+        SELECT
+            value
+        FROM
+            change_sequence
+    )
+WHERE
+    record_ref = NEW.record_ref;
+
+END;
@@ -0,0 +1,17 @@
+-- SQLite cannot compute FxHash, so refs rewritten to a later version cannot be
+-- rewritten back here. Records uploaded again after reverting would not be
+-- matched with the ones stored before, so reverting is refused while there
+-- are any such refs. Restore a backup taken before the migration instead.
+CREATE TEMP TABLE rewritten_record_refs (ref_version INTEGER NOT NULL);
+CREATE TEMP TRIGGER refuse_rewritten_record_refs
+BEFORE INSERT ON rewritten_record_refs
+BEGIN
+    SELECT RAISE(ABORT, 'Record refs have been rewritten and cannot be reverted');
+END;
+INSERT INTO rewritten_record_refs
+SELECT ref_version FROM records WHERE ref_version > 0 LIMIT 1;
+DROP TRIGGER refuse_rewritten_record_refs;
+DROP TABLE rewritten_record_refs;
+
+ALTER TABLE records
+DROP COLUMN ref_version;
@@ -0,0 +1,5 @@
+-- Version of the scheme record_ref was derived with. Existing refs are FxHash
+-- based (version 0) and are rewritten by the API on startup, since SQLite
+-- cannot compute the hashes of later versions.
+ALTER TABLE records
+ADD COLUMN ref_version INTEGER NOT NULL DEFAULT 0;
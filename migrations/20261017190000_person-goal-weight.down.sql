@@ -0,0 +1,2 @@
+ALTER TABLE persons
+DROP COLUMN goal_weight_kg;
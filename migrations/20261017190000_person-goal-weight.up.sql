@@ -0,0 +1,2 @@
+ALTER TABLE persons
+ADD COLUMN goal_weight_kg DOUBLE;
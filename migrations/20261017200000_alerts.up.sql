@@ -0,0 +1,31 @@
+CREATE TABLE
+    alert_rules (
+        id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+        person_id INTEGER NOT NULL,
+        -- Stored in RON notation, like sources of records.
+        condition TEXT NOT NULL,
+        webhook_url TEXT NOT NULL
+    );
+
+CREATE INDEX alert_rules_person_id ON alert_rules (person_id);
+
+CREATE TABLE
+    alerts (
+        id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+        rule_id INTEGER NOT NULL,
+        person_id INTEGER NOT NULL,
+        -- Identifies what triggered the alert, e.g. a record, so that it is
+        -- only raised once when the same record is uploaded again.
+        trigger_key TEXT NOT NULL,
+        triggered_at BIGINT NOT NULL,
+        measured_at BIGINT,
+        utc_offset INTEGER,
+        record_id INTEGER,
+        message TEXT NOT NULL,
+        status TEXT NOT NULL,
+        attempts INTEGER NOT NULL DEFAULT 0
+    );
+
+CREATE UNIQUE INDEX alerts_rule_trigger ON alerts (rule_id, trigger_key);
+
+CREATE INDEX alerts_person_id ON alerts (person_id, triggered_at);